@@ -0,0 +1,134 @@
+//! Staged files that are known to be formatted already, for `--cache`.
+//!
+//! The cache is a list of keys in `.git/format-staged-cache`, one per line. A key is a hash
+//! of a file's path and staged blob, and of the formatting commands along with the
+//! versions of their programs, so that upgrading a formatter invalidates everything it
+//! formatted before. A program's version is the output of `PROGRAM --version`, or the
+//! modification time of the program if that fails.
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+use git2::{ObjectType, Oid, Repository};
+
+const CACHE_FILE_NAME: &str = "format-staged-cache";
+
+pub struct Cache {
+    path: PathBuf,
+
+    /// The commands and the versions of their programs.
+    formatter: String,
+
+    /// The keys, oldest first.
+    keys: Vec<Oid>,
+    known: HashSet<Oid>,
+}
+
+impl Cache {
+    /// The most keys that are kept. The oldest are forgotten first.
+    const MAX_KEYS: usize = 100_000;
+
+    /// Open the repository's cache for files formatted by `commands`. A missing or
+    /// unreadable cache is empty.
+    pub fn open(repo: &Repository, commands: &[&[String]]) -> Cache {
+        let path = repo.path().join(CACHE_FILE_NAME);
+        let keys: Vec<Oid> = std::fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| Oid::from_str(line).ok())
+            .collect();
+
+        let mut formatter = String::new();
+        for command in commands {
+            for word in command.iter() {
+                formatter.push_str(word);
+                formatter.push('\0');
+            }
+            formatter.push_str(&version(command));
+            formatter.push('\n');
+        }
+
+        Cache {
+            path,
+            formatter,
+            known: keys.iter().copied().collect(),
+            keys,
+        }
+    }
+
+    fn key(&self, path: &Path, blob: Oid) -> Oid {
+        let mut data = self.formatter.as_bytes().to_vec();
+        data.extend_from_slice(&crate::path_bytes(path.as_os_str()));
+        data.extend_from_slice(format!("\0{}", blob).as_bytes());
+        Oid::hash_object(ObjectType::Blob, &data).expect("internal error: failed to hash cache key")
+    }
+
+    /// Whether the staged `blob` at `path`, relative to the repository root, is known to
+    /// be formatted.
+    pub fn contains(&self, path: &Path, blob: Oid) -> bool {
+        self.known.contains(&self.key(path, blob))
+    }
+
+    /// Remember that the staged `blob` at `path` is formatted.
+    pub fn insert(&mut self, path: &Path, blob: Oid) {
+        let key = self.key(path, blob);
+        if self.known.insert(key) {
+            self.keys.push(key);
+        }
+    }
+
+    /// Write the cache back, replacing the old one in a single step.
+    pub fn save(&self) -> std::io::Result<()> {
+        let keys = &self.keys[self.keys.len().saturating_sub(Cache::MAX_KEYS)..];
+        let temp_path = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&temp_path)?;
+        for key in keys {
+            writeln!(file, "{}", key)?;
+        }
+        drop(file);
+        crate::rename_file(&temp_path, &self.path)
+    }
+}
+
+/// The version of the program that runs `command`.
+fn version(command: &[String]) -> String {
+    let (env, command) = crate::commands::split_env(command);
+    let Some(program) = command.first() else {
+        return String::new();
+    };
+
+    let mut command = Command::new(program);
+    crate::commands::set_process_group(&mut command);
+    let output = command
+        .arg("--version")
+        .envs(env)
+        .env(crate::RUNNING_VAR, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .and_then(crate::commands::wait_for);
+    match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        _ => modified_time(program),
+    }
+}
+
+/// The modification time of `program`, which is looked up in `PATH` if it's just a name.
+fn modified_time(program: &str) -> String {
+    let path = if program.contains(std::path::MAIN_SEPARATOR) {
+        Some(PathBuf::from(program))
+    } else {
+        crate::run::find_program(program, None)
+    };
+    path.and_then(|path| std::fs::metadata(path).ok())
+        .and_then(|metadata| metadata.modified().ok())
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_nanos().to_string())
+        .unwrap_or_default()
+}
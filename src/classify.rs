@@ -0,0 +1,694 @@
+//! Deciding which files to format, and how: whether each is staged, has unstaged changes,
+//! or is to be skipped, and why.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+use git2::{
+    AttrCheckFlags, AttrValue, Error, FileMode, Index, Repository, Status, StatusOptions, Tree,
+};
+
+use crate::{
+    cli::{Attribute, Only, RelativeTo},
+    cwd_relative_path,
+    encoding::Encoding,
+    exit,
+    options::Options,
+    path_from_bytes, quote_path, repo_relative_path,
+    report::Outcomes,
+    staging::{get_staged, head_tree},
+    ExitCode,
+};
+
+/// Whether the file at `path`, relative to the repository root, has any of `attributes`.
+pub(crate) fn has_attribute(
+    repo: &Repository,
+    path: &Path,
+    attributes: &[Attribute],
+) -> Result<bool, Error> {
+    for attribute in attributes {
+        let value = repo.get_attr(path, &attribute.name, AttrCheckFlags::INDEX_THEN_FILE)?;
+        let matches = match (AttrValue::from_string(value), &attribute.value) {
+            (AttrValue::True, None) => true,
+            (AttrValue::String(value), Some(expected)) => value == expected,
+            _ => false,
+        };
+        if matches {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// A staged file that is to be formatted.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TargetFile {
+    /// The file in the working tree is the same as the staged file.
+    StagedOnly(PathBuf),
+
+    /// The file in the working tree has unstaged changes, e.g. because only some of
+    /// its hunks were staged with `git add -p`.
+    ///
+    /// Only the staged version is formatted, even when that's done in the working tree:
+    /// the file is put back as it was afterwards, and then just the formatting changes
+    /// are applied to it. Its unstaged hunks are never run through the formatter.
+    UnstagedAndStaged(PathBuf),
+}
+
+impl TargetFile {
+    /// The file's path, relative to the current directory.
+    pub fn path(&self) -> &Path {
+        match self {
+            TargetFile::StagedOnly(path) | TargetFile::UnstagedAndStaged(path) => path,
+        }
+    }
+}
+
+/** Replace each directory in `files` with the staged files inside it.
+
+A path is treated as a directory when the index has no entry for it, but does have
+entries underneath it. Gitlinks and symlinks are skipped, because they can't be
+formatted, and so are files outside a sparse checkout.
+
+A file that is named more than once (for example `src/main.rs` and `src`, or `./a` and
+`a`) is only kept the first time, so it isn't formatted or staged twice.
+*/
+pub(crate) fn expand_directories(
+    index: &Index,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut expanded = Vec::with_capacity(files.len());
+
+    for file in files {
+        if get_staged(index, dir_prefix, file).is_some() {
+            expanded.push(file.clone());
+            continue;
+        }
+
+        let dir = repo_relative_path(dir_prefix, file);
+        let dir_files: Vec<PathBuf> = index
+            .iter()
+            .filter(|entry| {
+                // Regular files that aren't in conflict (i.e. at stage 0), and are
+                // checked out.
+                entry.mode & 0o170000 == 0o100000
+                    && entry.flags & 0x3000 == 0
+                    && entry.flags_extended & INDEX_ENTRY_SKIP_WORKTREE == 0
+            })
+            .filter_map(|entry| {
+                let path = path_from_bytes(&entry.path);
+                let relative = path.strip_prefix(&dir).ok()?;
+                Some(file.join(relative))
+            })
+            .collect();
+
+        if dir_files.is_empty() {
+            // Not a directory. `check_files_staged` reports it.
+            expanded.push(file.clone());
+        } else {
+            expanded.extend(dir_files);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    expanded.retain(|file| seen.insert(repo_relative_path(dir_prefix, file)));
+    expanded
+}
+
+/// The staged path, relative to the repository root, that's closest to `file` by edit
+/// distance, if any is close enough to be a likely typo.
+pub(crate) fn closest_staged_path(
+    index: &Index,
+    dir_prefix: &Path,
+    file: &Path,
+) -> Option<PathBuf> {
+    let path: Vec<char> = repo_relative_path(dir_prefix, file)
+        .to_string_lossy()
+        .chars()
+        .collect();
+    let max_distance = (path.len() / 3).max(1);
+
+    index
+        .iter()
+        .filter(|entry| entry.mode != u32::from(FileMode::Commit))
+        .map(|entry| path_from_bytes(&entry.path))
+        .map(|candidate| {
+            (
+                edit_distance(
+                    &path,
+                    &candidate.to_string_lossy().chars().collect::<Vec<_>>(),
+                ),
+                candidate,
+            )
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        // The first of the closest, in index order.
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+pub(crate) fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/** Check that each file in `files` is staged, and find out whether it has unstaged changes.
+
+See [`classify_file`] for the details. Exits after reporting every file that can't be formatted.
+*/
+pub(crate) fn check_files_staged(
+    repo: &Repository,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    options: &Options,
+    outcomes: &mut Outcomes,
+) -> Result<Vec<TargetFile>, Error> {
+    let mut index = repo.index()?;
+
+    // The user has passed a file that isn't actually staged
+    let mut bad_file = false;
+
+    let statuses = get_statuses(repo, dir_prefix, files)?;
+    let (base_tree, same_as_base) = if options.changed_vs_head {
+        (head_tree(repo)?, Skip::SameAsHead)
+    } else if let Some(base) = &options.base {
+        let tree = repo
+            .revparse_single(base)
+            .and_then(|object| object.peel_to_tree())
+            .map_err(|err| {
+                Error::from_str(&format!("invalid --base `{}`: {}", base, err.message()))
+            })?;
+        (Some(tree), Skip::SameAsBase)
+    } else {
+        (None, Skip::SameAsHead)
+    };
+
+    let mut to_format = Vec::with_capacity(files.len());
+    let mut to_stage = Vec::new();
+    for file in files {
+        match classify_file(
+            repo,
+            &index,
+            &statuses,
+            base_tree.as_ref().map(|tree| (tree, same_as_base)),
+            dir_prefix,
+            file,
+            options,
+        ) {
+            Ok(Classified::Target(target)) => to_format.push(target),
+            Ok(Classified::Unstaged(file)) => {
+                to_stage.push(file.clone());
+                to_format.push(TargetFile::StagedOnly(file));
+            }
+            Ok(Classified::Skipped(skip)) => {
+                let shown = options.report_path(dir_prefix, file).into_owned();
+                if !options.summary_only {
+                    warning!("skipping {}, which {}", quote_path(&shown), skip);
+                }
+                outcomes.skipped.push((shown, skip));
+            }
+            Err(FormatStagedError::Git(err)) => return Err(err),
+            Err(err) if options.summary_only => {
+                outcomes.failed.push(err.to_string());
+                bad_file = true;
+            }
+            Err(err) => {
+                error!("{}", err);
+                match &err {
+                    FormatStagedError::StagedForDeletion { file, exists: true } => {
+                        hint!(
+                            "run `git add {}` to stage it again, or leave it out",
+                            quote_path(file)
+                        );
+                    }
+                    FormatStagedError::NotStaged { .. } => {
+                        if let Some(closest) = closest_staged_path(&index, dir_prefix, file) {
+                            let shown = match options.relative_to {
+                                RelativeTo::Cwd => cwd_relative_path(dir_prefix, &closest),
+                                RelativeTo::Repo => closest,
+                            };
+                            hint!("did you mean {}?", quote_path(&shown));
+                        }
+                    }
+                    _ => {}
+                }
+                bad_file = true;
+            }
+        }
+    }
+
+    if bad_file {
+        if options.summary_only {
+            outcomes.print();
+        }
+        exit(ExitCode::NotStaged);
+    }
+
+    // Only now that every file is known to be formattable are the unstaged ones staged,
+    // all in one write of the index.
+    if !to_stage.is_empty() {
+        for file in &to_stage {
+            let path = repo_relative_path(dir_prefix, file);
+            index.add_path(&path).map_err(|err| {
+                Error::new(
+                    err.code(),
+                    err.class(),
+                    format!(
+                        "failed to stage {} (as {} in the index): {}",
+                        quote_path(&options.report_path(dir_prefix, file)),
+                        quote_path(&path),
+                        err.message()
+                    ),
+                )
+            })?;
+        }
+        index.write()?;
+    }
+
+    Ok(to_format)
+}
+
+/** Decide how `file` would be formatted with the default options, or `None` if it would
+be skipped.
+
+`file` is relative to the current directory, which is `dir_prefix` relative to the root
+of `repo`. See [`classify_file`] for when files are skipped.
+*/
+pub fn classify(
+    repo: &Repository,
+    dir_prefix: &Path,
+    file: &str,
+) -> Result<Option<TargetFile>, FormatStagedError> {
+    let file = Path::new(file);
+    let index = repo.index()?;
+    let statuses = get_statuses(repo, dir_prefix, &[file.to_path_buf()])?;
+    match classify_file(
+        repo,
+        &index,
+        &statuses,
+        None,
+        dir_prefix,
+        file,
+        &Options::default(),
+    )? {
+        Classified::Target(target) => Ok(Some(target)),
+        Classified::Skipped(_) => Ok(None),
+        Classified::Unstaged(file) => Ok(Some(TargetFile::StagedOnly(file))),
+    }
+}
+
+/// What [`classify_file`] decided to do with a file.
+pub(crate) enum Classified {
+    Target(TargetFile),
+    Skipped(Skip),
+    /// The file's working tree version is to be staged and then formatted, for
+    /// [`Options::allow_unstaged`].
+    Unstaged(PathBuf),
+}
+
+/// Why a file is skipped.
+#[derive(Clone, Copy)]
+pub(crate) enum Skip {
+    IntentToAdd,
+    Binary,
+    UnstagedChanges,
+    SameAsHead,
+    SameAsBase,
+    TooSmall,
+    TooLarge,
+    Submodule,
+    NowDirectory,
+    ParentNowFile,
+    Deleted,
+    BadEncoding,
+}
+
+impl Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Skip::IntentToAdd => "was added with `git add -N` and has nothing staged",
+            Skip::Binary => "looks like a binary file",
+            Skip::UnstagedChanges => "has unstaged changes",
+            Skip::SameAsHead => "is the same as in HEAD",
+            Skip::SameAsBase => "is the same as in the --base commit",
+            Skip::TooSmall => "is smaller than --min-file-size",
+            Skip::TooLarge => "is larger than --max-file-size",
+            Skip::Submodule => "is a submodule",
+            Skip::BadEncoding => "isn't valid in the --encoding",
+            Skip::NowDirectory => "has been replaced by a directory in the working tree",
+            Skip::ParentNowFile => {
+                "is in a directory that has been replaced by a file in the working tree"
+            }
+            Skip::Deleted => "has been deleted from the working tree",
+        })
+    }
+}
+
+/// Why a file can't be formatted.
+#[derive(Debug)]
+pub enum FormatStagedError {
+    /// Git failed.
+    Git(Error),
+
+    /// The file has no staged version.
+    NotStaged { file: PathBuf },
+
+    /// The file is staged for deletion. `exists` is whether it's still in the working tree.
+    StagedForDeletion { file: PathBuf, exists: bool },
+
+    /// The file isn't checked out, because it's outside a sparse checkout.
+    OutsideSparseCheckout { file: PathBuf },
+}
+
+impl std::error::Error for FormatStagedError {}
+
+impl From<Error> for FormatStagedError {
+    fn from(err: Error) -> FormatStagedError {
+        FormatStagedError::Git(err)
+    }
+}
+
+impl Display for FormatStagedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatStagedError::Git(err) => write!(f, "{}", err),
+            FormatStagedError::NotStaged { file } => {
+                write!(f, "{} is not a staged file", quote_path(file))
+            }
+            // E.g. after `git rm --cached`.
+            FormatStagedError::StagedForDeletion { file, exists: true } => write!(
+                f,
+                "{} is staged for deletion, but still exists in the working tree",
+                quote_path(file)
+            ),
+            FormatStagedError::StagedForDeletion {
+                file,
+                exists: false,
+            } => write!(
+                f,
+                "{} is staged for deletion, so there's nothing to format",
+                quote_path(file)
+            ),
+            FormatStagedError::OutsideSparseCheckout { file } => {
+                write!(f, "{} is outside the sparse checkout", quote_path(file))
+            }
+        }
+    }
+}
+
+/** Decide how `file` is to be formatted, or whether it's skipped.
+
+`statuses` are the working tree statuses from [`get_statuses`], and `base_tree` is the
+tree of `HEAD` if [`Options::changed_vs_head`] is set, or of [`Options::base`], along
+with the reason to give for skipping files that are the same as in it. When
+[`Options::allow_unstaged`] is set, a tracked file with unstaged changes is to be staged
+before it's formatted, so that its working tree version is; that's left to the caller, so
+that nothing is staged unless every file can be formatted.
+
+Files are skipped if they were added with `git add --intent-to-add`, because nothing has
+been staged for them; if they're submodules; if they've been changed from files into
+directories, or the other way around, in the working tree; if they've been deleted from
+the working tree, when they're to be formatted there; if their staged versions are the
+same as in `base_tree`; if their staged versions are outside the sizes allowed by
+[`Options::min_file_size`] and [`Options::max_file_size`]; if their staged versions look
+binary, unless they match [`Options::treat_as_text`]; and if they have unstaged changes
+when only clean ones are wanted.
+*/
+pub(crate) fn classify_file(
+    repo: &Repository,
+    index: &Index,
+    statuses: &HashMap<PathBuf, Status>,
+    base_tree: Option<(&Tree, Skip)>,
+    dir_prefix: &Path,
+    file: &Path,
+    options: &Options,
+) -> Result<Classified, FormatStagedError> {
+    let path = repo_relative_path(dir_prefix, file);
+    let shown = options.report_path(dir_prefix, file).into_owned();
+
+    // A file without a status entry is unmodified.
+    let status = statuses.get(&path).copied().unwrap_or(Status::CURRENT);
+
+    let index_entry = match get_staged(index, dir_prefix, file) {
+        Some(index_entry) => index_entry,
+        None if status.contains(Status::INDEX_DELETED) => {
+            return Err(FormatStagedError::StagedForDeletion {
+                file: shown,
+                exists: file.exists(),
+            })
+        }
+        None => return Err(FormatStagedError::NotStaged { file: shown }),
+    };
+
+    if index_entry.flags_extended & INDEX_ENTRY_SKIP_WORKTREE != 0 {
+        return Err(FormatStagedError::OutsideSparseCheckout { file: shown });
+    }
+
+    if index_entry.flags_extended & INDEX_ENTRY_INTENT_TO_ADD != 0 {
+        return Ok(Classified::Skipped(Skip::IntentToAdd));
+    }
+
+    // A submodule's entry is a commit, not a blob with content to format.
+    if index_entry.mode == u32::from(FileMode::Commit) {
+        return Ok(Classified::Skipped(Skip::Submodule));
+    }
+
+    // The staged file can't be written back to the working tree if it has become a
+    // directory there, or if one of its directories has become a file.
+    let is_dir = |path: &Path| std::fs::symlink_metadata(path).map(|metadata| metadata.is_dir());
+    let parent_is_file = file
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .is_some_and(|parent| matches!(is_dir(parent), Ok(false)));
+    if matches!(is_dir(file), Ok(true)) {
+        return Ok(Classified::Skipped(Skip::NowDirectory));
+    }
+    if parent_is_file {
+        return Ok(Classified::Skipped(Skip::ParentNowFile));
+    }
+
+    // Formatting in the working tree needs the file to be there. The other modes only
+    // need its staged version.
+    if !options.in_temp_dir()
+        && !options.in_memory()
+        && std::fs::symlink_metadata(file)
+            .is_err_and(|err| err.kind() == std::io::ErrorKind::NotFound)
+    {
+        return Ok(Classified::Skipped(Skip::Deleted));
+    }
+
+    if let Some((base_tree, same_as_base)) = base_tree {
+        match base_tree.get_path(&path) {
+            Ok(base_entry) if base_entry.id() == index_entry.id => {
+                return Ok(Classified::Skipped(same_as_base));
+            }
+            Ok(_) => {}
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    if options.min_file_size.is_some() || options.max_file_size.is_some() {
+        // Only the object's header is read, so that large files are skipped cheaply.
+        let (size, _) = repo.odb()?.read_header(index_entry.id)?;
+        let size = size as u64;
+        if options.min_file_size.is_some_and(|min| size < min) {
+            return Ok(Classified::Skipped(Skip::TooSmall));
+        }
+        if options.max_file_size.is_some_and(|max| size > max) {
+            return Ok(Classified::Skipped(Skip::TooLarge));
+        }
+    }
+
+    if options.encoding != Encoding::Utf8 {
+        // A file in a declared encoding is text, so it isn't checked for NUL bytes,
+        // which are common in UTF-16.
+        if options
+            .encoding
+            .decode(repo.find_blob(index_entry.id)?.content())
+            .is_none()
+        {
+            return Ok(Classified::Skipped(Skip::BadEncoding));
+        }
+    } else if !options
+        .treat_as_text
+        .iter()
+        .any(|pattern| pattern.matches(&path.to_string_lossy()))
+        && is_binary(repo.find_blob(index_entry.id)?.content())
+    {
+        return Ok(Classified::Skipped(Skip::Binary));
+    }
+
+    if options.allow_unstaged && status.contains(Status::WT_MODIFIED) {
+        return Ok(Classified::Unstaged(file.to_path_buf()));
+    }
+
+    let target = if status.intersects(
+        Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_TYPECHANGE | Status::WT_RENAMED,
+    ) {
+        TargetFile::UnstagedAndStaged(file.to_path_buf())
+    } else {
+        TargetFile::StagedOnly(file.to_path_buf())
+    };
+
+    if options.only == Some(Only::StagedClean) && matches!(target, TargetFile::UnstagedAndStaged(_))
+    {
+        return Ok(Classified::Skipped(Skip::UnstagedChanges));
+    }
+
+    Ok(Classified::Target(target))
+}
+
+/// The extended index entry flag for files added with `git add --intent-to-add`.
+pub(crate) const INDEX_ENTRY_INTENT_TO_ADD: u16 = 1 << 13;
+
+/// The extended index entry flag for files that aren't checked out, because they're
+/// outside a sparse checkout.
+pub(crate) const INDEX_ENTRY_SKIP_WORKTREE: u16 = 1 << 14;
+
+/// Whether `content` looks binary. Like Git, this checks for a NUL byte near the start.
+pub(crate) fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|&byte| byte == 0)
+}
+
+/** Get the statuses of `files` with a single status scan, keyed by path relative to the
+repository root.
+
+This is much faster than calling [`Repository::status_file`] for each file in a large
+repository.
+*/
+pub(crate) fn get_statuses(
+    repo: &Repository,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+) -> Result<HashMap<PathBuf, Status>, Error> {
+    if files.is_empty() {
+        // An empty pathspec would match every file.
+        return Ok(HashMap::new());
+    }
+
+    let mut status_options = StatusOptions::new();
+    status_options
+        .include_untracked(false)
+        .include_ignored(false)
+        .include_unmodified(true)
+        .disable_pathspec_match(true);
+    for file in files {
+        status_options.pathspec(repo_relative_path(dir_prefix, file));
+    }
+
+    Ok(repo
+        .statuses(Some(&mut status_options))?
+        .iter()
+        .map(|entry| (path_from_bytes(entry.path_bytes()), entry.status()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::{IndexEntry, IndexTime, ObjectType, Oid};
+
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    /// An in-memory index with an empty file staged at each of `paths`, and a submodule at
+    /// each of `submodules`.
+    fn index_with(paths: &[&str], submodules: &[&str]) -> Index {
+        let mut index = Index::new().unwrap();
+        let files = paths.iter().map(|path| (path, FileMode::Blob));
+        let submodules = submodules.iter().map(|path| (path, FileMode::Commit));
+        for (path, mode) in files.chain(submodules) {
+            index
+                .add(&IndexEntry {
+                    ctime: IndexTime::new(0, 0),
+                    mtime: IndexTime::new(0, 0),
+                    dev: 0,
+                    ino: 0,
+                    mode: mode.into(),
+                    uid: 0,
+                    gid: 0,
+                    file_size: 0,
+                    id: Oid::hash_object(ObjectType::Blob, b"").unwrap(),
+                    flags: 0,
+                    flags_extended: 0,
+                    path: path.as_bytes().to_vec(),
+                })
+                .unwrap();
+        }
+        index
+    }
+
+    fn closest(index: &Index, file: &str) -> Option<PathBuf> {
+        closest_staged_path(index, Path::new(""), Path::new(file))
+    }
+
+    #[test]
+    fn edit_distance_counts_edits() {
+        assert_eq!(edit_distance(&chars(""), &chars("")), 0);
+        assert_eq!(edit_distance(&chars("abc"), &chars("abc")), 0);
+        assert_eq!(edit_distance(&chars(""), &chars("abc")), 3);
+        assert_eq!(edit_distance(&chars("abc"), &chars("")), 3);
+        assert_eq!(edit_distance(&chars("abc"), &chars("abd")), 1);
+        assert_eq!(edit_distance(&chars("abc"), &chars("ac")), 1);
+        assert_eq!(edit_distance(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(edit_distance(&chars("é"), &chars("e")), 1);
+    }
+
+    #[test]
+    fn closest_staged_path_allows_a_third_of_the_length() {
+        let index = index_with(&["abcdef.rs"], &[]);
+
+        // Nine characters allow three edits, but not four.
+        assert_eq!(
+            closest(&index, "abcxyz.rs"),
+            Some(PathBuf::from("abcdef.rs"))
+        );
+        assert_eq!(closest(&index, "abwxyz.rs"), None);
+    }
+
+    #[test]
+    fn closest_staged_path_allows_one_edit_for_short_paths() {
+        let index = index_with(&["ab"], &[]);
+
+        assert_eq!(closest(&index, "ax"), Some(PathBuf::from("ab")));
+        assert_eq!(closest(&index, "xy"), None);
+    }
+
+    #[test]
+    fn closest_staged_path_prefers_the_closest() {
+        let index = index_with(&["src/main.rs", "src/mains.rs"], &[]);
+
+        assert_eq!(
+            closest(&index, "src/mainsx.rs"),
+            Some(PathBuf::from("src/mains.rs"))
+        );
+        // A tie goes to the first in index order.
+        assert_eq!(
+            closest(&index, "src/mainz.rs"),
+            Some(PathBuf::from("src/main.rs"))
+        );
+    }
+
+    #[test]
+    fn closest_staged_path_skips_submodules() {
+        let index = index_with(&[], &["vendor"]);
+
+        assert_eq!(closest(&index, "vendr"), None);
+    }
+}
@@ -0,0 +1,739 @@
+//! The command line interface, and the parsers for its option values.
+
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+    time::Duration,
+};
+
+use clap::{ColorChoice, Parser, ValueEnum};
+
+use crate::{
+    commands::{command_line, shell_split, DEFAULT_ARG_MAX},
+    encoding::Encoding,
+    file_type::FileType,
+    glob::Pattern,
+    workdir::DEFAULT_STAGED_SUFFIX,
+};
+
+#[derive(Parser)]
+// A `help` subcommand would also take over files named `help`.
+#[clap(disable_help_subcommand = true)]
+pub(crate) struct Cli {
+    #[clap(subcommand)]
+    pub(crate) subcommand: Option<CliCommand>,
+
+    /// The staged files to format.
+    ///
+    /// A directory stands for all the staged files inside it. (A file named `filter`
+    /// has to be given as `./filter`.)
+    pub(crate) files: Vec<PathBuf>,
+
+    /// Run as if started in DIR instead of the current directory, like `git -C`.
+    ///
+    /// When given more than once, each DIR is relative to the one before it. Relative
+    /// paths in the other arguments are relative to the last DIR.
+    #[clap(short = 'C', value_name = "DIR")]
+    pub(crate) chdir: Vec<PathBuf>,
+
+    /// Format the files that `git diff --cached` reports as added, modified, renamed or
+    /// copied, instead of the files given.
+    #[clap(long, conflicts_with = "files")]
+    pub(crate) paths_from_diff: bool,
+
+    /// Format files inside submodules too, by staging their formatted versions in the
+    /// submodules' own indexes.
+    ///
+    /// A path inside a submodule, or a submodule itself, stands for the staged files in
+    /// the submodule. Everything is reported together once all the repositories have
+    /// been formatted, with paths as they are in the superproject.
+    #[clap(long)]
+    pub(crate) recurse_submodules: bool,
+
+    /// Drop the first N components of each of the given paths before looking them up,
+    /// like `tar --strip-components` (e.g. `--strip-components 1` turns `web/src/app.ts`
+    /// into `src/app.ts`).
+    #[clap(long, value_name = "N", conflicts_with = "paths_from_diff")]
+    pub(crate) strip_components: Option<usize>,
+
+    /// Don't backport formatting changes to the working tree.
+    ///
+    /// Only the staged versions of the files are formatted, as copies in
+    /// `.git/format-staged` like with `--index-only`; the working tree files are never
+    /// written to.
+    #[clap(long, overrides_with = "working_tree")]
+    pub(crate) no_working_tree: bool,
+
+    /// Backport formatting changes to the working tree after all, overriding
+    /// `no-working-tree` in the config file.
+    #[clap(long, overrides_with = "no_working_tree")]
+    pub(crate) working_tree: bool,
+
+    /// Format copies of the staged files in a temporary directory, `.git/format-staged`.
+    ///
+    /// The working tree files are never written to, so this works in a read-only
+    /// checkout as long as `.git` is writable. This is what `--no-working-tree` does
+    /// too, unless it's used with `--stdin` or `--lsp`.
+    #[clap(long, overrides_with = "no_index_only")]
+    pub(crate) index_only: bool,
+
+    /// Format the files where they are after all, overriding `index-only` in the config
+    /// file.
+    #[clap(long, overrides_with = "index_only")]
+    pub(crate) no_index_only: bool,
+
+    /// Add this extension to the names of the copies made by `--index-only` or
+    /// `--no-working-tree`, for formatters that pick a language based on it (e.g. with
+    /// `--temp-suffix .tsx`, `a.ts` is formatted as `a.ts.tsx`).
+    #[clap(long, value_name = "SUFFIX")]
+    pub(crate) temp_suffix: Option<String>,
+
+    /// The suffix for the copy of each staged file that is kept next to it while the
+    /// working tree is formatted, for repositories where `.staged.orig` files are real.
+    #[clap(long, value_name = "SUFFIX", default_value = DEFAULT_STAGED_SUFFIX)]
+    pub(crate) staged_suffix: String,
+
+    /// Run the formatter as a filter: the staged content of each file is written to its
+    /// stdin, and the formatted content is read from its stdout.
+    ///
+    /// The formatter is run once per file, and file names aren't added to its arguments.
+    /// Instead, `{}` in an argument is replaced by the file's path, for formatters that
+    /// need a hint about the language (e.g. `prettier --stdin-filepath {}`).
+    ///
+    /// The working tree is only written to when the formatting changes are copied to
+    /// it. If that isn't possible, for example because the checkout is read-only, the
+    /// changes are still staged.
+    #[clap(long, conflicts_with = "index_only")]
+    pub(crate) stdin: bool,
+
+    /// Run the formatter once per file with the file's content on its stdin, for
+    /// formatters that read their input from stdin but write the result to a file.
+    ///
+    /// Unlike --stdin, the formatter's stdout is ignored, and the result is read from the
+    /// file named by `{}`, which must appear in its arguments.
+    #[clap(long, conflicts_with_all = ["stdin", "lsp", "chdir_file"])]
+    pub(crate) formatter_stdin: bool,
+
+    /// With --stdin, read the formatter's result from a named pipe instead of its
+    /// stdout, for formatters that insist on writing to a path. Only on Unix.
+    ///
+    /// `{output}` in the formatter's arguments is replaced by the pipe's path, and its
+    /// stdout is shown like its stderr. The pipe is created in the `.git` directory for
+    /// each run of the formatter, so the result is never written to disk.
+    #[clap(long, requires = "stdin")]
+    pub(crate) output_fifo: bool,
+
+    /// How the path that replaces `{}` in --stdin mode is written: relative to the
+    /// current directory (`cwd`), relative to the repository root (`repo`), or as an
+    /// `absolute` path.
+    ///
+    /// Formatters that look for ignore files relative to the path they're given can
+    /// need a particular form.
+    #[clap(long, value_name = "FORM", default_value = "cwd", requires = "stdin")]
+    pub(crate) stdin_filepath: StdinFilepath,
+
+    /// Format with a language server instead: the command after `--` is started as an
+    /// LSP server, and each file's staged content is formatted with a
+    /// `textDocument/formatting` request.
+    ///
+    /// Like `--stdin`, the working tree is only written to when the formatting changes
+    /// are copied to it.
+    #[clap(long, conflicts_with_all = ["index_only", "stdin", "verify_clean"])]
+    pub(crate) lsp: bool,
+
+    /// Write the files to be formatted, and the temporary files used for them, to FILE
+    /// as JSON before formatting.
+    #[clap(long, value_name = "FILE")]
+    pub(crate) dump_plan: Option<PathBuf>,
+
+    /// Write the staged content of FILE to stdout, or to the file given by --dump-to,
+    /// and exit without formatting anything.
+    #[clap(long, value_name = "FILE", conflicts_with = "dump_plan")]
+    pub(crate) dump_staged_blob: Option<PathBuf>,
+
+    /// Where --dump-staged-blob writes to.
+    #[clap(long, value_name = "PATH", requires = "dump_staged_blob")]
+    pub(crate) dump_to: Option<PathBuf>,
+
+    /// Print the options that would be used, and where each of them came from, and exit
+    /// without formatting anything.
+    ///
+    /// An option can come from the command line, the config file, Git's config or the
+    /// environment, in that order of precedence, or it can be left at its default.
+    #[clap(long, conflicts_with_all = ["dump_staged_blob", "dump_plan"])]
+    pub(crate) dump_config: bool,
+
+    /// Stage the working tree versions of tracked files with unstaged changes, and then
+    /// format them.
+    ///
+    /// Without this flag, only the staged version of such a file is formatted. Nothing
+    /// is staged unless every file can be formatted, but once staged, a file stays
+    /// staged even if formatting fails.
+    #[clap(long, overrides_with = "no_allow_unstaged")]
+    pub(crate) allow_unstaged: bool,
+
+    /// Only format the staged versions of files after all, overriding `allow-unstaged`
+    /// in the config file.
+    #[clap(long, overrides_with = "allow_unstaged")]
+    pub(crate) no_allow_unstaged: bool,
+
+    /// After formatting, format the newly staged files again and warn about any that
+    /// change.
+    ///
+    /// A formatter that changes its own output is unstable.
+    #[clap(long)]
+    pub(crate) verify_clean: bool,
+
+    /// Run a formatting script that isn't executable through the interpreter on its
+    /// `#!` line.
+    #[clap(long)]
+    pub(crate) use_shebang: bool,
+
+    /// Format files matching GLOB even if they look binary.
+    ///
+    /// Staged files containing a NUL byte are normally skipped.
+    #[clap(long, value_name = "GLOB", value_parser = Pattern::new)]
+    pub(crate) treat_as_text: Vec<Pattern>,
+
+    /// Skip files whose staged versions are smaller than BYTES.
+    #[clap(long, value_name = "BYTES")]
+    pub(crate) min_file_size: Option<u64>,
+
+    /// Skip files whose staged versions are larger than BYTES.
+    #[clap(long, value_name = "BYTES")]
+    pub(crate) max_file_size: Option<u64>,
+
+    /// The encoding of the staged files.
+    ///
+    /// Files in another encoding than UTF-8 are converted to UTF-8 for the formatter,
+    /// and back again before they're staged. Files that aren't valid in the encoding
+    /// are skipped.
+    #[clap(long, value_name = "ENCODING", default_value = "utf-8")]
+    pub(crate) encoding: Encoding,
+
+    /// Only format files of type TYPE, such as `rust`, `make` or `dockerfile`, and
+    /// format them with COMMAND instead of the formatter after `--` if it's given.
+    ///
+    /// Files are recognised by name, so extensionless files like `Makefile` and
+    /// `Dockerfile` can be picked out of the list of files too. Other files are
+    /// skipped. COMMAND is split into words like a shell would, as in
+    /// `--type 'dockerfile=dockerfmt -w'`. The formatter after `--` can be left out if every type has its own. Can be given
+    /// more than once.
+    #[clap(long = "type", value_name = "TYPE[=COMMAND]", value_parser = parse_type)]
+    pub(crate) types: Vec<TypeFormatter>,
+
+    /// Only format files with the Git attribute NAME set to VALUE, or just set if
+    /// there's no `=VALUE`.
+    ///
+    /// Attributes are read from the staged `.gitattributes` files first, and then the
+    /// working tree ones. Other files are skipped. Can be given more than once.
+    #[clap(long, value_name = "NAME[=VALUE]", value_parser = parse_attribute)]
+    pub(crate) attr: Vec<Attribute>,
+
+    /// Exit with an error if formatting changed any file.
+    ///
+    /// The formatted files are still staged, but a pre-commit hook using this flag
+    /// aborts the commit so that the changes can be reviewed.
+    #[clap(long)]
+    pub(crate) fail_on_reformat: bool,
+
+    /// Always exit successfully, even if formatting fails.
+    ///
+    /// Errors are still reported, but never block a commit.
+    #[clap(long, conflicts_with = "fail_on_reformat")]
+    pub(crate) exit_zero: bool,
+
+    /// Don't count changes that only affect whitespace (including blank lines) as
+    /// reformatting.
+    ///
+    /// This only affects which files are reported as reformatted, for example by
+    /// `--fail-on-reformat`. The formatted files are staged either way.
+    #[clap(long)]
+    pub(crate) ignore_whitespace: bool,
+
+    /// Convert the line endings in the formatters' output to the ones most used in the
+    /// staged file (LF or CRLF), for formatters that always write one kind.
+    ///
+    /// Every line ending in the output is converted, so a file with mixed line endings
+    /// ends up with just one kind. Files without any line endings are left alone.
+    #[clap(long)]
+    pub(crate) keep_line_endings: bool,
+
+    /// Print each formatting command to stderr before running it.
+    ///
+    /// The command is printed with its file names and `{}` substitutions filled in, and
+    /// quoted so that it can be pasted into a shell.
+    #[clap(long)]
+    pub(crate) print_command: bool,
+
+    /// How to report the results.
+    ///
+    /// With `json`, a summary of each formatted file is written to stdout. Anything the
+    /// formatters write to stderr is captured and included in the summary. (Their stdout
+    /// always goes to stderr, so stdout only has the summary on it.)
+    #[clap(long, value_name = "FORMAT", default_value = "text")]
+    pub(crate) format: OutputFormat,
+
+    /// Capture what each formatter writes to stdout and stderr, and write it to stderr in
+    /// one piece, under a line naming the command, once the formatter has finished.
+    ///
+    /// By default the formatters write straight to the terminal, where their output can
+    /// end up mixed with git-format-staged's.
+    #[clap(long)]
+    pub(crate) capture_output: bool,
+
+    /// Print a short summary of what happened to each file at the end, instead of a
+    /// warning or error for each file as it's found.
+    ///
+    /// Files are grouped into those that were reformatted, were unchanged, were skipped,
+    /// and couldn't be formatted.
+    #[clap(long, conflicts_with = "format")]
+    pub(crate) summary_only: bool,
+
+    /// Write the summary from `--summary-only` or `--format json` to PATH instead of
+    /// stdout.
+    ///
+    /// PATH is replaced in one step, so a reader never sees a partly written summary.
+    #[clap(long, value_name = "PATH")]
+    pub(crate) report_file: Option<PathBuf>,
+
+    /// Report file paths relative to the repository root (`repo`), or as they were
+    /// given, relative to the current directory (`cwd`).
+    #[clap(long, value_name = "BASE", default_value = "cwd")]
+    pub(crate) relative_to: RelativeTo,
+
+    /// The most bytes of arguments to pass to a formatting command at once.
+    ///
+    /// When there are more files than fit, the command is run several times, on a
+    /// batch of files each time, like `xargs`.
+    #[clap(long, value_name = "BYTES", default_value_t = DEFAULT_ARG_MAX)]
+    pub(crate) arg_max: usize,
+
+    /// Format the files in N jobs at once, by splitting them between N runs of each
+    /// formatting command. `auto` runs one job for each CPU.
+    ///
+    /// There are never more jobs than files to format.
+    #[clap(
+        long,
+        alias = "threads",
+        value_name = "N",
+        value_parser = parse_jobs,
+        conflicts_with_all = ["stdin", "lsp", "formatter_stdin", "chdir_file"]
+    )]
+    pub(crate) jobs: Option<usize>,
+
+    /// Format the files with extension EXT in N jobs at once, by splitting them between
+    /// N runs of each formatting command (e.g. `--jobs-per-extension js=4`).
+    ///
+    /// A group of extensions in braces, like `{ts,tsx,js,jsx}=4`, shares the N jobs
+    /// between the files with any of them.
+    ///
+    /// Can be given once for each extension. Files with other extensions are formatted
+    /// in the --jobs jobs, as usual.
+    #[clap(
+        long,
+        value_name = "EXT=N",
+        value_parser = parse_jobs_per_extension,
+        conflicts_with_all = ["stdin", "lsp", "formatter_stdin", "chdir_file"]
+    )]
+    pub(crate) jobs_per_extension: Vec<(Vec<String>, usize)>,
+
+    /// Run the formatter once for each file, from the directory containing it, and pass
+    /// it just the file's name.
+    ///
+    /// This is for formatters that only look for their configuration in the current
+    /// directory.
+    #[clap(long, conflicts_with_all = ["stdin", "lsp"])]
+    pub(crate) chdir_file: bool,
+
+    /// Only format the lines that are staged as changed since `HEAD`, for formatters
+    /// that take line ranges (e.g. `clang-format -i --lines={start}:{end}`).
+    ///
+    /// The formatter is run once per file. Each argument containing `{start}` or
+    /// `{end}` is repeated for each block of changed lines, with them replaced by its
+    /// first and last line number. Files with no added or changed lines aren't formatted.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "stdin",
+            "lsp",
+            "formatter_stdin",
+            "chdir_file",
+            "jobs",
+            "jobs_per_extension",
+            "verify_clean",
+        ]
+    )]
+    pub(crate) only_changed_lines: bool,
+
+    /// Format each file up to N times, until formatting stops changing it, for
+    /// formatters that need more than one pass to settle.
+    #[clap(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "lsp")]
+    pub(crate) passes: u32,
+
+    /// Remember which staged files the formatter left unchanged, and don't format them
+    /// again while they're unchanged.
+    ///
+    /// The cache is kept in `.git/format-staged-cache`. Its entries are tied to the
+    /// version of each formatter, as reported by `--version`, so upgrading a formatter
+    /// invalidates them.
+    #[clap(long)]
+    pub(crate) cache: bool,
+
+    /// Don't format staged files whose blob IDs are listed in FILE, one per line, because
+    /// they're known to be formatted already, e.g. by an earlier run in CI.
+    ///
+    /// Blank lines, and lines starting with `#`, are ignored.
+    #[clap(long, value_name = "FILE")]
+    pub(crate) known_formatted: Option<PathBuf>,
+
+    /// Only format some of the given files.
+    ///
+    /// With `staged-clean`, files that also have unstaged changes are skipped, so the
+    /// working tree never has to be reconciled with the formatting changes.
+    #[clap(long, value_name = "WHICH")]
+    pub(crate) only: Option<Only>,
+
+    /// Skip files whose staged versions are the same as in `HEAD`, such as unchanged
+    /// files that were added again.
+    #[clap(long)]
+    pub(crate) changed_vs_head: bool,
+
+    /// Skip files whose staged versions are the same as in REF, such as the merge base of
+    /// a pull request, so that only the files that have diverged from it are formatted.
+    ///
+    /// Unlike a list of the paths changed since REF, this compares the staged content, so
+    /// a file that was changed and then changed back is skipped too.
+    #[clap(
+        long,
+        value_name = "REF",
+        conflicts_with_all = ["changed_vs_head", "recurse_submodules"]
+    )]
+    pub(crate) base: Option<String>,
+
+    /// After staging the formatted files, print a diff of the index against `HEAD` to
+    /// stdout: exactly what will be committed.
+    #[clap(long, conflicts_with = "format")]
+    pub(crate) diff_head: bool,
+
+    /// After staging the formatted files, print how many lines formatting added to and
+    /// removed from each of them to stdout, like `git diff --stat`.
+    #[clap(long, conflicts_with = "format")]
+    pub(crate) stat: bool,
+
+    /// Run CMD once everything has been staged, with the reformatted files as its
+    /// arguments.
+    ///
+    /// CMD is run by `sh -c`, so the files are in `"$@"`. If it fails, a warning is
+    /// printed, unless --post-format-must-succeed is given.
+    #[clap(long, value_name = "CMD")]
+    pub(crate) post_format: Option<String>,
+
+    /// Exit with an error if the --post-format command fails.
+    #[clap(long, requires = "post_format")]
+    pub(crate) post_format_must_succeed: bool,
+
+    /// Apply the formatting to the working tree files, but don't stage it.
+    #[clap(
+        long,
+        conflicts_with_all = ["no_working_tree", "index_only", "verify_clean", "allow_unstaged"]
+    )]
+    pub(crate) no_index_write: bool,
+
+    /// Commit the staged files once they're formatted, with the message given by
+    /// `--message`.
+    ///
+    /// The commit is made directly, like `git commit --no-verify`, so no hooks are run.
+    #[clap(
+        long,
+        requires = "message",
+        conflicts_with_all = ["no_index_write", "fail_on_reformat"]
+    )]
+    pub(crate) commit: bool,
+
+    /// The message for `--commit`.
+    #[clap(short, long, value_name = "MSG", requires = "commit")]
+    pub(crate) message: Option<String>,
+
+    /// Flush the index to disk with `fsync` after staging the formatted files.
+    ///
+    /// The index is always completely written before git-format-staged exits, so a
+    /// commit started after it sees the formatted files either way. This only matters
+    /// if the machine might crash or lose power straight afterwards.
+    #[clap(long, conflicts_with = "no_index_write")]
+    pub(crate) sync: bool,
+
+    /// After staging the formatted files, read them back from the index and check that
+    /// they're exactly what the formatter produced, with their file modes unchanged.
+    ///
+    /// This is a check on git-format-staged itself: if it ever fails, please report it.
+    #[clap(long, conflicts_with = "no_index_write")]
+    pub(crate) verify_staged_unchanged: bool,
+
+    /// Give up if formatting hasn't finished after SECONDS, killing the formatter and
+    /// exiting with code 124.
+    ///
+    /// Nothing is staged, and the working tree is restored as it was.
+    #[clap(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    pub(crate) deadline: Option<Duration>,
+
+    /// The signal that stops a formatter when the --deadline passes, e.g. `INT` or
+    /// `KILL`. A formatter that's still running after the --kill-grace period is killed
+    /// with SIGKILL.
+    ///
+    /// On Unix the signal is sent to the formatter's whole process group, so that any
+    /// processes it started are stopped too. Each formatter is run in its own process
+    /// group for this, so it doesn't get the terminal's Ctrl-C.
+    #[clap(
+        long,
+        value_name = "SIGNAL",
+        default_value = "TERM",
+        value_parser = parse_signal,
+        requires = "deadline"
+    )]
+    pub(crate) kill_signal: String,
+
+    /// How long a formatter has to exit after the --kill-signal, before it's killed.
+    #[clap(
+        long,
+        value_name = "SECONDS",
+        default_value = "5",
+        value_parser = parse_seconds,
+        requires = "deadline"
+    )]
+    pub(crate) kill_grace: Duration,
+
+    /// When to color diagnostic output.
+    ///
+    /// `auto` (the default) colors output when stderr is a terminal and `NO_COLOR`
+    /// isn't set.
+    #[clap(long, value_name = "WHEN")]
+    pub(crate) color: Option<ColorChoice>,
+
+    /// Don't print hints about how to resolve problems.
+    ///
+    /// Hints can also be turned off with `git config format-staged.advice false`.
+    #[clap(long)]
+    pub(crate) no_advice: bool,
+
+    /// Read default options from FILE, instead of `.git-format-staged.toml` in the
+    /// repository root.
+    ///
+    /// Options given on the command line take precedence over the config file.
+    #[clap(long, value_name = "FILE")]
+    pub(crate) config: Option<PathBuf>,
+
+    /// The formatting command as a single string, which is split into words the way a
+    /// shell would, instead of after `--` (e.g. `--command "prettier --write"`).
+    ///
+    /// The command isn't run by a shell, so nothing in it is expanded, and shell
+    /// operators such as `|` and `;` have to be quoted to be passed literally.
+    #[clap(long = "command", value_name = "COMMAND", conflicts_with = "command")]
+    pub(crate) shell_command: Option<String>,
+
+    /// Read and update the index in FILE, instead of the repository's index.
+    ///
+    /// By default the index named by `GIT_INDEX_FILE` is used, if it's set.
+    #[clap(long, value_name = "FILE")]
+    pub(crate) index_file: Option<PathBuf>,
+
+    /// Stage the formatted files in a copy of the index at PATH, and leave the index
+    /// itself and the working tree as they were.
+    ///
+    /// PATH can be used as `GIT_INDEX_FILE`, e.g. to inspect the result or to commit
+    /// it separately. It's replaced if it already exists.
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["no_index_write", "commit", "allow_unstaged", "diff_head"]
+    )]
+    pub(crate) out_index: Option<PathBuf>,
+
+    /// The formatting command.
+    ///
+    /// Several commands can be separated by `--then`. They are run in order, each
+    /// one formatting the output of the last.
+    ///
+    /// A relative program path, like `node_modules/.bin/prettier`, that doesn't exist
+    /// in the current directory is looked up from the repository root instead.
+    ///
+    /// Leading `KEY=VALUE` words set environment variables for the command, as they
+    /// would in a shell.
+    #[clap(last = true)]
+    pub(crate) command: Vec<String>,
+}
+
+/// A Git attribute for [`Cli::attr`]: its name, and the value it must have, if it must
+/// have one rather than just being set.
+#[derive(Clone)]
+pub(crate) struct Attribute {
+    pub(crate) name: String,
+    pub(crate) value: Option<String>,
+}
+
+impl Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}={}", self.name, value),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A file type for [`Cli::types`], and the formatter for files of that type, if it has
+/// its own.
+#[derive(Clone)]
+pub(crate) struct TypeFormatter {
+    pub(crate) file_type: FileType,
+    pub(crate) command: Option<Vec<String>>,
+}
+
+impl Display for TypeFormatter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.command {
+            Some(command) => write!(
+                f,
+                "{}={}",
+                self.file_type,
+                command_line(&command[0], &command[1..])
+            ),
+            None => write!(f, "{}", self.file_type),
+        }
+    }
+}
+
+#[derive(clap::Subcommand)]
+pub(crate) enum CliCommand {
+    /// Format stdin and write the result to stdout, without touching Git, e.g. for an
+    /// editor's format-on-save.
+    Filter {
+        /// The path of the file being formatted, which replaces `{}` in the command.
+        #[clap(long, value_name = "PATH")]
+        path: Option<PathBuf>,
+
+        /// The formatting command, which reads from stdin and writes to stdout, as with
+        /// `--stdin`.
+        #[clap(last = true, required = true)]
+        command: Vec<String>,
+    },
+}
+
+pub(crate) fn parse_seconds(arg: &str) -> Result<Duration, String> {
+    match arg.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(Duration::from_secs_f64(seconds)),
+        _ => Err(format!("`{}` isn't a positive number of seconds", arg)),
+    }
+}
+
+/// The signals that --kill-signal can be, without their `SIG` prefixes.
+pub(crate) const KILL_SIGNALS: &[&str] = &["HUP", "INT", "QUIT", "KILL", "USR1", "USR2", "TERM"];
+
+pub(crate) fn parse_signal(arg: &str) -> Result<String, String> {
+    let name = arg.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    if KILL_SIGNALS.contains(&name) {
+        Ok(name.to_string())
+    } else {
+        Err(format!(
+            "unsupported signal `{}` (supported signals: {})",
+            arg,
+            KILL_SIGNALS.join(", ")
+        ))
+    }
+}
+
+/// Parse a number of jobs, or `auto` for as many as there are CPUs.
+pub(crate) fn parse_jobs(arg: &str) -> Result<usize, String> {
+    if arg == "auto" {
+        return Ok(std::thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+    }
+    match arg.parse() {
+        Ok(jobs) if jobs > 0 => Ok(jobs),
+        _ => Err(format!("invalid number of jobs `{}`", arg)),
+    }
+}
+
+/// Parse `EXT=N`, or `{EXT,EXT,...}=N` for several extensions that share the N jobs.
+pub(crate) fn parse_jobs_per_extension(arg: &str) -> Result<(Vec<String>, usize), String> {
+    let (extensions, jobs) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected EXT=N".to_string())?;
+    let extensions: Vec<&str> = match extensions
+        .strip_prefix('{')
+        .and_then(|group| group.strip_suffix('}'))
+    {
+        Some(group) => group.split(',').collect(),
+        None => vec![extensions],
+    };
+    let extensions = extensions
+        .into_iter()
+        .map(|extension| {
+            let extension = extension.strip_prefix('.').unwrap_or(extension);
+            if extension.is_empty() || extension.contains(['/', '{', '}', ',']) {
+                return Err(format!("invalid extension `{}`", extension));
+            }
+            Ok(extension.to_string())
+        })
+        .collect::<Result<_, _>>()?;
+    match jobs.parse() {
+        Ok(jobs) if jobs > 0 => Ok((extensions, jobs)),
+        _ => Err(format!("invalid number of jobs `{}`", jobs)),
+    }
+}
+
+pub(crate) fn parse_attribute(arg: &str) -> Result<Attribute, String> {
+    let (name, value) = match arg.split_once('=') {
+        Some((name, value)) => (name, Some(value.to_string())),
+        None => (arg, None),
+    };
+    if name.is_empty() || name.starts_with('-') || name.starts_with('!') {
+        return Err(format!("invalid attribute name `{}`", name));
+    }
+    Ok(Attribute {
+        name: name.to_string(),
+        value,
+    })
+}
+
+pub(crate) fn parse_type(arg: &str) -> Result<TypeFormatter, String> {
+    let (name, command) = match arg.split_once('=') {
+        Some((name, command)) => (name, Some(command)),
+        None => (arg, None),
+    };
+    let file_type = FileType::new(name).map_err(|err| err.to_string())?;
+    let command = match command {
+        Some(command) => {
+            let command = shell_split(command)?;
+            if command.is_empty() {
+                return Err(format!("no formatter specified for `{}`", name));
+            }
+            Some(command)
+        }
+        None => None,
+    };
+    Ok(TypeFormatter { file_type, command })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum StdinFilepath {
+    Cwd,
+    Repo,
+    Absolute,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum RelativeTo {
+    Repo,
+    Cwd,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Only {
+    StagedClean,
+}
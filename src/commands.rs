@@ -0,0 +1,1128 @@
+//! Running formatting commands: batching files onto command lines, running them in jobs,
+//! piping staged content through filters, and stopping them at the `--timeout` deadline.
+
+use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+    fmt::{self, Display},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use git2::{DiffOptions, Error, ErrorCode, ObjectType, Repository};
+
+use crate::{
+    exit,
+    options::Options,
+    output, read_file, repo_relative_path,
+    staging::{head_tree, staged_entry},
+    ExitCode, RUNNING_VAR,
+};
+
+/// Why a formatting command didn't succeed.
+pub(crate) enum CommandError {
+    /// The command couldn't be run.
+    Spawn {
+        command: String,
+        err: std::io::Error,
+    },
+
+    /// The command exited unsuccessfully.
+    Failed { program: String, status: ExitStatus },
+
+    /// The command was killed because the `--deadline` passed.
+    TimedOut { program: String },
+}
+
+impl CommandError {
+    /// The error for `err` from [`wait_for`], while running `command`.
+    pub(crate) fn from_wait(err: std::io::Error, command: String, program: &str) -> CommandError {
+        if err.kind() == std::io::ErrorKind::TimedOut {
+            CommandError::TimedOut {
+                program: program.to_string(),
+            }
+        } else {
+            CommandError::Spawn { command, err }
+        }
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Spawn { command, err } => write!(f, "command `{command}` failed: {err}"),
+            CommandError::Failed { program, status } => match status.code() {
+                Some(code) => write!(f, "{} exited with code {}", program, code),
+                None => match signal(status) {
+                    Some(signal) => write!(f, "{} was killed by {}", program, signal),
+                    None => write!(f, "{} was terminated by a signal", program),
+                },
+            },
+            CommandError::TimedOut { program } => {
+                write!(
+                    f,
+                    "{} was still running when the --deadline passed",
+                    program
+                )
+            }
+        }
+    }
+}
+
+/// The signal that terminated a process, by name if it's one whose number is the same on
+/// every Unix.
+#[cfg(unix)]
+pub(crate) fn signal(status: &ExitStatus) -> Option<String> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let signal = status.signal()?;
+    let name = match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        6 => "SIGABRT",
+        8 => "SIGFPE",
+        // The kernel's out-of-memory killer uses SIGKILL, and so do CI runners that
+        // enforce memory limits.
+        9 => return Some("SIGKILL (perhaps it ran out of memory)".to_string()),
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        _ => return Some(format!("signal {}", signal)),
+    };
+    Some(name.to_string())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn signal(_status: &ExitStatus) -> Option<String> {
+    None
+}
+
+/// Whether formatting commands are printed before they're run. See [`Cli::print_command`](crate::cli::Cli::print_command).
+pub(crate) static PRINT_COMMANDS: AtomicBool = AtomicBool::new(false);
+
+/// The default for [`Cli::arg_max`](crate::cli::Cli::arg_max). It's well under the usual limits on the size of a
+/// command line, leaving room for the environment.
+#[cfg(windows)]
+pub(crate) const DEFAULT_ARG_MAX: usize = 30 * 1024;
+#[cfg(not(windows))]
+pub(crate) const DEFAULT_ARG_MAX: usize = 128 * 1024;
+
+/** Run each formatting command on `paths` in turn, stopping at the first one that fails.
+
+If the arguments would be longer than [`Options::arg_max`] bytes, each command is run
+several times on batches of `paths`. With [`Options::chdir_file`], each command is run
+once for each path instead, from the path's directory; with
+[`Options::formatter_stdin`], once for each path with the file's content on its stdin;
+and with `line_ranges`, once for each path with changed lines, which are templated into
+its arguments by [`with_line_ranges`].
+
+The commands' stdout is sent to stderr. If `capture_stderr` is set, their stderr is
+returned instead of being shown. When a command fails, what it wrote is shown after all.
+*/
+pub(crate) fn run_commands(
+    commands: &[&[String]],
+    paths: &[PathBuf],
+    line_ranges: Option<&[Vec<LineRange>]>,
+    capture_stderr: bool,
+    options: &Options,
+) -> Result<Vec<u8>, CommandError> {
+    let mut stderr = Vec::new();
+    for command in commands {
+        if let Some(line_ranges) = line_ranges {
+            for (path, ranges) in paths.iter().zip(line_ranges) {
+                if ranges.is_empty() {
+                    continue;
+                }
+                let command = with_line_ranges(command, ranges);
+                run_command(
+                    &command,
+                    std::slice::from_ref(path),
+                    None,
+                    capture_stderr,
+                    &mut stderr,
+                )?;
+            }
+            continue;
+        }
+        if options.formatter_stdin {
+            for path in paths {
+                let (_, command_stderr) = filter_commands(
+                    &[command],
+                    path,
+                    &read_file(path),
+                    capture_stderr,
+                    FilterOutput::InPlace,
+                )?;
+                stderr.extend(command_stderr);
+            }
+            continue;
+        }
+        if options.chdir_file {
+            for path in paths {
+                let dir = match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir,
+                    _ => Path::new("."),
+                };
+                let name = path.file_name().unwrap_or_default();
+                run_command(
+                    command,
+                    &[PathBuf::from(name)],
+                    Some(dir),
+                    capture_stderr,
+                    &mut stderr,
+                )?;
+            }
+            continue;
+        }
+        if options.jobs_per_extension.is_empty() {
+            run_in_jobs(
+                command,
+                paths,
+                options.jobs,
+                capture_stderr,
+                options.arg_max,
+                &mut stderr,
+            )?;
+        } else {
+            run_by_extension(command, paths, capture_stderr, options, &mut stderr)?;
+        }
+    }
+
+    Ok(stderr)
+}
+
+/// A block of lines in a file, from the first line to the last, counting from 1.
+pub(crate) type LineRange = (usize, usize);
+
+/** The blocks of lines in the staged version of each of `files` that were added or
+changed since `HEAD`, for [`Options::only_changed_lines`].
+
+A file that isn't in `HEAD` is all changed. Lines that were only removed leave nothing
+to format.
+*/
+pub(crate) fn changed_lines(
+    repo: &Repository,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+) -> Result<Vec<Vec<LineRange>>, Error> {
+    let index = repo.index()?;
+    let head_tree = head_tree(repo)?;
+    let mut line_ranges = Vec::with_capacity(files.len());
+    for file in files {
+        let path = repo_relative_path(dir_prefix, file);
+        let staged = repo.find_blob(staged_entry(&index, dir_prefix, file)?.id)?;
+        let head = match head_tree.as_ref().map(|tree| tree.get_path(&path)) {
+            Some(Ok(entry)) if entry.kind() == Some(ObjectType::Blob) => {
+                Some(repo.find_blob(entry.id())?)
+            }
+            Some(Ok(_)) | None => None,
+            Some(Err(err)) if err.code() == ErrorCode::NotFound => None,
+            Some(Err(err)) => return Err(err),
+        };
+        let patch = git2::Patch::from_buffers(
+            head.as_ref().map_or(&[][..], |head| head.content()),
+            None,
+            staged.content(),
+            None,
+            Some(DiffOptions::new().context_lines(0).force_text(true)),
+        )?;
+        let mut ranges = Vec::new();
+        for i in 0..patch.num_hunks() {
+            let (hunk, _) = patch.hunk(i)?;
+            if hunk.new_lines() > 0 {
+                let start = hunk.new_start() as usize;
+                ranges.push((start, start + hunk.new_lines() as usize - 1));
+            }
+        }
+        line_ranges.push(ranges);
+    }
+    Ok(line_ranges)
+}
+
+/// `command` with each word that contains `{start}` or `{end}` repeated for each of
+/// `ranges`, with those replaced by the range's first and last line.
+pub(crate) fn with_line_ranges(command: &[String], ranges: &[LineRange]) -> Vec<String> {
+    let mut expanded = Vec::with_capacity(command.len());
+    for word in command {
+        if word.contains("{start}") || word.contains("{end}") {
+            expanded.extend(ranges.iter().map(|(start, end)| {
+                word.replace("{start}", &start.to_string())
+                    .replace("{end}", &end.to_string())
+            }));
+        } else {
+            expanded.push(word.clone());
+        }
+    }
+    expanded
+}
+
+/** Run `command` on `paths` in parallel, as configured by [`Options::jobs_per_extension`].
+
+The paths with each configured extension, or group of extensions, are split between
+that many jobs, which are run at the same time. Each group's jobs all finish before the
+next group's start. The remaining paths are formatted in [`Options::jobs`] jobs.
+*/
+pub(crate) fn run_by_extension(
+    command: &[String],
+    paths: &[PathBuf],
+    capture_stderr: bool,
+    options: &Options,
+    stderr: &mut Vec<u8>,
+) -> Result<(), CommandError> {
+    let mut groups: Vec<(usize, Vec<PathBuf>)> = options
+        .jobs_per_extension
+        .iter()
+        .map(|&(_, jobs)| (jobs, Vec::new()))
+        .collect();
+    let mut other_paths = Vec::new();
+    for path in paths {
+        let extension = path.extension();
+        match options
+            .jobs_per_extension
+            .iter()
+            .position(|(extensions, _)| {
+                extension.is_some_and(|extension| {
+                    extensions.iter().any(|other| extension == other.as_str())
+                })
+            }) {
+            Some(i) => groups[i].1.push(path.clone()),
+            None => other_paths.push(path.clone()),
+        }
+    }
+
+    for (jobs, paths) in groups.iter().filter(|(_, paths)| !paths.is_empty()) {
+        run_in_jobs(
+            command,
+            paths,
+            *jobs,
+            capture_stderr,
+            options.arg_max,
+            stderr,
+        )?;
+    }
+    if !other_paths.is_empty() {
+        run_in_jobs(
+            command,
+            &other_paths,
+            options.jobs,
+            capture_stderr,
+            options.arg_max,
+            stderr,
+        )?;
+    }
+    Ok(())
+}
+
+/** Run `command` on `paths`, split between `jobs` runs of it at the same time.
+
+There are never more jobs than paths. Each job formats its paths in batches of at most
+`arg_max` bytes, one batch at a time.
+*/
+pub(crate) fn run_in_jobs(
+    command: &[String],
+    paths: &[PathBuf],
+    jobs: usize,
+    capture_stderr: bool,
+    arg_max: usize,
+    stderr: &mut Vec<u8>,
+) -> Result<(), CommandError> {
+    let jobs = jobs.clamp(1, paths.len().max(1));
+    if jobs == 1 {
+        for batch in batch_paths(command, paths, arg_max) {
+            run_command(command, batch, None, capture_stderr, stderr)?;
+        }
+        return Ok(());
+    }
+
+    trace!("formatting {} files in {} jobs", paths.len(), jobs);
+    let results: Vec<Result<Vec<u8>, CommandError>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(paths.len().div_ceil(jobs))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut stderr = Vec::new();
+                    for batch in batch_paths(command, chunk, arg_max) {
+                        run_command(command, batch, None, capture_stderr, &mut stderr)?;
+                    }
+                    Ok(stderr)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    // Every job is waited for before a failure is reported, so that none is still
+    // writing to its files.
+    for result in results {
+        stderr.extend(result?);
+    }
+    Ok(())
+}
+
+/** Split `paths` into batches that can be added to `command` without its arguments
+being longer than `arg_max` bytes.
+
+Each argument counts its terminating NUL byte. A batch always has at least one path, even
+if that path alone is too long.
+*/
+pub(crate) fn batch_paths<'a>(
+    command: &[String],
+    paths: &'a [PathBuf],
+    arg_max: usize,
+) -> Vec<&'a [PathBuf]> {
+    let arg_len = |arg: &OsStr| arg.len() + 1;
+    let command_len: usize = command.iter().map(|arg| arg_len(arg.as_ref())).sum();
+
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut len = command_len;
+    for (i, path) in paths.iter().enumerate() {
+        if i > start && len + arg_len(path.as_os_str()) > arg_max {
+            batches.push(&paths[start..i]);
+            start = i;
+            len = command_len;
+        }
+        len += arg_len(path.as_os_str());
+    }
+    batches.push(&paths[start..]);
+    batches
+}
+
+/// Run `command` on `paths`, from `current_dir` if given, adding its stderr to `stderr`
+/// if `capture_stderr` is set.
+pub(crate) fn run_command(
+    command: &[String],
+    paths: &[PathBuf],
+    current_dir: Option<&Path>,
+    capture_stderr: bool,
+    stderr: &mut Vec<u8>,
+) -> Result<(), CommandError> {
+    let (env, command) = split_env(command);
+    let (program, args) = command
+        .split_first()
+        .expect("internal error: empty formatting command");
+    let args: Vec<&OsStr> = args
+        .iter()
+        .map(AsRef::as_ref)
+        .chain(paths.iter().map(|path| path.as_os_str()))
+        .collect();
+
+    // A relative program path would otherwise be looked up from `current_dir`.
+    let program_path = match (current_dir, std::env::current_dir()) {
+        (Some(_), Ok(cwd))
+            if program.contains(std::path::MAIN_SEPARATOR) && Path::new(program).is_relative() =>
+        {
+            cwd.join(program)
+        }
+        _ => PathBuf::from(program),
+    };
+
+    if PRINT_COMMANDS.load(Ordering::Relaxed) {
+        let cd = match current_dir {
+            Some(current_dir) => format!("cd {} && ", shell_quote(&current_dir.to_string_lossy())),
+            None => String::new(),
+        };
+        note!(
+            "{}{}{}",
+            cd,
+            env_line(&env),
+            command_line(&program_path, &args)
+        );
+    }
+
+    let spawn_error = |err| CommandError::Spawn {
+        command: command_line(&program_path, &args),
+        err,
+    };
+
+    let mut child = Command::new(&program_path);
+    if let Some(current_dir) = current_dir {
+        child.current_dir(current_dir);
+    }
+    child.args(&args).envs(env).env(RUNNING_VAR, "1");
+    set_process_group(&mut child);
+    child.stdout(output::formatter_stdout());
+    child.stderr(if capture_stderr {
+        Stdio::piped()
+    } else {
+        output::formatter_stderr()
+    });
+    let output = child.spawn().map_err(spawn_error).and_then(|child| {
+        wait_for(child).map_err(|err| {
+            CommandError::from_wait(err, command_line(&program_path, &args), program)
+        })
+    })?;
+    // Captured stderr is kept for the summary, rather than replayed.
+    let uncaptured_stderr: &[u8] = if capture_stderr {
+        stderr.extend(&output.stderr);
+        &[]
+    } else {
+        &output.stderr
+    };
+    output::replay(
+        &command_line(&program_path, &args),
+        &output.stdout,
+        uncaptured_stderr,
+    );
+    let exit_status = output.status;
+
+    if !exit_status.success() {
+        output::failed_stderr(stderr);
+        return Err(CommandError::Failed {
+            program: program.clone(),
+            status: exit_status,
+        });
+    }
+
+    Ok(())
+}
+
+/// Where [`filter_commands`] reads the formatted content from.
+#[derive(Clone, Copy)]
+pub(crate) enum FilterOutput<'a> {
+    /// The commands' stdout.
+    Stdout,
+
+    /// Nowhere: the commands write their results to the path they're given themselves,
+    /// and their stdout is sent to stderr.
+    InPlace,
+
+    /// A named pipe at this path, which is created for each command and replaces
+    /// `{output}` in its arguments. The commands' stdout is sent to stderr.
+    Fifo(&'a Path),
+}
+
+/** Run `content` through each formatting command in turn, from stdin to `output`.
+
+`{}` in the commands' arguments is replaced by `path`. Returns the formatted content, and
+the commands' stderr if `capture_stderr` is set (see [`run_commands`]). With
+[`FilterOutput::InPlace`], `content` is returned as it was.
+*/
+pub(crate) fn filter_commands(
+    commands: &[&[String]],
+    path: &Path,
+    content: &[u8],
+    capture_stderr: bool,
+    output: FilterOutput,
+) -> Result<(Vec<u8>, Vec<u8>), CommandError> {
+    let read_stdout = matches!(output, FilterOutput::Stdout);
+    let mut content = content.to_vec();
+    let mut stderr = Vec::new();
+    for command in commands {
+        let (env, command) = split_env(command);
+        let (program, args) = command
+            .split_first()
+            .expect("internal error: empty formatting command");
+        let args: Vec<OsString> = args
+            .iter()
+            .map(|arg| match output {
+                FilterOutput::Fifo(fifo) => substitute_fifo(arg, path, fifo),
+                _ => substitute_path(arg, path),
+            })
+            .collect();
+
+        if PRINT_COMMANDS.load(Ordering::Relaxed) {
+            note!("{}{}", env_line(&env), command_line(program, &args));
+        }
+
+        let spawn_error = |err| CommandError::Spawn {
+            command: command_line(program, &args),
+            err,
+        };
+
+        // The pipe is read from before the formatter starts, so that it never blocks
+        // writing to it.
+        let fifo = match output {
+            FilterOutput::Fifo(fifo) => {
+                Some(Fifo::create(fifo).map_err(|err| CommandError::Spawn {
+                    command: command_line("mkfifo", &[fifo]),
+                    err,
+                })?)
+            }
+            _ => None,
+        };
+
+        let mut child = Command::new(program);
+        set_process_group(&mut child);
+        let child = child
+            .args(&args)
+            .envs(env)
+            .env(RUNNING_VAR, "1")
+            .stdin(Stdio::piped())
+            .stdout(if read_stdout {
+                Stdio::piped()
+            } else {
+                output::formatter_stdout()
+            })
+            .stderr(if capture_stderr {
+                Stdio::piped()
+            } else {
+                output::formatter_stderr()
+            })
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                if let Some(fifo) = fifo {
+                    let _ = fifo.finish();
+                }
+                return Err(spawn_error(err));
+            }
+        };
+
+        let mut stdin = child.stdin.take().unwrap();
+        // The input is written from another thread so that a formatter which starts
+        // writing before it has read everything can't block forever. The thread isn't
+        // waited for if the formatter is killed, because it may never finish.
+        let input = content.clone();
+        let writer = std::thread::spawn(move || match stdin.write_all(&input) {
+            // The formatter doesn't have to read all of its input.
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        });
+        let output = wait_for(child).and_then(|output| writer.join().unwrap().map(|()| output));
+        // The formatter has exited, so everything it wrote to the pipe can be read.
+        let fifo_content = fifo.map(Fifo::finish);
+        let output = output
+            .map_err(|err| CommandError::from_wait(err, command_line(program, &args), program))?;
+
+        let uncaptured_stdout: &[u8] = if read_stdout { &[] } else { &output.stdout };
+        let uncaptured_stderr: &[u8] = if capture_stderr {
+            stderr.extend(&output.stderr);
+            &[]
+        } else {
+            &output.stderr
+        };
+        output::replay(
+            &command_line(program, &args),
+            uncaptured_stdout,
+            uncaptured_stderr,
+        );
+        if !output.status.success() {
+            output::failed_stderr(&stderr);
+            return Err(CommandError::Failed {
+                program: program.clone(),
+                status: output.status,
+            });
+        }
+        match fifo_content {
+            Some(fifo_content) => {
+                // The pipe is still open if something the formatter started is still
+                // running, which counts as the formatter running past the deadline.
+                content = fifo_content.map_err(|err| {
+                    CommandError::from_wait(err, command_line(program, &args), program)
+                })?;
+            }
+            None if read_stdout => content = output.stdout,
+            None => {}
+        }
+    }
+
+    Ok((content, stderr))
+}
+
+/// A named pipe that a formatter writes its result to, for [`Options::output_fifo`], and
+/// the thread that reads from it.
+pub(crate) struct Fifo {
+    pub(crate) path: PathBuf,
+    events: Receiver<FifoEvent>,
+}
+
+/// What the thread reading from a [`Fifo`] has done.
+enum FifoEvent {
+    /// It has opened the pipe, which it can only do once a writer has opened it too.
+    Opened,
+
+    /// It has read everything from the pipe, or failed to.
+    Read(std::io::Result<Vec<u8>>),
+}
+
+impl Fifo {
+    /// Create the pipe at `path`, and start reading from it.
+    pub(crate) fn create(path: &Path) -> std::io::Result<Fifo> {
+        let status = Command::new("mkfifo")
+            .arg("-m")
+            .arg("600")
+            .arg(path)
+            .stdin(Stdio::null())
+            .status()?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!("mkfifo {}", status)));
+        }
+        let reader_path = path.to_path_buf();
+        let (sender, events) = mpsc::channel();
+        std::thread::spawn(move || {
+            // Opening the pipe blocks until the formatter opens it too.
+            let read = std::fs::File::open(reader_path).and_then(|mut pipe| {
+                let _ = sender.send(FifoEvent::Opened);
+                let mut content = Vec::new();
+                pipe.read_to_end(&mut content).map(|_| content)
+            });
+            let _ = sender.send(FifoEvent::Read(read));
+        });
+        Ok(Fifo {
+            path: path.to_path_buf(),
+            events,
+        })
+    }
+
+    /// Read everything that was written to the pipe, and remove it. This must only be
+    /// called once the formatter has exited.
+    ///
+    /// If the formatter never opened the pipe, the reader is still waiting for it to, or
+    /// hasn't started yet. Opening the pipe for writing here lets the reader's open
+    /// through whenever it happens, and once it has, closing it again ends the reader's
+    /// input, since the formatter's end was closed when it exited. Opening it for
+    /// reading as well keeps this open from waiting for a reader itself.
+    pub(crate) fn finish(self) -> std::io::Result<Vec<u8>> {
+        let writer = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .open(&self.path);
+        let mut event = self.receive();
+        if let Ok(FifoEvent::Opened) = event {
+            drop(writer);
+            event = self.receive();
+        }
+        let _ = std::fs::remove_file(&self.path);
+        match event? {
+            FifoEvent::Read(read) => read,
+            FifoEvent::Opened => unreachable!("the pipe is only opened once"),
+        }
+    }
+
+    /// Wait for the reader's next event, until the `--deadline`. A formatter can leave
+    /// the pipe open in a process that outlives it, so that the reader never finishes.
+    fn receive(&self) -> std::io::Result<FifoEvent> {
+        match DEADLINE.get() {
+            Some(&deadline) => self
+                .events
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "the output pipe was still open when the --deadline passed",
+                    )
+                }),
+            // The reader always sends what it read before it stops.
+            None => Ok(self.events.recv().unwrap()),
+        }
+    }
+}
+
+/// `arg` with each `{output}` replaced by `fifo`, and each `{}` by `path`.
+pub(crate) fn substitute_fifo(arg: &str, path: &Path, fifo: &Path) -> OsString {
+    let mut substituted = OsString::new();
+    for (i, part) in arg.split("{output}").enumerate() {
+        if i > 0 {
+            substituted.push(fifo);
+        }
+        substituted.push(substitute_path(part, path));
+    }
+    substituted
+}
+
+/// `arg` with each `{}` replaced by `path`.
+pub(crate) fn substitute_path(arg: &str, path: &Path) -> OsString {
+    let mut substituted = OsString::new();
+    for (i, part) in arg.split("{}").enumerate() {
+        if i > 0 {
+            substituted.push(path);
+        }
+        substituted.push(part);
+    }
+    substituted
+}
+
+/// Exit after a formatting command has failed, with the command's exit code if it has one.
+pub(crate) fn exit_on_command_error(err: CommandError) -> ! {
+    match err {
+        CommandError::Spawn {
+            err: ref spawn_err, ..
+        } => {
+            let code = if spawn_err.kind() == std::io::ErrorKind::NotFound {
+                ExitCode::FormatterNotFound
+            } else {
+                ExitCode::Error
+            };
+            error!("{}", err);
+            exit(code);
+        }
+        CommandError::Failed { status, .. } => match status.code() {
+            Some(code) => exit(code),
+            None => {
+                error!("{}", err);
+                exit(ExitCode::Error);
+            }
+        },
+        CommandError::TimedOut { .. } => {
+            error!("{}", err);
+            exit(ExitCode::TimedOut);
+        }
+    }
+}
+
+/// When the run has to finish by. See [`Cli::deadline`](crate::cli::Cli::deadline).
+pub(crate) static DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+/// The signal that stops a formatter after the [`DEADLINE`], and how long it has to exit
+/// before it's killed. See [`Cli::kill_signal`](crate::cli::Cli::kill_signal) and [`Cli::kill_grace`](crate::cli::Cli::kill_grace).
+pub(crate) static KILL: OnceLock<(String, Duration)> = OnceLock::new();
+
+/// Put a formatting command in its own process group, if it might have to be stopped
+/// with [`stop`].
+pub(crate) fn set_process_group(command: &mut Command) {
+    #[cfg(unix)]
+    if DEADLINE.get().is_some() {
+        use std::os::unix::process::CommandExt;
+
+        command.process_group(0);
+    }
+
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/** Stop `child` after the deadline has passed.
+
+On Unix, the [`KILL`] signal is sent to its process group, and then SIGKILL if it hasn't
+exited by the end of the grace period. Elsewhere it's killed straight away.
+*/
+pub(crate) fn stop(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some((signal, grace)) = KILL.get() {
+        let group = format!("-{}", child.id());
+        let send = |signal: &str| {
+            // There's no way to signal a process group in the standard library. `kill`
+            // failing just means that the group has already exited.
+            Command::new("kill")
+                .args(["-s", signal, "--", &group])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map(|_| ())
+        };
+        match send(signal) {
+            Ok(()) => {
+                let give_up = Instant::now() + *grace;
+                while signal != "KILL" && Instant::now() < give_up {
+                    if let Ok(Some(_)) = child.try_wait() {
+                        // Anything else in the group gets no grace period.
+                        let _ = send("KILL");
+                        return;
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                let _ = send("KILL");
+            }
+            Err(err) => warning!(
+                "couldn't run `kill` to stop process group {}, so only its leader is killed: {}",
+                child.id(),
+                err
+            ),
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/** Wait for `child` to exit, collecting its stdout and stderr if they're piped, like
+[`Child::wait_with_output`].
+
+If the `--deadline` passes first, the child is killed and an error of kind
+[`std::io::ErrorKind::TimedOut`] is returned. Its pipes are then read by threads that are
+left behind, in case something the child started still has them open.
+*/
+pub(crate) fn wait_for(mut child: Child) -> std::io::Result<Output> {
+    let Some(&deadline) = DEADLINE.get() else {
+        return child.wait_with_output();
+    };
+
+    fn read_pipe(
+        pipe: Option<impl Read + Send + 'static>,
+    ) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+        std::thread::spawn(move || {
+            let mut content = Vec::new();
+            if let Some(mut pipe) = pipe {
+                pipe.read_to_end(&mut content)?;
+            }
+            Ok(content)
+        })
+    }
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            stop(&mut child);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "the deadline passed",
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap()?,
+        stderr: stderr.join().unwrap()?,
+    })
+}
+
+/// Run the shell command `command` with `files` as its positional parameters.
+pub(crate) fn run_post_format(command: &str, files: &[PathBuf]) -> Result<(), CommandError> {
+    let args: Vec<&OsStr> = ["-c", command, "sh"]
+        .into_iter()
+        .map(OsStr::new)
+        .chain(files.iter().map(|file| file.as_os_str()))
+        .collect();
+
+    if PRINT_COMMANDS.load(Ordering::Relaxed) {
+        note!("{}", command_line("sh", &args));
+    }
+
+    let status = Command::new("sh")
+        .args(&args)
+        .env(RUNNING_VAR, "1")
+        .stdout(output::child_stdout())
+        .status()
+        .map_err(|err| CommandError::Spawn {
+            command: command_line("sh", &args),
+            err,
+        })?;
+    if !status.success() {
+        return Err(CommandError::Failed {
+            program: format!("`{}`", command),
+            status,
+        });
+    }
+    Ok(())
+}
+
+/** Split the leading `KEY=VALUE` environment variable assignments off `command`.
+
+As in a shell, a word is an assignment if the part before the `=` is a valid variable
+name. The first word that isn't starts the command proper.
+*/
+pub(crate) fn split_env(command: &[String]) -> (Vec<(&str, &str)>, &[String]) {
+    let env: Vec<(&str, &str)> = command
+        .iter()
+        .map_while(|word| {
+            let (key, value) = word.split_once('=')?;
+            let mut chars = key.chars();
+            let is_name = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            is_name.then_some((key, value))
+        })
+        .collect();
+    let rest = &command[env.len()..];
+    (env, rest)
+}
+
+/// Environment variable assignments as the start of a shell command, quoting the values
+/// that need it.
+pub(crate) fn env_line(env: &[(&str, &str)]) -> String {
+    env.iter()
+        .map(|(key, value)| format!("{}={} ", key, shell_quote(value)))
+        .collect()
+}
+
+/// `program` and `args` as a shell command, quoting the words that need it.
+pub(crate) fn command_line(program: impl AsRef<OsStr>, args: &[impl AsRef<OsStr>]) -> String {
+    std::iter::once(program.as_ref())
+        .chain(args.iter().map(AsRef::as_ref))
+        .map(|word| shell_quote(&word.to_string_lossy()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quote `word` for a POSIX shell, if it contains anything other than safe characters.
+pub(crate) fn shell_quote(word: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(format!("'{}'", word.replace('\'', "'\\''")))
+    }
+}
+
+/** Split `line` into words the way a POSIX shell would, without expanding anything.
+
+Words are separated by whitespace. Inside single quotes, everything up to the next
+single quote is taken literally. Inside double quotes, `\` only escapes `"`, `\`, `$`
+and `` ` ``. Outside quotes, `\` makes the next character literal. Unquoted shell
+operators are an error, because there's no shell to interpret them.
+*/
+pub(crate) fn shell_split(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed `'`".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unclosed `\"`".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed `\"`".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing `\\`".to_string()),
+            },
+            '|' | '&' | ';' | '<' | '>' | '(' | ')' | '$' | '`' => {
+                return Err(format!(
+                    "`{}` would need a shell to run; quote it to pass it literally",
+                    c
+                ));
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn shell_split_splits_on_whitespace() {
+        assert_eq!(shell_split("  a  b\tc\n"), Ok(words(&["a", "b", "c"])));
+        assert_eq!(shell_split(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn shell_split_takes_single_quotes_literally() {
+        assert_eq!(
+            shell_split(r#"sed 's/ \+$//' -i"#),
+            Ok(words(&["sed", r"s/ \+$//", "-i"]))
+        );
+        assert_eq!(shell_split("''"), Ok(words(&[""])));
+        assert_eq!(shell_split("a'b c'd"), Ok(words(&["ab cd"])));
+    }
+
+    #[test]
+    fn shell_split_escapes_some_characters_in_double_quotes() {
+        assert_eq!(
+            shell_split(r#""a \"b\" \\ \$ \` \n""#),
+            Ok(words(&[r#"a "b" \ $ ` \n"#]))
+        );
+        assert_eq!(shell_split(r#""""#), Ok(words(&[""])));
+    }
+
+    #[test]
+    fn shell_split_escapes_with_backslash() {
+        assert_eq!(shell_split(r"a\ b \;"), Ok(words(&["a b", ";"])));
+    }
+
+    #[test]
+    fn shell_split_rejects_unclosed_quotes() {
+        assert_eq!(shell_split("'a"), Err("unclosed `'`".to_string()));
+        assert_eq!(shell_split(r#""a"#), Err("unclosed `\"`".to_string()));
+        assert_eq!(shell_split(r#""a\"#), Err("unclosed `\"`".to_string()));
+    }
+
+    #[test]
+    fn shell_split_rejects_trailing_backslash() {
+        assert_eq!(shell_split(r"a \"), Err("trailing `\\`".to_string()));
+    }
+
+    #[test]
+    fn shell_split_rejects_shell_operators() {
+        assert!(shell_split("a | b").is_err());
+        assert!(shell_split("a; b").is_err());
+        assert!(shell_split("a $HOME").is_err());
+        assert_eq!(shell_split("'a | b'"), Ok(words(&["a | b"])));
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn batch_paths_fits_everything_in_one_batch() {
+        let all = paths(&["a", "b", "c"]);
+
+        assert_eq!(batch_paths(&words(&["fmt"]), &all, 1000), vec![&all[..]]);
+    }
+
+    #[test]
+    fn batch_paths_counts_each_nul() {
+        // `fmt` and each path take 4 and 2 bytes with their NULs.
+        let all = paths(&["a", "b", "c"]);
+
+        assert_eq!(
+            batch_paths(&words(&["fmt"]), &all, 8),
+            vec![&all[..2], &all[2..]]
+        );
+        assert_eq!(
+            batch_paths(&words(&["fmt"]), &all, 7),
+            vec![&all[..1], &all[1..2], &all[2..]]
+        );
+    }
+
+    #[test]
+    fn batch_paths_keeps_a_path_that_is_too_long_alone() {
+        let all = paths(&["a", "much-too-long", "b"]);
+
+        assert_eq!(
+            batch_paths(&words(&["fmt"]), &all, 8),
+            vec![&all[..1], &all[1..2], &all[2..]]
+        );
+    }
+
+    #[test]
+    fn batch_paths_with_no_paths_is_one_empty_batch() {
+        assert_eq!(
+            batch_paths(&words(&["fmt"]), &[], 8),
+            vec![&[] as &[PathBuf]]
+        );
+    }
+}
@@ -0,0 +1,482 @@
+/*! Default options, read from a `.git-format-staged.toml` file.
+
+The file is a small subset of TOML: top-level `key = value` pairs, where each value is a
+string, a boolean, a non-negative integer, or an array of those. Keys are named after the
+corresponding command line options:
+
+```toml
+files = ["src/main.rs", "src/config.rs"]
+command = ["rustfmt", "--edition", "2021"]
+no-working-tree = true
+jobs = 4
+```
+
+`globs` picks the staged files to format instead of, or as well as, listing them in
+`files`, and `excludes` leaves files out of either. Neither applies to files given on the
+command line. Paths in `files`, and the patterns, are relative to the repository root.
+*/
+
+use std::fmt::{self, Display};
+
+/// The configuration file that is read from the repository root, if it exists.
+pub const CONFIG_FILE_NAME: &str = ".git-format-staged.toml";
+
+/// Options from a configuration file. Options given on the command line take precedence.
+#[derive(Default)]
+pub struct Config {
+    pub files: Option<Vec<String>>,
+    pub globs: Option<Vec<String>>,
+    pub excludes: Option<Vec<String>>,
+    pub command: Option<Vec<String>>,
+
+    /// A number of jobs, or `auto`, as given to `--jobs`.
+    pub jobs: Option<String>,
+    pub no_working_tree: Option<bool>,
+    pub index_only: Option<bool>,
+    pub allow_unstaged: Option<bool>,
+    pub color: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    line: usize,
+    message: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+enum Value {
+    String(String),
+    Bool(bool),
+    Integer(u64),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Bool(_) => "a boolean",
+            Value::Integer(_) => "an integer",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(source: &str) -> Result<Config, ConfigError> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+            line: 1,
+        };
+        let mut config = Config::default();
+        let mut seen_keys: Vec<String> = Vec::new();
+
+        loop {
+            parser.skip_whitespace_and_comments(true);
+            if parser.peek().is_none() {
+                break;
+            }
+
+            let line = parser.line;
+            if parser.peek() == Some('[') {
+                return Err(parser.error("tables are not supported"));
+            }
+
+            let key = parser.key()?;
+            parser.skip_whitespace_and_comments(false);
+            parser.expect('=')?;
+            parser.skip_whitespace_and_comments(false);
+            let value = parser.value()?;
+            parser.end_of_line()?;
+
+            if seen_keys.contains(&key) {
+                return Err(ConfigError {
+                    line,
+                    message: format!("duplicate key `{}`", key),
+                });
+            }
+            config
+                .set(&key, value)
+                .map_err(|message| ConfigError { line, message })?;
+            seen_keys.push(key);
+        }
+
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match key {
+            "files" => self.files = Some(string_array(key, value)?),
+            "globs" => self.globs = Some(string_array(key, value)?),
+            "excludes" => self.excludes = Some(string_array(key, value)?),
+            "command" => self.command = Some(string_array(key, value)?),
+            "jobs" => {
+                self.jobs = Some(match value {
+                    Value::Integer(jobs) => jobs.to_string(),
+                    Value::String(jobs) => jobs,
+                    value => {
+                        return Err(format!(
+                            "`{}` must be an integer or a string, not {}",
+                            key,
+                            value.type_name()
+                        ))
+                    }
+                })
+            }
+            "no-working-tree" => self.no_working_tree = Some(bool(key, value)?),
+            "index-only" => self.index_only = Some(bool(key, value)?),
+            "allow-unstaged" => self.allow_unstaged = Some(bool(key, value)?),
+            "color" => self.color = Some(string(key, value)?),
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+fn string(key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value),
+        value => Err(format!(
+            "`{}` must be a string, not {}",
+            key,
+            value.type_name()
+        )),
+    }
+}
+
+fn bool(key: &str, value: Value) -> Result<bool, String> {
+    match value {
+        Value::Bool(value) => Ok(value),
+        value => Err(format!(
+            "`{}` must be a boolean, not {}",
+            key,
+            value.type_name()
+        )),
+    }
+}
+
+fn string_array(key: &str, value: Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::Array(values) => values
+            .into_iter()
+            .map(|value| match value {
+                Value::String(value) => Ok(value),
+                value => Err(format!(
+                    "`{}` must be an array of strings, but it contains {}",
+                    key,
+                    value.type_name()
+                )),
+            })
+            .collect(),
+        value => Err(format!(
+            "`{}` must be an array of strings, not {}",
+            key,
+            value.type_name()
+        )),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> ConfigError {
+        ConfigError {
+            line: self.line,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ConfigError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("expected `{}`, found `{}`", expected, c))),
+            None => Err(self.error(&format!("expected `{}`, found end of file", expected))),
+        }
+    }
+
+    /// Skip spaces, tabs and comments, and also newlines if `newlines` is set.
+    fn skip_whitespace_and_comments(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {
+                    self.next();
+                }
+                '\n' if newlines => {
+                    self.next();
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.next();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), ConfigError> {
+        self.skip_whitespace_and_comments(false);
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(self.error(&format!("expected end of line, found `{}`", c))),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ConfigError> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    self.next();
+                }
+                if self.pos == start {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(self.chars[start..self.pos].iter().collect())
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ConfigError> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some(c) if c.is_ascii_alphanumeric() || c == '+' || c == '-' => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || "+-_.".contains(c))
+                {
+                    self.next();
+                }
+                let word: String = self.chars[start..self.pos].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => match word.replace('_', "").parse() {
+                        Ok(n) if word.starts_with(|c: char| c.is_ascii_digit()) => {
+                            Ok(Value::Integer(n))
+                        }
+                        _ => Err(self.error(&format!("unsupported value `{}`", word))),
+                    },
+                }
+            }
+            Some(c) => Err(self.error(&format!("expected a value, found `{}`", c))),
+            None => Err(self.error("expected a value, found end of file")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ConfigError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments(true);
+            if self.peek() == Some(']') {
+                break;
+            }
+            values.push(self.value()?);
+            self.skip_whitespace_and_comments(true);
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                }
+                _ => break,
+            }
+        }
+        self.expect(']')?;
+        Ok(Value::Array(values))
+    }
+
+    fn literal_string(&mut self) -> Result<String, ConfigError> {
+        self.expect('\'')?;
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(value),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, ConfigError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(value),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => self.unicode_escape()?,
+                        Some(c) => return Err(self.error(&format!("invalid escape `\\{}`", c))),
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    value.push(escaped);
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, ConfigError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        match Config::parse(source) {
+            Ok(_) => panic!("{:?} parsed", source),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_every_key() {
+        let config = Config::parse(
+            r#"
+# Comments and blank lines are ignored.
+files = ["a.rs", 'b.rs']
+globs = ["*.rs"]
+excludes = [
+    "target/**", # trailing comma and comments inside arrays
+]
+command = ["rustfmt", "--edition", "2021"]
+jobs = 4
+no-working-tree = true
+index-only = false
+allow-unstaged = true
+color = "never"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.files.unwrap(), ["a.rs", "b.rs"]);
+        assert_eq!(config.globs.unwrap(), ["*.rs"]);
+        assert_eq!(config.excludes.unwrap(), ["target/**"]);
+        assert_eq!(config.command.unwrap(), ["rustfmt", "--edition", "2021"]);
+        assert_eq!(config.jobs.as_deref(), Some("4"));
+        assert_eq!(config.no_working_tree, Some(true));
+        assert_eq!(config.index_only, Some(false));
+        assert_eq!(config.allow_unstaged, Some(true));
+        assert_eq!(config.color.as_deref(), Some("never"));
+    }
+
+    #[test]
+    fn missing_keys_are_unset() {
+        let config = Config::parse("").unwrap();
+        assert!(config.files.is_none());
+        assert!(config.jobs.is_none());
+        assert!(config.index_only.is_none());
+    }
+
+    #[test]
+    fn jobs_can_be_a_string() {
+        let config = Config::parse("jobs = \"auto\"").unwrap();
+        assert_eq!(config.jobs.as_deref(), Some("auto"));
+    }
+
+    #[test]
+    fn escapes_in_basic_strings() {
+        let config = Config::parse(r#"command = ["a\"b", "\\", "\té"]"#).unwrap();
+        assert_eq!(config.command.unwrap(), ["a\"b", "\\", "\té"]);
+    }
+
+    #[test]
+    fn literal_strings_have_no_escapes() {
+        let config = Config::parse(r"files = ['a\n']").unwrap();
+        assert_eq!(config.files.unwrap(), ["a\\n"]);
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        assert_eq!(
+            error("files = []\n\nbogus = 1"),
+            "line 3: unknown key `bogus`"
+        );
+        assert_eq!(error("jobs = 1\njobs = 2"), "line 2: duplicate key `jobs`");
+    }
+
+    #[test]
+    fn wrong_types_are_errors() {
+        assert_eq!(
+            error("index-only = \"yes\""),
+            "line 1: `index-only` must be a boolean, not a string"
+        );
+        assert_eq!(
+            error("files = \"a.rs\""),
+            "line 1: `files` must be an array of strings, not a string"
+        );
+        assert_eq!(
+            error("command = [\"a\", 1]"),
+            "line 1: `command` must be an array of strings, but it contains an integer"
+        );
+        assert_eq!(
+            error("jobs = true"),
+            "line 1: `jobs` must be an integer or a string, not a boolean"
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error("[table]"), "line 1: tables are not supported");
+        assert_eq!(error("files"), "line 1: expected `=`, found end of file");
+        assert_eq!(error("color = \"never"), "line 1: unterminated string");
+        assert_eq!(error("jobs = -1"), "line 1: unsupported value `-1`");
+        assert_eq!(
+            error("color = \"a\" \"b\""),
+            "line 1: expected end of line, found `\"`"
+        );
+        assert_eq!(error(r#"color = "\q""#), r"line 1: invalid escape `\q`");
+    }
+}
@@ -0,0 +1,47 @@
+//! Text encodings, for `--encoding`.
+//!
+//! Files in an encoding other than UTF-8 are converted to UTF-8 for the formatter, and the
+//! formatted result is converted back before it's staged. A byte order mark is kept as
+//! the character U+FEFF, so it comes back out the same way it went in.
+
+use std::borrow::Cow;
+
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    #[value(name = "utf-8")]
+    Utf8,
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    #[value(name = "utf-16be")]
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Convert `bytes` in this encoding to UTF-8, or `None` if they aren't valid.
+    pub fn decode(self, bytes: &[u8]) -> Option<Cow<'_, [u8]>> {
+        let unit = match self {
+            Encoding::Utf8 => return Some(Cow::Borrowed(bytes)),
+            Encoding::Utf16Le => u16::from_le_bytes,
+            Encoding::Utf16Be => u16::from_be_bytes,
+        };
+        if bytes.len() % 2 != 0 {
+            return None;
+        }
+        let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+        let text: String = char::decode_utf16(units).collect::<Result<_, _>>().ok()?;
+        Some(Cow::Owned(text.into_bytes()))
+    }
+
+    /// Convert UTF-8 `bytes` to this encoding, or `None` if they aren't valid UTF-8.
+    pub fn encode(self, bytes: &[u8]) -> Option<Cow<'_, [u8]>> {
+        let unit: fn(u16) -> [u8; 2] = match self {
+            Encoding::Utf8 => return Some(Cow::Borrowed(bytes)),
+            Encoding::Utf16Le => u16::to_le_bytes,
+            Encoding::Utf16Be => u16::to_be_bytes,
+        };
+        let text = std::str::from_utf8(bytes).ok()?;
+        Some(Cow::Owned(text.encode_utf16().flat_map(unit).collect()))
+    }
+}
@@ -0,0 +1,94 @@
+//! Well-known file types, for `--type`.
+//!
+//! Each type is a set of glob patterns that match the names of files of that type,
+//! including files without an extension like `Dockerfile` and `Makefile`.
+
+use std::fmt::{self, Display};
+
+use crate::glob::Pattern;
+
+const TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cc", "*.cpp", "*.cxx", "*.hh", "*.hpp", "*.hxx"]),
+    ("css", &["*.css", "*.scss", "*.less"]),
+    (
+        "dockerfile",
+        &[
+            "Dockerfile",
+            "Dockerfile.*",
+            "*.dockerfile",
+            "Containerfile",
+        ],
+    ),
+    ("go", &["*.go"]),
+    ("haskell", &["*.hs", "*.lhs"]),
+    ("html", &["*.html", "*.htm"]),
+    ("js", &["*.js", "*.jsx", "*.mjs", "*.cjs"]),
+    ("json", &["*.json"]),
+    ("make", &["Makefile", "GNUmakefile", "makefile", "*.mk"]),
+    ("markdown", &["*.md", "*.markdown"]),
+    ("nix", &["*.nix"]),
+    ("python", &["*.py", "*.pyi"]),
+    ("ruby", &["*.rb", "Gemfile", "Rakefile"]),
+    ("rust", &["*.rs"]),
+    ("shell", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx", "*.mts", "*.cts"]),
+    ("yaml", &["*.yml", "*.yaml"]),
+];
+
+#[derive(Clone, Debug)]
+pub struct FileType {
+    name: String,
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug)]
+pub struct UnknownFileType {
+    name: String,
+}
+
+impl Display for UnknownFileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = TYPES.iter().map(|(name, _)| *name).collect();
+        write!(
+            f,
+            "unknown file type `{}` (known types: {})",
+            self.name,
+            names.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownFileType {}
+
+impl Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl FileType {
+    pub fn new(name: &str) -> Result<FileType, UnknownFileType> {
+        let (_, patterns) = TYPES
+            .iter()
+            .find(|(type_name, _)| *type_name == name)
+            .ok_or_else(|| UnknownFileType {
+                name: name.to_string(),
+            })?;
+        Ok(FileType {
+            name: name.to_string(),
+            patterns: patterns
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern).expect("internal error: invalid file type pattern")
+                })
+                .collect(),
+        })
+    }
+
+    /// Whether `path`, relative to the repository root, is a file of this type.
+    pub fn matches(&self, path: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.matches(path))
+    }
+}
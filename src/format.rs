@@ -0,0 +1,892 @@
+//! Formatting the staged files: in the working tree, in a temporary directory, through
+//! `--stdin`, or through a language server, and then staging the results.
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use git2::{ApplyLocation, DiffOptions, Error, ErrorCode, FileMode, Index, Repository};
+
+use crate::{
+    cache::Cache,
+    classify::{check_files_staged, expand_directories, has_attribute, TargetFile},
+    cli::OutputFormat,
+    commands::{changed_lines, exit_on_command_error, filter_commands, run_commands},
+    exit,
+    file_type::FileType,
+    lsp,
+    options::Options,
+    path_from_bytes, quote_path, read_file, repo_relative_path,
+    report::{dump_plan, print_diff_head, print_stat, Outcomes, Report, ReportedFile},
+    staging::{
+        build_formatted_tree, check_index_unchanged, conflicting_files, copy_index, decode_staged,
+        get_index_tree, index_fingerprint, reformatted_files, staged_entry, sync_file,
+        update_index,
+    },
+    verify::{verify_clean, verify_staged},
+    workdir::{
+        modified_times, modified_tracked_files, prepare_temp_dir, prepare_workdir, remove_temp_dir,
+        remove_temp_files, restore_modified_time, restore_workdir, temp_dir_paths, temp_file_paths,
+        warn_other_changes,
+    },
+    write_file, ExitCode,
+};
+
+/** Format `files`, like [`git_format_staged`], except that the ones in submodules are
+formatted in the submodules' own repositories, for `--recurse-submodules`.
+
+Each submodule is formatted from its root, with the paths of its files made relative to
+that, and then the current directory is changed back. The results from every repository
+are gathered into one [`Report`], with the submodules' paths reported as they would be in
+the superproject.
+*/
+pub(crate) fn format_with_submodules(
+    repo: &Repository,
+    repo_path: &Path,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    commands: &[&[String]],
+    options: &Options,
+) -> Result<Report, Error> {
+    let index = repo.index()?;
+    let submodules: Vec<PathBuf> = index
+        .iter()
+        .filter(|entry| entry.mode == u32::from(FileMode::Commit))
+        .map(|entry| path_from_bytes(&entry.path))
+        .collect();
+
+    let mut outer_files = Vec::new();
+    // Each submodule, its path relative to the current directory, and its files.
+    let mut submodule_files: Vec<(&Path, PathBuf, Vec<PathBuf>)> = Vec::new();
+    for file in files {
+        let path = repo_relative_path(dir_prefix, file);
+        let Some(submodule) = submodules
+            .iter()
+            .find(|submodule| path.starts_with(submodule))
+        else {
+            outer_files.push(file.clone());
+            continue;
+        };
+        let inner_path = path.strip_prefix(submodule).unwrap();
+        let inner_file = if inner_path.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            inner_path.to_path_buf()
+        };
+        match submodule_files
+            .iter_mut()
+            .find(|(other, _, _)| *other == submodule.as_path())
+        {
+            Some((_, _, files)) => files.push(inner_file),
+            None => {
+                let shown = file
+                    .ancestors()
+                    .nth(inner_path.components().count())
+                    .unwrap_or(file)
+                    .to_path_buf();
+                submodule_files.push((submodule, shown, vec![inner_file]));
+            }
+        }
+    }
+
+    let cwd = std::env::current_dir()
+        .map_err(|err| Error::from_str(&format!("couldn't find the current directory: {}", err)))?;
+    let mut report = Report::default();
+    for (submodule, shown, files) in &submodule_files {
+        let submodule_path = repo_path.join(submodule);
+        let submodule_repo = Repository::open(&submodule_path).map_err(|err| {
+            Error::from_str(&format!(
+                "couldn't open submodule {}, which may not be checked out: {}",
+                quote_path(&submodule),
+                err.message()
+            ))
+        })?;
+        let chdir = |dir: &Path| {
+            std::env::set_current_dir(dir).map_err(|err| {
+                Error::from_str(&format!("cannot change to {}: {}", quote_path(&dir), err))
+            })
+        };
+        chdir(&submodule_path)?;
+        let submodule_report = format_with_submodules(
+            &submodule_repo,
+            &submodule_path,
+            Path::new(""),
+            files,
+            commands,
+            options,
+        )?;
+        chdir(&cwd)?;
+        // The submodule's paths are relative to its root, which is `shown` from here.
+        report.append(submodule_report, |path| {
+            options
+                .report_path(dir_prefix, &repo_relative_path(shown, path))
+                .into_owned()
+        });
+    }
+
+    if outer_files.is_empty() && !submodule_files.is_empty() {
+        return Ok(report);
+    }
+    let outer_report =
+        git_format_staged(repo, repo_path, dir_prefix, &outer_files, commands, options)?;
+    report.append(outer_report, Path::to_path_buf);
+    Ok(report)
+}
+
+/** Format `files` with `format`, except that the files of each type in `type_commands`
+are formatted with that type's commands instead of `commands`, for `--type TYPE=COMMAND`.
+
+Each file goes to the first type in `type_commands` that it's of, and the files of each
+formatter are formatted together. Directories are expanded first, so
+that the files inside them are sorted by type too. The results are gathered into one
+[`Report`].
+*/
+pub(crate) fn format_by_type(
+    repo: &Repository,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    commands: &[&[String]],
+    type_commands: &[(&FileType, Vec<&[String]>)],
+    format: impl Fn(&[PathBuf], &[&[String]]) -> Result<Report, Error>,
+) -> Result<Report, Error> {
+    let files = expand_directories(&repo.index()?, dir_prefix, files);
+    // Each formatter, and the files it formats.
+    let mut groups: Vec<(&[&[String]], Vec<PathBuf>)> = Vec::new();
+    for file in files {
+        let path = repo_relative_path(dir_prefix, &file);
+        let path = path.to_string_lossy();
+        let commands = type_commands
+            .iter()
+            .find(|(file_type, _)| file_type.matches(&path))
+            .map_or(commands, |(_, commands)| commands.as_slice());
+        match groups.iter_mut().find(|(other, _)| *other == commands) {
+            Some((_, files)) => files.push(file),
+            None => groups.push((commands, vec![file])),
+        }
+    }
+
+    let mut report = Report::default();
+    for (commands, files) in groups {
+        report.append(format(&files, commands)?, Path::to_path_buf);
+    }
+    Ok(report)
+}
+
+/// Format the staged versions of `files`, and stage the results. What happened is
+/// returned to be reported by [`finish`](crate::report::finish).
+pub(crate) fn git_format_staged(
+    repo: &Repository,
+    repo_path: &Path,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    commands: &[&[String]],
+    options: &Options,
+) -> Result<Report, git2::Error> {
+    assert!(repo_path.is_absolute());
+    assert!(dir_prefix.is_relative());
+
+    // libgit2 can't read an index that `index.sparse` has made sparse. `git
+    // sparse-checkout` sets it in `config.worktree`, which libgit2 doesn't read either.
+    let index = repo.index().map_err(|err| {
+        let sparse_index = [
+            repo.config(),
+            git2::Config::open(&repo.path().join("config.worktree")),
+        ]
+        .into_iter()
+        .any(|config| {
+            config
+                .and_then(|config| config.get_bool("index.sparse"))
+                .unwrap_or(false)
+        });
+        if sparse_index {
+            Error::new(
+                err.code(),
+                err.class(),
+                "the index is a sparse index, which isn't supported; run `git sparse-checkout reapply --no-sparse-index` to expand it",
+            )
+        } else {
+            err
+        }
+    })?;
+    let mut files = expand_directories(&index, dir_prefix, files);
+    if !options.types.is_empty() {
+        files.retain(|file| {
+            let path = repo_relative_path(dir_prefix, file);
+            let path = path.to_string_lossy();
+            options
+                .types
+                .iter()
+                .any(|file_type| file_type.matches(&path))
+        });
+        if files.is_empty() {
+            trace!("none of the files are of the given --type");
+            return Ok(Report::default());
+        }
+    }
+    if !options.attr.is_empty() {
+        let mut matching = Vec::with_capacity(files.len());
+        for file in files {
+            let path = repo_relative_path(dir_prefix, &file);
+            if has_attribute(repo, &path, &options.attr)? {
+                matching.push(file);
+            }
+        }
+        files = matching;
+        if files.is_empty() {
+            trace!("none of the files have the given --attr");
+            return Ok(Report::default());
+        }
+    }
+    let files = &files;
+
+    let mut outcomes = Outcomes::default();
+    let mut to_format = check_files_staged(repo, dir_prefix, files, options, &mut outcomes)?;
+    // Everything from here on is decided from the index as it is now. If another process
+    // changes it in the meantime, the formatted files aren't staged over its changes.
+    let mut index_snapshot = index_fingerprint(&repo.index()?);
+    let mut cache = options.cache.then(|| Cache::open(repo, commands));
+    if cache.is_some() || !options.known_formatted.is_empty() {
+        let index = repo.index()?;
+        let mut unknown = Vec::with_capacity(to_format.len());
+        for target in to_format {
+            let file = target.path();
+            let id = staged_entry(&index, dir_prefix, file)?.id;
+            let known = options.known_formatted.contains(&id)
+                || cache
+                    .as_ref()
+                    .is_some_and(|cache| cache.contains(&repo_relative_path(dir_prefix, file), id));
+            if known {
+                outcomes
+                    .unchanged
+                    .push(options.report_path(dir_prefix, file).into_owned());
+            } else {
+                unknown.push(target);
+            }
+        }
+        to_format = unknown;
+    }
+    if to_format.is_empty() {
+        // Every file was skipped, or is known to be formatted, or there were none. The
+        // formatter isn't run without any files, because many would format everything.
+        return Ok(Report {
+            outcomes,
+            ..Report::default()
+        });
+    }
+    let files = &to_format
+        .iter()
+        .map(|target| target.path().to_path_buf())
+        .collect::<Vec<_>>();
+    for target in &to_format {
+        trace!(
+            "formatting {}{}",
+            quote_path(target.path()),
+            match target {
+                TargetFile::StagedOnly(_) => "",
+                TargetFile::UnstagedAndStaged(_) => ", which has unstaged changes",
+            }
+        );
+    }
+
+    // In `--index-only` and `--no-working-tree` mode the staged files are formatted in a
+    // temporary directory, instead of in the working tree. It's inside the repository so
+    // that formatters which search upward for their configuration still find the
+    // project's.
+    let temp_dir = if options.in_temp_dir() {
+        Some(repo.path().join("format-staged"))
+    } else {
+        None
+    };
+
+    // The paths that are passed to the formatting commands.
+    let format_paths: Vec<PathBuf> = match &temp_dir {
+        Some(temp_dir) => {
+            temp_dir_paths(temp_dir, dir_prefix, files, options.temp_suffix.as_deref())?
+        }
+        None => files.to_vec(),
+    };
+
+    if let Some(plan_path) = &options.dump_plan {
+        dump_plan(
+            plan_path,
+            repo_path,
+            commands,
+            &to_format,
+            &format_paths,
+            options,
+        );
+    }
+
+    let line_ranges = if options.only_changed_lines {
+        Some(changed_lines(repo, dir_prefix, files)?)
+    } else {
+        None
+    };
+
+    // Formatting in the working tree rewrites the files, even ones that end up
+    // unchanged. Their modification times are put back afterwards, so that build tools
+    // don't think they need rebuilding.
+    let modified = if temp_dir.is_none() && !options.in_memory() {
+        modified_times(files)
+    } else {
+        Vec::new()
+    };
+
+    let originals = match &temp_dir {
+        Some(temp_dir) => {
+            trace!("copying the staged files to {}", quote_path(temp_dir));
+            prepare_temp_dir(
+                repo,
+                &repo.index()?,
+                dir_prefix,
+                files,
+                temp_dir,
+                &format_paths,
+                options.encoding,
+            )?;
+            None
+        }
+        None if options.in_memory() => None,
+        None => {
+            trace!("writing the staged files over the working tree files, which are backed up");
+            Some(prepare_workdir(
+                repo,
+                dir_prefix,
+                files,
+                &options.staged_suffix,
+                options.encoding,
+            )?)
+        }
+    };
+
+    let cleanup = || match (&temp_dir, &originals) {
+        (Some(temp_dir), _) => remove_temp_dir(temp_dir),
+        (None, Some(originals)) => {
+            restore_workdir(files, originals, &options.staged_suffix);
+            for (file, modified) in files.iter().zip(&modified) {
+                restore_modified_time(file, *modified);
+            }
+        }
+        (None, None) => {}
+    };
+
+    // The formatters' stderr is only captured for the JSON summary.
+    let capture_stderr = options.format == OutputFormat::Json;
+
+    // In `--stdin` mode each file's stderr is captured separately. Otherwise the
+    // formatters are run on all the files at once, so their stderr is shared.
+    let mut file_stderr = Vec::new();
+    let mut shared_stderr = Vec::new();
+
+    let formatted = if options.lsp {
+        format_with_lsp(repo, repo_path, dir_prefix, files, commands[0], options)?
+    } else if options.stdin {
+        let index = repo.index()?;
+        let mut formatted = Vec::new();
+        for file in files {
+            let staged = repo.find_blob(staged_entry(&index, dir_prefix, file)?.id)?;
+            let path = options.stdin_path(repo_path, dir_prefix, file);
+            let mut content = decode_staged(options.encoding, file, staged.content())?.into_owned();
+            let mut stderr = Vec::new();
+            let mut stable = false;
+            for _ in 0..options.passes {
+                match filter_commands(
+                    commands,
+                    &path,
+                    &content,
+                    capture_stderr,
+                    options.filter_output(),
+                ) {
+                    Ok((new_content, new_stderr)) => {
+                        stable = new_content == content;
+                        content = new_content;
+                        stderr.extend(new_stderr);
+                    }
+                    Err(err) => {
+                        if options.summary_only {
+                            outcomes.fail_all(dir_prefix, files, &err, options);
+                            outcomes.print();
+                        }
+                        exit_on_command_error(err)
+                    }
+                }
+                if stable {
+                    break;
+                }
+            }
+            if !stable && options.passes > 1 {
+                warn_unstable(&options.report_path(dir_prefix, file), options.passes);
+            }
+            // An empty result is much more likely to be a mistake than the formatted
+            // file, so the file is left as it was.
+            if content.is_empty() && !staged.content().is_empty() {
+                if options.output_fifo.is_some() {
+                    warning!(
+                        "the formatter wrote nothing to {{output}} for {}, so it's left as it was",
+                        quote_path(&options.report_path(dir_prefix, file))
+                    );
+                } else {
+                    warning!(
+                        "the formatter wrote nothing to stdout for {}, so it's left as it was",
+                        quote_path(&options.report_path(dir_prefix, file))
+                    );
+                    hint!("if it writes its result to a file instead, use --formatter-stdin");
+                }
+                content = decode_staged(options.encoding, file, staged.content())?.into_owned();
+            }
+            formatted.push(content);
+            file_stderr.push(stderr);
+        }
+        formatted
+    } else {
+        // The files are only read before formatting when there's more than one pass,
+        // to tell when they've stopped changing.
+        let mut formatted: Vec<Vec<u8>> = if options.passes > 1 {
+            format_paths.iter().map(|path| read_file(path)).collect()
+        } else {
+            Vec::new()
+        };
+        let mut changing: Vec<bool> = vec![true; files.len()];
+        // A formatter that's given several files might change others too, like a
+        // project-wide formatter would. Those changes aren't staged, so they're pointed
+        // out afterwards. This is only for a warning, so it's skipped if the scan fails.
+        let modified_before = modified_tracked_files(repo, repo_path).ok();
+        for _ in 0..options.passes {
+            match run_commands(
+                commands,
+                &format_paths,
+                line_ranges.as_deref(),
+                capture_stderr,
+                options,
+            ) {
+                Ok(stderr) => shared_stderr.extend(stderr),
+                Err(err) => {
+                    cleanup();
+                    if options.summary_only {
+                        outcomes.fail_all(dir_prefix, files, &err, options);
+                        outcomes.print();
+                    }
+                    exit_on_command_error(err);
+                }
+            }
+
+            let new_formatted: Vec<Vec<u8>> =
+                format_paths.iter().map(|path| read_file(path)).collect();
+            if options.passes > 1 {
+                changing = formatted
+                    .iter()
+                    .zip(&new_formatted)
+                    .map(|(old, new)| old != new)
+                    .collect();
+            }
+            formatted = new_formatted;
+            if !changing.contains(&true) {
+                break;
+            }
+        }
+        if options.passes > 1 {
+            for (file, _) in files
+                .iter()
+                .zip(&changing)
+                .filter(|(_, changing)| **changing)
+            {
+                warn_unstable(&options.report_path(dir_prefix, file), options.passes);
+            }
+        }
+        if options.formatter_stdin {
+            // As in `--stdin` mode, a file that the formatter left empty is left as it was.
+            let keep_staged = |formatted: &mut [Vec<u8>]| -> Result<(), Error> {
+                let index = repo.index()?;
+                for (file, content) in files.iter().zip(formatted) {
+                    if !content.is_empty() {
+                        continue;
+                    }
+                    let staged = repo.find_blob(staged_entry(&index, dir_prefix, file)?.id)?;
+                    if staged.content().is_empty() {
+                        continue;
+                    }
+                    warning!(
+                        "the formatter left {} empty, so it's left as it was",
+                        quote_path(&options.report_path(dir_prefix, file))
+                    );
+                    *content =
+                        decode_staged(options.encoding, file, staged.content())?.into_owned();
+                }
+                Ok(())
+            };
+            if let Err(err) = keep_staged(&mut formatted) {
+                cleanup();
+                return Err(err);
+            }
+        }
+        if let Some(modified_before) = modified_before {
+            warn_other_changes(
+                repo,
+                repo_path,
+                dir_prefix,
+                files,
+                &modified_before,
+                options,
+            );
+        }
+        formatted
+    };
+
+    let formatted = if options.keep_line_endings {
+        let with_staged_line_endings = || {
+            let index = repo.index()?;
+            let mut normalized = Vec::with_capacity(files.len());
+            for (file, content) in files.iter().zip(formatted) {
+                let staged = repo.find_blob(staged_entry(&index, dir_prefix, file)?.id)?;
+                let staged = decode_staged(options.encoding, file, staged.content())?;
+                normalized.push(match_line_endings(&staged, content));
+            }
+            Ok(normalized)
+        };
+        match with_staged_line_endings() {
+            Ok(normalized) => normalized,
+            Err(err) => {
+                cleanup();
+                return Err(err);
+            }
+        }
+    } else {
+        formatted
+    };
+
+    let formatted: Vec<Vec<u8>> = files
+        .iter()
+        .zip(formatted)
+        .map(|(file, content)| match options.encoding.encode(&content) {
+            Some(encoded) => encoded.into_owned(),
+            None => {
+                error!(
+                    "the formatted {} isn't valid UTF-8, so it can't be converted back to the --encoding",
+                    quote_path(&options.report_path(dir_prefix, file))
+                );
+                cleanup();
+                exit(ExitCode::Error);
+            }
+        })
+        .collect();
+
+    trace!("building the tree of formatted files");
+    let index_tree = get_index_tree(repo)?;
+    let formatted_tree = build_formatted_tree(repo, &index_tree, dir_prefix, files, &formatted)?;
+    if let Some(cache) = &mut cache {
+        for file in files {
+            let path = repo_relative_path(dir_prefix, file);
+            let staged = index_tree.get_path(&path)?.id();
+            if staged == formatted_tree.get_path(&path)?.id() {
+                cache.insert(&path, staged);
+            }
+        }
+    }
+    let reformatted = reformatted_files(
+        repo,
+        &index_tree,
+        &formatted_tree,
+        dir_prefix,
+        files,
+        options.ignore_whitespace,
+    )?;
+
+    if let Some(originals) = &originals {
+        // The formatted files have been read into `formatted_tree`, so the working tree
+        // files can be restored to their original unstaged versions.
+        for (file, original) in files.iter().zip(originals) {
+            write_file(file, original);
+        }
+    }
+
+    if options.update_working_tree && temp_dir.is_none() {
+        if let Err(err) = check_index_unchanged(repo, index_snapshot) {
+            cleanup();
+            return Err(err);
+        }
+        trace!("applying the formatting changes to the working tree");
+        // Files that look binary have been skipped unless they were matched by
+        // `--treat-as-text`, so every change can be applied as text.
+        let diff = repo.diff_tree_to_tree(
+            Some(&index_tree),
+            Some(&formatted_tree),
+            Some(DiffOptions::new().context_lines(0).force_text(true)),
+        )?;
+        match repo.apply(&diff, ApplyLocation::WorkDir, None) {
+            Ok(()) => {}
+            // In `--stdin` and `--lsp` mode the working tree hasn't been touched, so the
+            // formatted files can still be staged.
+            Err(err) if options.in_memory() => warning!(
+                "couldn't apply the formatting changes to the working tree: {}",
+                err.message()
+            ),
+            // The formatter changed the staged lines too much to be merged with the
+            // unstaged changes, e.g. by reflowing the whole file. The working tree files
+            // have just been restored, so nothing has been changed yet.
+            Err(err) if err.code() == ErrorCode::ApplyFail => {
+                let conflicting =
+                    conflicting_files(repo, &index_tree, &formatted_tree, dir_prefix, files)?;
+                cleanup();
+                let conflicting: Vec<PathBuf> = conflicting
+                    .iter()
+                    .map(|file| options.report_path(dir_prefix, file).into_owned())
+                    .collect();
+                if options.summary_only {
+                    outcomes.failed.extend(conflicting.iter().map(|file| {
+                        format!("{} (conflicts with unstaged changes)", quote_path(file))
+                    }));
+                    outcomes.print();
+                }
+                error!("the formatting changes conflict with the unstaged changes in:");
+                for file in &conflicting {
+                    note!("  {}", quote_path(file));
+                }
+                hint!("format the working tree files yourself and stage them again, or stash the unstaged changes first");
+                exit(ExitCode::Error);
+            }
+            Err(err) => return Err(err),
+        }
+        // Applying the changes also updates the index's record of the working tree files.
+        index_snapshot = index_fingerprint(&repo.index()?);
+    }
+
+    // Only the files that the formatting changes were copied to keep their new
+    // modification times.
+    for (file, modified) in files.iter().zip(&modified) {
+        let path = repo_relative_path(dir_prefix, file);
+        if index_tree.get_path(&path)?.id() == formatted_tree.get_path(&path)?.id() {
+            restore_modified_time(file, *modified);
+        }
+    }
+
+    // Formatting has succeeded and the working tree is in its final state (with
+    // changes "backported" to the unstaged files, unless `--no-working-tree` or
+    // `--index-only` was given). The index can be safely updated.
+    if options.write_index {
+        if let Err(err) = check_index_unchanged(repo, index_snapshot) {
+            cleanup();
+            return Err(err);
+        }
+        let index = match &options.out_index {
+            Some(out_index) => repo.index().and_then(|index| copy_index(&index, out_index)),
+            None => repo.index(),
+        };
+        let mut index = match index {
+            Ok(index) => index,
+            Err(err) => {
+                cleanup();
+                return Err(err);
+            }
+        };
+        trace!(
+            "staging the formatted files in {}",
+            index.path().map_or(Cow::Borrowed("the index"), quote_path)
+        );
+        // When this returns the new index file is in place, so Git sees it as soon as
+        // we exit, e.g. when it carries on with the commit after a pre-commit hook.
+        if let Err(err) = update_index(&mut index, &formatted_tree, dir_prefix, files) {
+            // Nothing has been staged, so the working tree is put back as it was.
+            cleanup();
+            return Err(err);
+        }
+        if options.sync {
+            if let Some(path) = index.path() {
+                sync_file(path).unwrap_or_else(|err| {
+                    error!("failed to sync {}: {}", quote_path(&path), err);
+                    exit(ExitCode::Error);
+                });
+            }
+        }
+    }
+
+    // Files that were left unchanged are only remembered once everything else has
+    // succeeded.
+    if let Some(cache) = &cache {
+        if let Err(err) = cache.save() {
+            warning!("failed to save the cache: {}", err);
+        }
+    }
+
+    // This run has succeeded. The temporary files can all be safely removed.
+    match (&temp_dir, &originals) {
+        (Some(temp_dir), _) => remove_temp_dir(temp_dir),
+        (None, Some(_)) => remove_temp_files(
+            files
+                .iter()
+                .flat_map(|file| temp_file_paths(file, &options.staged_suffix)),
+        ),
+        (None, None) => {}
+    }
+
+    if options.verify_staged_unchanged && options.write_index {
+        verify_staged(repo, &index_tree, dir_prefix, files, &formatted, options)?;
+    }
+
+    if options.verify_clean {
+        let index = match &options.out_index {
+            Some(out_index) => Index::open(out_index)?,
+            None => repo.index()?,
+        };
+        verify_clean(
+            repo, &index, repo_path, dir_prefix, files, commands, options,
+        )?;
+    }
+
+    if options.stat {
+        print_stat(
+            repo,
+            &index_tree,
+            &formatted_tree,
+            dir_prefix,
+            files,
+            options,
+        )?;
+    }
+
+    if options.diff_head {
+        print_diff_head(repo)?;
+    }
+
+    Ok(Report {
+        files: files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| ReportedFile {
+                path: options.report_path(dir_prefix, file).into_owned(),
+                reformatted: reformatted.contains(&file),
+                stderr: file_stderr.get(i).cloned().unwrap_or_default(),
+            })
+            .collect(),
+        shared_stderr,
+        outcomes,
+    })
+}
+
+/** Convert every line ending in `formatted` to the kind used by most lines of `staged`,
+for [`Options::keep_line_endings`].
+
+A tie counts as LF. `formatted` is returned as it is if `staged` has no line endings.
+*/
+pub(crate) fn match_line_endings(staged: &[u8], formatted: Vec<u8>) -> Vec<u8> {
+    let lines = staged.iter().filter(|&&byte| byte == b'\n').count();
+    if lines == 0 {
+        return formatted;
+    }
+    let crlf_lines = staged.windows(2).filter(|pair| pair == b"\r\n").count();
+    let crlf = crlf_lines * 2 > lines;
+
+    let mut normalized = Vec::with_capacity(formatted.len());
+    for (i, &byte) in formatted.iter().enumerate() {
+        match byte {
+            // The `\n` that follows adds it back if it's wanted.
+            b'\r' if formatted.get(i + 1) == Some(&b'\n') => {}
+            b'\n' if crlf => normalized.extend_from_slice(b"\r\n"),
+            byte => normalized.push(byte),
+        }
+    }
+    normalized
+}
+
+/// Warn that formatting `file` didn't settle within `passes` passes.
+pub(crate) fn warn_unstable(file: &Path, passes: u32) {
+    warning!(
+        "{} was still changing after {} passes of formatting",
+        quote_path(file),
+        passes
+    );
+}
+
+/// Format the staged versions of `files` with the language server `command`.
+pub(crate) fn format_with_lsp(
+    repo: &Repository,
+    repo_path: &Path,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    command: &[String],
+    options: &Options,
+) -> Result<Vec<Vec<u8>>, Error> {
+    let mut server = lsp::Server::start(command, repo_path).unwrap_or_else(|err| {
+        error!("{}", err);
+        exit(lsp_exit_code(&err));
+    });
+
+    let index = repo.index()?;
+    let mut formatted = Vec::new();
+    for file in files {
+        let staged = repo.find_blob(staged_entry(&index, dir_prefix, file)?.id)?;
+        // The server is told about the file at its real location, so that it can find
+        // the project's configuration.
+        let path = repo_path.join(repo_relative_path(dir_prefix, file));
+        match server.format(
+            &path,
+            &decode_staged(options.encoding, file, staged.content())?,
+        ) {
+            Ok(content) => formatted.push(content),
+            Err(err) => {
+                error!(
+                    "couldn't format {}: {}",
+                    quote_path(&options.report_path(dir_prefix, file)),
+                    err
+                );
+                exit(lsp_exit_code(&err));
+            }
+        }
+    }
+
+    if let Err(err) = server.shutdown() {
+        warning!("{}", err);
+    }
+    Ok(formatted)
+}
+
+/// The exit code for a language server error.
+pub(crate) fn lsp_exit_code(err: &lsp::LspError) -> ExitCode {
+    if err.timed_out() {
+        ExitCode::TimedOut
+    } else {
+        ExitCode::Error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matched(staged: &[u8], formatted: &[u8]) -> Vec<u8> {
+        match_line_endings(staged, formatted.to_vec())
+    }
+
+    #[test]
+    fn match_line_endings_converts_to_crlf() {
+        assert_eq!(matched(b"a\r\nb\r\n", b"a\nb\r\nc\n"), b"a\r\nb\r\nc\r\n");
+    }
+
+    #[test]
+    fn match_line_endings_converts_to_lf() {
+        assert_eq!(matched(b"a\nb\n", b"a\r\nb\nc\r\n"), b"a\nb\nc\n");
+    }
+
+    #[test]
+    fn match_line_endings_follows_most_lines() {
+        assert_eq!(matched(b"a\r\nb\r\nc\n", b"a\nb\n"), b"a\r\nb\r\n");
+        assert_eq!(matched(b"a\r\nb\nc\n", b"a\r\nb\r\n"), b"a\nb\n");
+    }
+
+    #[test]
+    fn match_line_endings_tie_counts_as_lf() {
+        assert_eq!(matched(b"a\r\nb\n", b"a\r\nb\r\n"), b"a\nb\n");
+    }
+
+    #[test]
+    fn match_line_endings_keeps_formatted_without_staged_line_endings() {
+        assert_eq!(matched(b"a", b"a\r\nb\n"), b"a\r\nb\n");
+        assert_eq!(matched(b"", b"a\r\n"), b"a\r\n");
+    }
+
+    #[test]
+    fn match_line_endings_keeps_lone_cr() {
+        assert_eq!(matched(b"a\n", b"a\rb\r\n"), b"a\rb\n");
+        assert_eq!(matched(b"a\r\n", b"a\rb\n"), b"a\rb\r\n");
+    }
+}
@@ -0,0 +1,311 @@
+//! Shell-style glob patterns for matching paths in the repository.
+//!
+//! * `*` matches any run of characters except `/`.
+//! * `**` matches any run of characters, including `/`, and `**/` matches any number of
+//!   whole directories.
+//! * `?` matches any single character except `/`.
+//! * `[abc]`, `[a-z]` and `[!abc]` match a single character from (or not from) a set.
+//! * `\` makes the following character match literally.
+//!
+//! A pattern without a `/` is matched against the file name only, so `*.rs` matches
+//! `src/main.rs`. Other patterns are matched against the whole path, relative to the
+//! repository root.
+
+use std::fmt::{self, Display};
+
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    source: String,
+    tokens: Vec<Token>,
+    basename_only: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyString,
+    AnyPath,
+    AnyDirs,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+#[derive(Debug)]
+pub struct PatternError {
+    pattern: String,
+    position: usize,
+    message: &'static str,
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid pattern `{}`: {} at position {}",
+            self.pattern, self.message, self.position
+        )
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Pattern, PatternError> {
+        let error = |position, message| PatternError {
+            pattern: source.to_string(),
+            position,
+            message,
+        };
+
+        // It could only match an empty path, so it's surely a mistake.
+        if source.is_empty() {
+            return Err(error(0, "empty pattern"));
+        }
+        // Patterns are only matched against files, whose paths never end in `/`.
+        if source.ends_with('/') {
+            return Err(error(source.chars().count() - 1, "trailing `/`"));
+        }
+
+        let chars: Vec<char> = source.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    if chars.get(i + 2) == Some(&'/') {
+                        tokens.push(Token::AnyDirs);
+                        i += 3;
+                    } else {
+                        tokens.push(Token::AnyPath);
+                        i += 2;
+                    }
+                }
+                '*' => {
+                    tokens.push(Token::AnyString);
+                    i += 1;
+                }
+                '?' => {
+                    tokens.push(Token::AnyChar);
+                    i += 1;
+                }
+                '\\' => match chars.get(i + 1) {
+                    Some(&c) => {
+                        tokens.push(Token::Char(c));
+                        i += 2;
+                    }
+                    None => return Err(error(i, "trailing `\\`")),
+                },
+                '[' => {
+                    let start = i;
+                    i += 1;
+                    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+                    if negated {
+                        i += 1;
+                    }
+
+                    let mut ranges = Vec::new();
+                    loop {
+                        let c = match chars.get(i) {
+                            // `]` is taken literally when it's the first character in the set.
+                            Some(']') if !ranges.is_empty() => break,
+                            Some('\\') => {
+                                i += 1;
+                                *chars.get(i).ok_or_else(|| error(start, "unclosed `[`"))?
+                            }
+                            Some(&c) => c,
+                            None => return Err(error(start, "unclosed `[`")),
+                        };
+                        i += 1;
+
+                        if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|&c| c != ']')
+                        {
+                            let end = chars[i + 1];
+                            if end < c {
+                                return Err(error(i, "invalid character range"));
+                            }
+                            ranges.push((c, end));
+                            i += 2;
+                        } else {
+                            ranges.push((c, c));
+                        }
+                    }
+                    i += 1;
+
+                    tokens.push(Token::Class { negated, ranges });
+                }
+                c => {
+                    tokens.push(Token::Char(c));
+                    i += 1;
+                }
+            }
+        }
+
+        Ok(Pattern {
+            source: source.to_string(),
+            tokens,
+            basename_only: !source.contains('/'),
+        })
+    }
+
+    /// Whether `path`, relative to the repository root, matches the pattern.
+    pub fn matches(&self, path: &str) -> bool {
+        let path = if self.basename_only {
+            path.rsplit('/').next().unwrap_or(path)
+        } else {
+            path
+        };
+        let path: Vec<char> = path.chars().collect();
+        matches_tokens(&self.tokens, &path)
+    }
+}
+
+fn matches_tokens(tokens: &[Token], path: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return path.is_empty();
+    };
+
+    match token {
+        Token::Char(c) => path.first() == Some(c) && matches_tokens(rest, &path[1..]),
+        Token::AnyChar => {
+            path.first().is_some_and(|&c| c != '/') && matches_tokens(rest, &path[1..])
+        }
+        Token::AnyString => {
+            let max = path.iter().position(|&c| c == '/').unwrap_or(path.len());
+            (0..=max).any(|n| matches_tokens(rest, &path[n..]))
+        }
+        Token::AnyPath => (0..=path.len()).any(|n| matches_tokens(rest, &path[n..])),
+        Token::AnyDirs => (0..=path.len())
+            .filter(|&n| n == 0 || path[n - 1] == '/')
+            .any(|n| matches_tokens(rest, &path[n..])),
+        Token::Class { negated, ranges } => path.first().is_some_and(|&c| {
+            c != '/'
+                && ranges.iter().any(|&(start, end)| start <= c && c <= end) != *negated
+                && matches_tokens(rest, &path[1..])
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Pattern::new(pattern).unwrap().matches(path)
+    }
+
+    #[test]
+    fn star_stays_in_one_directory() {
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "src/bin/main.rs"));
+        assert!(!matches("src/*", "src"));
+    }
+
+    #[test]
+    fn pattern_without_slash_matches_file_name() {
+        assert!(matches("*.rs", "src/bin/main.rs"));
+        assert!(matches("Makefile", "docs/Makefile"));
+        assert!(!matches("src", "src/main.rs"));
+    }
+
+    #[test]
+    fn any_dirs_at_start_matches_any_directory() {
+        assert!(matches("**/main.rs", "main.rs"));
+        assert!(matches("**/main.rs", "src/bin/main.rs"));
+        assert!(!matches("**/main.rs", "src/mymain.rs"));
+    }
+
+    #[test]
+    fn any_path_at_end_matches_everything_inside() {
+        assert!(matches("docs/**", "docs/a.md"));
+        assert!(matches("docs/**", "docs/a/b.md"));
+        assert!(!matches("docs/**", "src/docs/a.md"));
+    }
+
+    #[test]
+    fn any_dirs_at_end_is_a_trailing_slash() {
+        let err = Pattern::new("docs/**/").unwrap_err();
+        assert_eq!(err.message, "trailing `/`");
+        assert_eq!(err.position, 7);
+    }
+
+    #[test]
+    fn any_dirs_in_the_middle_matches_no_directories_too() {
+        assert!(matches("a/**/b.txt", "a/b.txt"));
+        assert!(matches("a/**/b.txt", "a/x/y/b.txt"));
+        assert!(!matches("a/**/b.txt", "a/xb.txt"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(!matches("a/?/c", "a///c"));
+    }
+
+    #[test]
+    fn classes_match_sets_and_ranges() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(matches("[a-c].txt", "b.txt"));
+        assert!(!matches("[a-c].txt", "d.txt"));
+        assert!(matches("[!a-c].txt", "d.txt"));
+        assert!(matches("[^a-c].txt", "d.txt"));
+        assert!(!matches("[!a-c].txt", "a.txt"));
+        assert!(matches("[a-].txt", "-.txt"));
+    }
+
+    #[test]
+    fn close_bracket_first_in_class_is_literal() {
+        assert!(matches("[]]", "]"));
+        assert!(!matches("[]]", "a"));
+        assert!(matches("[!]]", "a"));
+        assert!(!matches("[!]]", "]"));
+        assert!(matches("[]a]", "a"));
+    }
+
+    #[test]
+    fn classes_never_match_slash() {
+        assert!(!matches("a[!x]b", "a/b"));
+    }
+
+    #[test]
+    fn backslash_escapes_special_characters() {
+        assert!(matches(r"\*.txt", "*.txt"));
+        assert!(!matches(r"\*.txt", "a.txt"));
+        assert!(matches(r"[\]]", "]"));
+    }
+
+    #[test]
+    fn trailing_backslash_is_an_error() {
+        let err = Pattern::new(r"a\").unwrap_err();
+        assert_eq!(err.message, "trailing `\\`");
+        assert_eq!(err.position, 1);
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors() {
+        assert_eq!(Pattern::new("").unwrap_err().message, "empty pattern");
+        assert_eq!(Pattern::new("[ab").unwrap_err().message, "unclosed `[`");
+        assert_eq!(Pattern::new("[a\\").unwrap_err().message, "unclosed `[`");
+        assert_eq!(
+            Pattern::new("[z-a]").unwrap_err().message,
+            "invalid character range"
+        );
+    }
+
+    #[test]
+    fn errors_show_the_pattern_and_position() {
+        assert_eq!(
+            Pattern::new("[ab").unwrap_err().to_string(),
+            "invalid pattern `[ab`: unclosed `[` at position 0"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+/*! `git-format-staged`, which formats the staged versions of files and stages the results.
+
+The program itself is [`run()`]. [`classify()`] is public too, for deciding how a single file
+would be formatted.
+*/
+
+#[macro_use]
+mod output;
+
+mod cache;
+mod config;
+mod encoding;
+mod file_type;
+mod glob;
+mod lsp;
+
+mod classify;
+mod cli;
+mod commands;
+mod format;
+mod options;
+mod report;
+mod run;
+mod staging;
+mod verify;
+mod workdir;
+
+pub use classify::{classify, FormatStagedError, TargetFile};
+pub use run::run;
+
+use std::{
+    borrow::Cow,
+    ffi::OsStr,
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// An environment variable that's set for every command run by git-format-staged, so
+/// that a formatter which runs git-format-staged again is caught instead of recursing.
+const RUNNING_VAR: &str = "GIT_FORMAT_STAGED_RUNNING";
+
+/// An environment variable that turns on tracing of each step of the run, along with
+/// `--print-command`, like `GIT_TRACE` does for Git. It's for debugging a hook without
+/// having to change its flags.
+const TRACE_VAR: &str = "GIT_FORMAT_STAGED_TRACE";
+
+/// An environment variable that keeps the temporary files instead of removing them, for
+/// debugging.
+const KEEP_TEMP_VAR: &str = "GIT_FORMAT_STAGED_KEEP_TEMP";
+
+/// Whether the temporary files are kept. See [`KEEP_TEMP_VAR`].
+static KEEP_TEMP: AtomicBool = AtomicBool::new(false);
+
+/// Whether the environment variable `name` is set to something other than ``, `0` or
+/// `false`.
+fn env_flag(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| {
+        !value.is_empty() && value != "0" && !value.eq_ignore_ascii_case("false")
+    })
+}
+
+/// `path`, relative to the repository root, as a path relative to the current directory.
+fn cwd_relative_path(dir_prefix: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(dir_prefix) {
+        Ok(path) if !dir_prefix.as_os_str().is_empty() => path.to_path_buf(),
+        _ => dir_prefix
+            .components()
+            .map(|_| Component::ParentDir)
+            .collect::<PathBuf>()
+            .join(path),
+    }
+}
+
+/// Encode a string as a JSON string literal.
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/** The path of `file`, which is relative to the current directory, relative to the
+repository root.
+
+`dir_prefix` is the current directory relative to the repository root. `.` and `..`
+components are resolved, so that the result can be looked up in the index.
+*/
+fn repo_relative_path(dir_prefix: &Path, file: &Path) -> PathBuf {
+    let mut path = dir_prefix.to_path_buf();
+    for component in file.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !path.pop() {
+                    path.push(component);
+                }
+            }
+            component => path.push(component),
+        }
+    }
+    path
+}
+
+fn read_file(path: &Path) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|err| {
+        error!("failed to read {}: {}", quote_path(path), err);
+        exit(ExitCode::Error);
+    })
+}
+
+fn write_file(path: &Path, content: &[u8]) {
+    std::fs::write(path, content).unwrap_or_else(|err| {
+        error!("failed to write {}: {}", quote_path(path), err);
+        exit(ExitCode::Error);
+    })
+}
+
+/** Rename `from` to `to`, like [`std::fs::rename`], or copy it and remove the original
+if they're on different filesystems, which happens with bind mounts.
+
+The copy isn't atomic the way a rename is, but it's the best that can be done there.
+*/
+fn rename_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    move_file(from, to, |from, to| std::fs::rename(from, to))
+}
+
+/// [`rename_file`], with `rename` in place of [`std::fs::rename`].
+fn move_file(
+    from: &Path,
+    to: &Path,
+    rename: impl FnOnce(&Path, &Path) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    match rename(from, to) {
+        Err(err) if err.raw_os_error() == Some(CROSS_DEVICE) => {
+            trace!(
+                "{} and {} are on different filesystems, so copying instead of renaming",
+                quote_path(from),
+                quote_path(to)
+            );
+            std::fs::copy(from, to)?;
+            std::fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// The error for a rename across filesystems: `EXDEV`, or `ERROR_NOT_SAME_DEVICE` on
+/// Windows.
+#[cfg(not(windows))]
+const CROSS_DEVICE: i32 = 18;
+#[cfg(windows)]
+const CROSS_DEVICE: i32 = 17;
+
+/// A path from the index, a diff or a status list, which Git stores as bytes.
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        PathBuf::from(OsStr::from_bytes(bytes))
+    }
+
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// The bytes of `path`, as Git would store them. See [`path_from_bytes`].
+fn path_bytes(path: &OsStr) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        Cow::Borrowed(path.as_bytes())
+    }
+
+    #[cfg(not(unix))]
+    {
+        match path.to_string_lossy() {
+            Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+            Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+        }
+    }
+}
+
+/// Whether to exit successfully even after an error. See [`Cli::exit_zero`](crate::cli::Cli::exit_zero).
+static EXIT_ZERO: AtomicBool = AtomicBool::new(false);
+
+/// Exit with `code`, or successfully if `--exit-zero` was given.
+fn exit(code: impl Into<i32>) -> ! {
+    std::process::exit(if EXIT_ZERO.load(Ordering::Relaxed) {
+        0
+    } else {
+        code.into()
+    })
+}
+
+/** The exit codes other than 0, for success, which scripts can rely on.
+
+When a formatter fails, git-format-staged exits with the formatter's own exit code
+instead, so these are also documented in the README.
+*/
+#[derive(Clone, Copy)]
+enum ExitCode {
+    /// Any error that doesn't have a code of its own.
+    Error = 1,
+
+    /// The options don't make sense together. Clap also exits with this code when it
+    /// can't parse the command line.
+    Usage = 2,
+
+    /// The current directory isn't in a Git repository.
+    NotARepository = 3,
+
+    /// One of the files can't be formatted because it isn't staged.
+    NotStaged = 4,
+
+    /// There are no files to format.
+    NoFiles = 5,
+
+    /// The `--deadline` passed, the same as `timeout(1)`'s code.
+    TimedOut = 124,
+
+    /// A formatter couldn't be found, the same as a shell's code.
+    FormatterNotFound = 127,
+}
+
+impl From<ExitCode> for i32 {
+    fn from(code: ExitCode) -> i32 {
+        code as i32
+    }
+}
+
+/** Quote a path for display, the same way Git does when `core.quotePath` is enabled.
+
+Paths containing control characters, `"`, `\` or non-ASCII bytes are wrapped in double
+quotes, and those bytes are escaped. This shows the bytes of a path that isn't valid
+UTF-8 as they are, as Git does.
+*/
+fn quote_path<P: AsRef<Path> + ?Sized>(path: &P) -> Cow<'_, str> {
+    fn needs_quoting(byte: u8) -> bool {
+        byte < 0x20 || byte == b'"' || byte == b'\\' || byte >= 0x7f
+    }
+
+    let path = path.as_ref().as_os_str();
+    let bytes = path_bytes(path);
+    if !bytes.iter().copied().any(needs_quoting) {
+        // Plain ASCII, so it's also valid UTF-8.
+        return path.to_string_lossy();
+    }
+
+    let mut quoted = String::from("\"");
+    for &byte in bytes.iter() {
+        match byte {
+            b'\x07' => quoted.push_str("\\a"),
+            b'\x08' => quoted.push_str("\\b"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            b'\x0b' => quoted.push_str("\\v"),
+            b'\x0c' => quoted.push_str("\\f"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            byte if needs_quoting(byte) => quoted.push_str(&format!("\\{:03o}", byte)),
+            byte => quoted.push(byte as char),
+        }
+    }
+    quoted.push('"');
+
+    Cow::Owned(quoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for a test, named `name`.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join("git-format-staged-unit-tests")
+            .join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rename_file_renames() {
+        let dir = temp_dir("rename");
+        std::fs::write(dir.join("from"), "content").unwrap();
+        std::fs::write(dir.join("to"), "old").unwrap();
+
+        rename_file(&dir.join("from"), &dir.join("to")).unwrap();
+
+        assert!(!dir.join("from").exists());
+        assert_eq!(std::fs::read_to_string(dir.join("to")).unwrap(), "content");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn move_file_copies_across_filesystems() {
+        let dir = temp_dir("rename-cross-device");
+        std::fs::write(dir.join("from"), "content").unwrap();
+        std::fs::write(dir.join("to"), "old").unwrap();
+
+        move_file(&dir.join("from"), &dir.join("to"), |_, _| {
+            Err(std::io::Error::from_raw_os_error(CROSS_DEVICE))
+        })
+        .unwrap();
+
+        assert!(!dir.join("from").exists());
+        assert_eq!(std::fs::read_to_string(dir.join("to")).unwrap(), "content");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn move_file_passes_other_errors_on() {
+        let dir = temp_dir("rename-other-error");
+        std::fs::write(dir.join("from"), "content").unwrap();
+
+        let err = move_file(&dir.join("from"), &dir.join("to"), |_, _| {
+            Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+        })
+        .unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(dir.join("from").exists());
+        assert!(!dir.join("to").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn quote_path_leaves_plain_paths_alone() {
+        assert_eq!(quote_path("src/main.rs"), "src/main.rs");
+        assert_eq!(quote_path("a b/c's (1).txt"), "a b/c's (1).txt");
+    }
+
+    #[test]
+    fn quote_path_escapes_control_characters() {
+        assert_eq!(quote_path("a\nb"), r#""a\nb""#);
+        assert_eq!(quote_path("a\tb\rc"), r#""a\tb\rc""#);
+        assert_eq!(quote_path("\x07\x08\x0b\x0c"), r#""\a\b\v\f""#);
+        assert_eq!(quote_path("\x01\x7f"), r#""\001\177""#);
+    }
+
+    #[test]
+    fn quote_path_escapes_quotes_and_backslashes() {
+        assert_eq!(quote_path("say \"hi\".txt"), r#""say \"hi\".txt""#);
+        assert_eq!(quote_path("a\\b"), r#""a\\b""#);
+    }
+
+    #[test]
+    fn quote_path_escapes_non_ascii_bytes() {
+        assert_eq!(quote_path("caf\u{e9}"), r#""caf\303\251""#);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quote_path_shows_invalid_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(quote_path(OsStr::from_bytes(b"a\xffb")), r#""a\377b""#);
+    }
+}
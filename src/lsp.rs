@@ -0,0 +1,605 @@
+/*! Formatting through a language server, for `--lsp`.
+
+The server is started once and spoken to over stdio. Each file is opened with its staged
+content as the document text, formatted with a `textDocument/formatting` request, and
+closed again. The returned edits are applied to the staged content to get the formatted
+file. Only whole-document formatting is supported.
+*/
+
+use std::{
+    fmt::{self, Display},
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use crate::json_string;
+
+#[derive(Debug)]
+pub struct LspError {
+    message: String,
+    timed_out: bool,
+}
+
+impl LspError {
+    /// Whether the server was stopped because the `--deadline` passed.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+}
+
+impl Display for LspError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<std::io::Error> for LspError {
+    fn from(err: std::io::Error) -> LspError {
+        error(format!("couldn't talk to the language server: {}", err))
+    }
+}
+
+fn error(message: impl Into<String>) -> LspError {
+    LspError {
+        message: message.into(),
+        timed_out: false,
+    }
+}
+
+fn exited() -> LspError {
+    error("the language server exited unexpectedly")
+}
+
+/** A running language server.
+
+Its messages are read by a thread, so that waiting for one can give up when the
+`--deadline` passes.
+*/
+pub struct Server {
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Result<Value, LspError>>,
+    next_id: u64,
+}
+
+impl Server {
+    /// Start the server `command`, and initialize it for the workspace at `root`.
+    pub fn start(command: &[String], root: &Path) -> Result<Server, LspError> {
+        let (env, command) = crate::commands::split_env(command);
+        let (program, args) = command
+            .split_first()
+            .expect("internal error: empty language server command");
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .envs(env)
+            .env(crate::RUNNING_VAR, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        crate::commands::set_process_group(&mut command);
+        let mut child = command
+            .spawn()
+            .map_err(|err| error(format!("couldn't start {}: {}", program, err)))?;
+
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let (sender, messages) = mpsc::channel();
+        std::thread::spawn(move || loop {
+            let message = receive(&mut stdout);
+            let failed = message.is_err();
+            if sender.send(message).is_err() || failed {
+                break;
+            }
+        });
+
+        let mut server = Server {
+            stdin: child.stdin.take().unwrap(),
+            messages,
+            child,
+            next_id: 1,
+        };
+
+        let root_uri = json_string(&file_uri(root));
+        server.request(
+            "initialize",
+            &format!(
+                "{{\"processId\": {}, \"rootUri\": {}, \"workspaceFolders\": [{{\"uri\": {}, \"name\": \"root\"}}], \"capabilities\": {{}}}}",
+                std::process::id(),
+                root_uri,
+                root_uri
+            ),
+        )?;
+        server.notify("initialized", "{}")?;
+        Ok(server)
+    }
+
+    /// Format `content`, the text of the file at `path`.
+    pub fn format(&mut self, path: &Path, content: &[u8]) -> Result<Vec<u8>, LspError> {
+        let text = std::str::from_utf8(content).map_err(|_| {
+            error("the file isn't valid UTF-8, so it can't be sent to a language server")
+        })?;
+        let uri = json_string(&file_uri(path));
+
+        self.notify(
+            "textDocument/didOpen",
+            &format!(
+                "{{\"textDocument\": {{\"uri\": {}, \"languageId\": {}, \"version\": 1, \"text\": {}}}}}",
+                uri,
+                json_string(language_id(path)),
+                json_string(text)
+            ),
+        )?;
+        let result = self.request(
+            "textDocument/formatting",
+            &format!(
+                "{{\"textDocument\": {{\"uri\": {}}}, \"options\": {{\"tabSize\": 4, \"insertSpaces\": true}}}}",
+                uri
+            ),
+        );
+        self.notify(
+            "textDocument/didClose",
+            &format!("{{\"textDocument\": {{\"uri\": {}}}}}", uri),
+        )?;
+
+        let edits = match result? {
+            Value::Null => return Ok(content.to_vec()),
+            Value::Array(edits) => edits
+                .iter()
+                .map(TextEdit::from_json)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| error("the language server sent an invalid edit"))?,
+            _ => {
+                return Err(error(
+                    "the language server sent an invalid formatting result",
+                ))
+            }
+        };
+        Ok(apply_edits(text, edits).into_bytes())
+    }
+
+    /// Ask the server to shut down, and wait for it to exit.
+    pub fn shutdown(mut self) -> Result<(), LspError> {
+        self.request("shutdown", "null")?;
+        self.notify("exit", "null")?;
+        if let Some(&deadline) = crate::commands::DEADLINE.get() {
+            while self.child.try_wait()?.is_none() {
+                if Instant::now() >= deadline {
+                    return Err(self.timed_out());
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        self.child.wait()?;
+        Ok(())
+    }
+
+    /// Stop the server, because the `--deadline` has passed.
+    fn timed_out(&mut self) -> LspError {
+        crate::commands::stop(&mut self.child);
+        LspError {
+            message: "the language server was still running when the --deadline passed".to_string(),
+            timed_out: true,
+        }
+    }
+
+    fn send(&mut self, message: &str) -> Result<(), LspError> {
+        write!(
+            self.stdin,
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        )?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    fn notify(&mut self, method: &str, params: &str) -> Result<(), LspError> {
+        self.send(&format!(
+            "{{\"jsonrpc\": \"2.0\", \"method\": {}, \"params\": {}}}",
+            json_string(method),
+            params
+        ))
+    }
+
+    /// Send a request, and wait for its result.
+    fn request(&mut self, method: &str, params: &str) -> Result<Value, LspError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&format!(
+            "{{\"jsonrpc\": \"2.0\", \"id\": {}, \"method\": {}, \"params\": {}}}",
+            id,
+            json_string(method),
+            params
+        ))?;
+
+        loop {
+            let message = self.receive()?;
+            match (message.get("id"), message.get("method")) {
+                // A request from the server, such as `workspace/configuration`. None of
+                // them are supported, but they have to be answered.
+                (Some(request_id), Some(_)) => {
+                    let request_id = request_id.to_json();
+                    self.send(&format!(
+                        "{{\"jsonrpc\": \"2.0\", \"id\": {}, \"result\": null}}",
+                        request_id
+                    ))?;
+                }
+                (Some(Value::Number(response_id)), None) if *response_id == id as f64 => {
+                    if let Some(err) = message.get("error") {
+                        let message = match err.get("message") {
+                            Some(Value::String(message)) => message.as_str(),
+                            _ => "unknown error",
+                        };
+                        return Err(error(format!(
+                            "the language server couldn't handle `{}`: {}",
+                            method, message
+                        )));
+                    }
+                    return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+                }
+                // Notifications, and responses to other requests.
+                _ => {}
+            }
+        }
+    }
+
+    /// Wait for the next message from the server.
+    fn receive(&mut self) -> Result<Value, LspError> {
+        let Some(&deadline) = crate::commands::DEADLINE.get() else {
+            return self.messages.recv().unwrap_or_else(|_| Err(exited()));
+        };
+        match self
+            .messages
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            Ok(message) => message,
+            Err(RecvTimeoutError::Timeout) => Err(self.timed_out()),
+            Err(RecvTimeoutError::Disconnected) => Err(exited()),
+        }
+    }
+}
+
+/// Read a message from the server's stdout.
+fn receive(stdout: &mut BufReader<ChildStdout>) -> Result<Value, LspError> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if stdout.read_line(&mut line)? == 0 {
+            return Err(exited());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let content_length = content_length
+        .ok_or_else(|| error("the language server sent a message without a length"))?;
+    let mut content = vec![0; content_length];
+    stdout.read_exact(&mut content)?;
+    let content = String::from_utf8(content)
+        .map_err(|_| error("the language server sent a message that isn't UTF-8"))?;
+    parse(&content).ok_or_else(|| error("the language server sent invalid JSON"))
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        // Don't leave the server running if formatting stopped early.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The `file://` URI for the absolute path `path`.
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in crate::path_bytes(path.as_os_str()).iter() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// The LSP language identifier for a file, guessed from its extension.
+fn language_id(path: &Path) -> &str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+    match extension {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "javascriptreact",
+        "ts" => "typescript",
+        "tsx" => "typescriptreact",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "hs" => "haskell",
+        "md" => "markdown",
+        "nix" => "nix",
+        "sh" => "shellscript",
+        "yml" => "yaml",
+        extension => extension,
+    }
+}
+
+struct TextEdit {
+    start: (usize, usize),
+    end: (usize, usize),
+    new_text: String,
+}
+
+impl TextEdit {
+    fn from_json(value: &Value) -> Option<TextEdit> {
+        let position = |value: &Value| {
+            let line = value.get("line")?.as_usize()?;
+            let character = value.get("character")?.as_usize()?;
+            Some((line, character))
+        };
+        let range = value.get("range")?;
+        Some(TextEdit {
+            start: position(range.get("start")?)?,
+            end: position(range.get("end")?)?,
+            new_text: match value.get("newText")? {
+                Value::String(new_text) => new_text.clone(),
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// Apply `edits`, which don't overlap, to `text`.
+fn apply_edits(text: &str, mut edits: Vec<TextEdit>) -> String {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+
+    // Positions count UTF-16 code units within a line.
+    let offset = |(line, character): (usize, usize)| -> usize {
+        let Some(&start) = line_starts.get(line) else {
+            return text.len();
+        };
+        let line_text = text[start..].split('\n').next().unwrap_or("");
+        let mut units = 0;
+        for (i, c) in line_text.char_indices() {
+            if units >= character {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        start + line_text.len()
+    };
+
+    // Applying the edits from the end keeps the earlier offsets valid. The sort is
+    // stable, so edits that insert at the same position keep their order.
+    edits.sort_by_key(|edit| edit.start);
+    let mut text = text.to_string();
+    for edit in edits.iter().rev() {
+        let start = offset(edit.start);
+        let end = offset(edit.end).max(start);
+        text.replace_range(start..end, &edit.new_text);
+    }
+    text
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_usize(&self) -> Option<usize> {
+        match self {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            Value::Null => "null".to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::String(s) => json_string(s),
+            Value::Array(values) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(Value::to_json)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Object(fields) => format!(
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", json_string(name), value.to_json()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Parse a JSON document.
+fn parse(source: &str) -> Option<Value> {
+    let mut parser = JsonParser {
+        chars: source.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos == parser.chars.len() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl JsonParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| " \t\r\n".contains(c)) {
+            self.pos += 1;
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Option<Value> {
+        for expected in word.chars() {
+            if self.next()? != expected {
+                return None;
+            }
+        }
+        Some(value)
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        match self.peek()? {
+            'n' => self.literal("null", Value::Null),
+            't' => self.literal("true", Value::Bool(true)),
+            'f' => self.literal("false", Value::Bool(false)),
+            '"' => self.string().map(Value::String),
+            '[' => {
+                self.next();
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.next();
+                    return Some(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => {}
+                        ']' => return Some(Value::Array(values)),
+                        _ => return None,
+                    }
+                }
+            }
+            '{' => {
+                self.next();
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.next();
+                    return Some(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let name = self.string()?;
+                    self.skip_whitespace();
+                    if self.next()? != ':' {
+                        return None;
+                    }
+                    fields.push((name, self.value()?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => {}
+                        '}' => return Some(Value::Object(fields)),
+                        _ => return None,
+                    }
+                }
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+                {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number.parse().ok().map(Value::Number)
+            }
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.next()? != '"' {
+            return None;
+        }
+        let mut value = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Some(value),
+                '\\' => match self.next()? {
+                    '"' => value.push('"'),
+                    '\\' => value.push('\\'),
+                    '/' => value.push('/'),
+                    'b' => value.push('\u{8}'),
+                    'f' => value.push('\u{c}'),
+                    'n' => value.push('\n'),
+                    'r' => value.push('\r'),
+                    't' => value.push('\t'),
+                    'u' => {
+                        let high = self.hex4()?;
+                        let code = if (0xd800..0xdc00).contains(&high) {
+                            // A surrogate pair.
+                            if self.next()? != '\\' || self.next()? != 'u' {
+                                return None;
+                            }
+                            let low = self.hex4()?;
+                            0x10000 + ((high - 0xd800) << 10) + (low.checked_sub(0xdc00)?)
+                        } else {
+                            high
+                        };
+                        value.push(char::from_u32(code)?);
+                    }
+                    _ => return None,
+                },
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            code = code * 16 + self.next()?.to_digit(16)?;
+        }
+        Some(code)
+    }
+}
@@ -0,0 +1,251 @@
+//! Options for [`git_format_staged`](crate::format::git_format_staged), with the defaults of the command line.
+
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use git2::Oid;
+
+use crate::{
+    cli::{Attribute, Only, OutputFormat, RelativeTo, StdinFilepath},
+    commands::{FilterOutput, DEFAULT_ARG_MAX},
+    encoding::Encoding,
+    file_type::FileType,
+    glob::Pattern,
+    repo_relative_path,
+    workdir::DEFAULT_STAGED_SUFFIX,
+};
+
+/// Settings that control how [`git_format_staged`](crate::format::git_format_staged) behaves.
+pub(crate) struct Options {
+    /// Whether to backport formatting changes to the working tree.
+    pub(crate) update_working_tree: bool,
+
+    /// Where to write the formatting plan, if anywhere.
+    pub(crate) dump_plan: Option<PathBuf>,
+
+    /// Whether to stage tracked files that only have unstaged changes.
+    pub(crate) allow_unstaged: bool,
+
+    /// Whether to format copies of the staged files outside the working tree.
+    pub(crate) index_only: bool,
+
+    /// The extension to add to the copies made in `--index-only` mode, if any.
+    pub(crate) temp_suffix: Option<String>,
+
+    /// The suffix for the copies of the staged files made in the working tree.
+    pub(crate) staged_suffix: String,
+
+    /// Whether to run the formatting commands as filters from stdin to stdout.
+    pub(crate) stdin: bool,
+
+    /// Whether the formatting commands read from stdin and write to the file named by `{}`.
+    pub(crate) formatter_stdin: bool,
+
+    /// The named pipe that the filters write to instead of their stdout, if any.
+    pub(crate) output_fifo: Option<PathBuf>,
+
+    /// How the path passed to the filters is written.
+    pub(crate) stdin_filepath: StdinFilepath,
+
+    /// Whether the formatting command is a language server.
+    pub(crate) lsp: bool,
+
+    /// Whether to check that formatting the newly staged files doesn't change them.
+    pub(crate) verify_clean: bool,
+
+    /// Files that are formatted even if they look binary.
+    pub(crate) treat_as_text: Vec<Pattern>,
+
+    /// The size in bytes below which files are skipped.
+    pub(crate) min_file_size: Option<u64>,
+
+    /// The size in bytes above which files are skipped.
+    pub(crate) max_file_size: Option<u64>,
+
+    /// The encoding of the staged files.
+    pub(crate) encoding: Encoding,
+
+    /// The types of file to format. All files are formatted if this is empty.
+    pub(crate) types: Vec<FileType>,
+
+    /// The Git attributes of the files to format. All files are formatted if this is
+    /// empty.
+    pub(crate) attr: Vec<Attribute>,
+
+    /// Whether to exit with an error if any file was reformatted.
+    pub(crate) fail_on_reformat: bool,
+
+    /// Whether whitespace-only changes count as reformatting.
+    pub(crate) ignore_whitespace: bool,
+
+    /// Whether to give the formatted files the staged files' line endings.
+    pub(crate) keep_line_endings: bool,
+
+    /// How to report the results.
+    pub(crate) format: OutputFormat,
+
+    /// Whether to report the results as a summary of outcomes.
+    pub(crate) summary_only: bool,
+
+    /// What reported file paths are relative to.
+    pub(crate) relative_to: RelativeTo,
+
+    /// The most bytes of arguments to pass to a formatting command at once.
+    pub(crate) arg_max: usize,
+
+    /// The blobs that are known to be formatted.
+    pub(crate) known_formatted: HashSet<Oid>,
+
+    /// How many jobs to format the files in.
+    pub(crate) jobs: usize,
+
+    /// How many jobs to format the files with each extension in.
+    pub(crate) jobs_per_extension: Vec<(Vec<String>, usize)>,
+
+    /// Whether to run the formatting commands on each file from its own directory.
+    pub(crate) chdir_file: bool,
+
+    /// Whether to only format the changed lines of each file.
+    pub(crate) only_changed_lines: bool,
+
+    /// The most times to format each file, stopping early once it stops changing.
+    pub(crate) passes: u32,
+
+    /// Whether to skip files that are known to be formatted, and remember the ones that
+    /// are.
+    pub(crate) cache: bool,
+
+    /// Which of the given files to format.
+    pub(crate) only: Option<Only>,
+
+    /// Whether to skip files whose staged versions are the same as in `HEAD`.
+    pub(crate) changed_vs_head: bool,
+
+    /// The commit to skip files that are the same as, if any.
+    pub(crate) base: Option<String>,
+
+    /// Whether to print the staged changes against `HEAD` when done.
+    pub(crate) diff_head: bool,
+
+    /// Whether to print how many lines formatting changed in each file when done.
+    pub(crate) stat: bool,
+
+    /// A shell command to run with the reformatted files when done.
+    pub(crate) post_format: Option<String>,
+
+    /// Whether the run fails if the `post_format` command does.
+    pub(crate) post_format_must_succeed: bool,
+
+    /// Whether to stage the formatted files.
+    pub(crate) write_index: bool,
+
+    /// A copy of the index to stage the formatted files in, instead of the index itself.
+    pub(crate) out_index: Option<PathBuf>,
+
+    /// Whether to `fsync` the index after writing it.
+    pub(crate) sync: bool,
+
+    /// Whether to check that the index has the formatted files once they're staged.
+    pub(crate) verify_staged_unchanged: bool,
+}
+
+/// The options when nothing is given on the command line.
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            update_working_tree: true,
+            dump_plan: None,
+            allow_unstaged: false,
+            index_only: false,
+            temp_suffix: None,
+            staged_suffix: DEFAULT_STAGED_SUFFIX.to_string(),
+            stdin: false,
+            formatter_stdin: false,
+            output_fifo: None,
+            stdin_filepath: StdinFilepath::Cwd,
+            lsp: false,
+            verify_clean: false,
+            treat_as_text: Vec::new(),
+            min_file_size: None,
+            max_file_size: None,
+            encoding: Encoding::Utf8,
+            types: Vec::new(),
+            attr: Vec::new(),
+            fail_on_reformat: false,
+            ignore_whitespace: false,
+            keep_line_endings: false,
+            format: OutputFormat::Text,
+            summary_only: false,
+            relative_to: RelativeTo::Cwd,
+            arg_max: DEFAULT_ARG_MAX,
+            known_formatted: HashSet::new(),
+            jobs: 1,
+            jobs_per_extension: Vec::new(),
+            chdir_file: false,
+            only_changed_lines: false,
+            passes: 1,
+            cache: false,
+            only: None,
+            changed_vs_head: false,
+            base: None,
+            diff_head: false,
+            stat: false,
+            post_format: None,
+            post_format_must_succeed: false,
+            write_index: true,
+            out_index: None,
+            sync: false,
+            verify_staged_unchanged: false,
+        }
+    }
+}
+
+impl Options {
+    /// Whether the staged files are formatted in memory, without being written anywhere.
+    pub(crate) fn in_memory(&self) -> bool {
+        self.stdin || self.lsp
+    }
+
+    /// Whether the staged files are formatted as copies in a temporary directory. That's
+    /// the case for `--index-only`, and whenever the working tree isn't to be updated, so
+    /// that its files are never overwritten, even for a moment.
+    pub(crate) fn in_temp_dir(&self) -> bool {
+        self.index_only || (!self.update_working_tree && !self.in_memory())
+    }
+
+    /// How to show `file`, which is relative to the current directory, in messages.
+    pub(crate) fn report_path<'a>(&self, dir_prefix: &Path, file: &'a Path) -> Cow<'a, Path> {
+        match self.relative_to {
+            RelativeTo::Cwd => Cow::Borrowed(file),
+            RelativeTo::Repo => Cow::Owned(repo_relative_path(dir_prefix, file)),
+        }
+    }
+
+    /// Where the filters write their results in `--stdin` mode.
+    pub(crate) fn filter_output(&self) -> FilterOutput<'_> {
+        match &self.output_fifo {
+            Some(fifo) => FilterOutput::Fifo(fifo),
+            None => FilterOutput::Stdout,
+        }
+    }
+
+    /// The path to pass for `file` in `--stdin` mode, in the form given by
+    /// [`Options::stdin_filepath`].
+    pub(crate) fn stdin_path<'a>(
+        &self,
+        repo_path: &Path,
+        dir_prefix: &Path,
+        file: &'a Path,
+    ) -> Cow<'a, Path> {
+        match self.stdin_filepath {
+            StdinFilepath::Cwd => Cow::Borrowed(file),
+            StdinFilepath::Repo => Cow::Owned(repo_relative_path(dir_prefix, file)),
+            StdinFilepath::Absolute => {
+                Cow::Owned(repo_path.join(repo_relative_path(dir_prefix, file)))
+            }
+        }
+    }
+}
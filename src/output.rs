@@ -0,0 +1,207 @@
+/*! Where output goes.
+
+Stdout is kept for data that other programs might parse: the `--format json` and
+`--summary-only` summaries, unless they're written to a `--report-file`, and the
+`--diff-head` patch. Everything else goes to stderr,
+including errors, warnings, hints, the commands printed by `--print-command`, the
+steps traced by `GIT_FORMAT_STAGED_TRACE`, and anything the formatters themselves write
+to stdout.
+
+With `--capture-output`, the formatters' stdout and stderr are captured instead, and
+written to stderr in one piece once each formatter has finished.
+*/
+
+use std::{
+    fmt,
+    io::{IsTerminal, StdoutLock, Write},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use clap::ColorChoice;
+
+/// Print an error message to stderr.
+macro_rules! error {
+    ($($arg:tt)*) => {
+        eprintln!(
+            "{}: {}",
+            $crate::output::paint("error", $crate::output::RED),
+            format_args!($($arg)*)
+        )
+    };
+}
+
+/// Print a warning message to stderr.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        eprintln!(
+            "{}: {}",
+            $crate::output::paint("warning", $crate::output::YELLOW),
+            format_args!($($arg)*)
+        )
+    };
+}
+
+/// Print a hint about what to do next to stderr, unless advice is turned off.
+macro_rules! hint {
+    ($($arg:tt)*) => {
+        if $crate::output::ADVICE.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!("hint: {}", format_args!($($arg)*))
+        }
+    };
+}
+
+/// Print a line of diagnostic output without a label, such as a continuation of an error,
+/// to stderr.
+macro_rules! note {
+    ($($arg:tt)*) => {
+        eprintln!($($arg)*)
+    };
+}
+
+/// Print a step of the run to stderr, if [`TRACE`] is set.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::output::TRACE.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!("trace: {}", format_args!($($arg)*))
+        }
+    };
+}
+
+/// Print a line of data to stdout.
+macro_rules! data {
+    ($($arg:tt)*) => {
+        $crate::output::data_line(format_args!($($arg)*))
+    };
+}
+
+/// Whether the steps of the run are printed, as set by
+/// [`TRACE_VAR`](crate::TRACE_VAR).
+pub static TRACE: AtomicBool = AtomicBool::new(false);
+
+/// Whether hints are printed. See [`Cli::no_advice`](crate::cli::Cli::no_advice).
+pub static ADVICE: AtomicBool = AtomicBool::new(true);
+
+/// Where the summary is written instead of stdout. See
+/// [`Cli::report_file`](crate::cli::Cli::report_file).
+pub static REPORT_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Whether the formatters' output is captured and replayed. See
+/// [`Cli::capture_output`](crate::cli::Cli::capture_output).
+pub static CAPTURE_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Whether diagnostics are written with ANSI colors. See [`Cli::color`](crate::cli::Cli::color).
+static USE_COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Decide whether diagnostics are colored.
+pub fn set_color(choice: ColorChoice) {
+    USE_COLOR.store(
+        match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").map_or(true, |value| value.is_empty())
+                    && std::io::stderr().is_terminal()
+            }
+        },
+        Ordering::Relaxed,
+    );
+}
+
+pub const RED: &str = "31";
+pub const YELLOW: &str = "33";
+
+/// Wrap `text` in the ANSI escape codes for `color`, if coloring is enabled.
+pub fn paint(text: &str, color: &str) -> String {
+    if USE_COLOR.load(Ordering::Relaxed) {
+        format!("\x1b[1;{}m{}\x1b[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Stdout, for writing data to.
+pub fn data() -> StdoutLock<'static> {
+    std::io::stdout().lock()
+}
+
+/// Write `line` to stdout. A reader that has gone away, like `head`, isn't an error.
+pub fn data_line(line: fmt::Arguments) {
+    let _ = writeln!(data(), "{}", line);
+}
+
+/// Write the summary of the run, which is one or more lines of text, to the
+/// [`REPORT_FILE`] if there is one, or else to stdout.
+pub fn summary(text: &str) {
+    let Some(path) = REPORT_FILE.get() else {
+        data!("{}", text);
+        return;
+    };
+    if let Err(err) = replace_file(path, format!("{}\n", text).as_bytes()) {
+        error!("failed to write {}: {}", crate::quote_path(&path), err);
+        crate::exit(crate::ExitCode::Error);
+    }
+}
+
+/// Write `content` to `path` by renaming a new file over it, so that readers see either
+/// the old content or the new, and never part of it.
+fn replace_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    std::fs::write(&temp_path, content)?;
+    crate::rename_file(Path::new(&temp_path), path).map_err(|err| {
+        let _ = std::fs::remove_file(&temp_path);
+        err
+    })
+}
+
+/// Where a command's stdout goes when it isn't read: to stderr, so that it doesn't mix
+/// with the data on stdout.
+pub fn child_stdout() -> Stdio {
+    std::io::stderr().into()
+}
+
+/// Where a formatter's stdout goes when it isn't read: into a pipe when its output is
+/// captured, or else to stderr like [`child_stdout`].
+pub fn formatter_stdout() -> Stdio {
+    if CAPTURE_OUTPUT.load(Ordering::Relaxed) {
+        Stdio::piped()
+    } else {
+        child_stdout()
+    }
+}
+
+/// Where a formatter's stderr goes when it isn't read: into a pipe when its output is
+/// captured, or else straight to stderr.
+pub fn formatter_stderr() -> Stdio {
+    if CAPTURE_OUTPUT.load(Ordering::Relaxed) {
+        Stdio::piped()
+    } else {
+        Stdio::inherit()
+    }
+}
+
+/// Write the output captured from the formatting `command` to stderr, under a line
+/// naming the command. Nothing is written if the command had no output.
+pub fn replay(command: &str, stdout: &[u8], stderr: &[u8]) {
+    if stdout.is_empty() && stderr.is_empty() {
+        return;
+    }
+    let mut out = std::io::stderr().lock();
+    let _ = writeln!(out, "output from {}:", command);
+    let _ = out.write_all(stdout);
+    let _ = out.write_all(stderr);
+    let _ = out.flush();
+}
+
+/// Write the stderr that was captured from a formatter for the summary to stderr after
+/// all, because the formatter failed and the summary won't be written.
+pub fn failed_stderr(stderr: &[u8]) {
+    let mut out = std::io::stderr().lock();
+    let _ = out.write_all(stderr);
+    let _ = out.flush();
+}
@@ -0,0 +1,445 @@
+//! Reporting what was formatted: the summary, `--format json`, `--stat`, `--diff-head`,
+//! and the plan printed by `--dry-run`.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use git2::{DiffOptions, Error, Repository, Tree};
+
+use crate::{
+    classify::{Skip, TargetFile},
+    cli::OutputFormat,
+    commands::{run_post_format, CommandError},
+    exit, json_string,
+    options::Options,
+    output, quote_path, repo_relative_path,
+    staging::head_tree,
+    workdir::temp_file_paths,
+    write_file, ExitCode,
+};
+
+/** Report the results of a run: the JSON summary or `--summary-only` summary, then
+`--post-format`, and finally the exit code for `--fail-on-reformat`.
+
+`--post-format` isn't run if nothing was formatted.
+*/
+pub(crate) fn finish(report: &Report, options: &Options) {
+    if options.format == OutputFormat::Json {
+        print_json_summary(report);
+    }
+
+    if options.summary_only {
+        let mut outcomes = report.outcomes.clone();
+        for file in &report.files {
+            let group = if file.reformatted {
+                &mut outcomes.reformatted
+            } else {
+                &mut outcomes.unchanged
+            };
+            group.push(file.path.clone());
+        }
+        outcomes.print();
+    }
+
+    let reformatted: Vec<PathBuf> = report
+        .files
+        .iter()
+        .filter(|file| file.reformatted)
+        .map(|file| file.path.clone())
+        .collect();
+
+    if let Some(post_format) = options
+        .post_format
+        .as_ref()
+        .filter(|_| !report.files.is_empty())
+    {
+        if let Err(err) = run_post_format(post_format, &reformatted) {
+            if options.post_format_must_succeed {
+                error!("--post-format: {}", err);
+                exit(ExitCode::Error);
+            }
+            warning!("--post-format: {}", err);
+        }
+    }
+
+    if options.fail_on_reformat && !reformatted.is_empty() {
+        error!("the following files were reformatted:");
+        for file in &reformatted {
+            note!("  {}", quote_path(file));
+        }
+        hint!("review the changes and commit again");
+        exit(ExitCode::Error);
+    }
+}
+
+/** Write the formatting plan to `path` as JSON.
+
+The plan lists each file that will be formatted, the path that is passed to the
+formatting commands for it, and the temporary files that are created for it, so that
+other tools can coordinate with this run.
+*/
+pub(crate) fn dump_plan(
+    path: &Path,
+    repo_path: &Path,
+    commands: &[&[String]],
+    to_format: &[TargetFile],
+    format_paths: &[PathBuf],
+    options: &Options,
+) {
+    let mut json = String::from("{\n");
+
+    json.push_str(&format!(
+        "  \"repository\": {},\n",
+        json_string(&repo_path.to_string_lossy())
+    ));
+
+    json.push_str("  \"commands\": [");
+    for (i, command) in commands.iter().enumerate() {
+        let command: Vec<String> = command.iter().map(|arg| json_string(arg)).collect();
+        json.push_str(if i == 0 { "\n    [" } else { ",\n    [" });
+        json.push_str(&command.join(", "));
+        json.push(']');
+    }
+    json.push_str(if commands.is_empty() {
+        "],\n"
+    } else {
+        "\n  ],\n"
+    });
+
+    json.push_str("  \"targets\": [");
+    for (i, (target, format_path)) in to_format.iter().zip(format_paths).enumerate() {
+        let file = target.path();
+        let temp_files: Vec<String> = if options.in_temp_dir() {
+            vec![json_string(&format_path.to_string_lossy())]
+        } else if options.in_memory() {
+            Vec::new()
+        } else {
+            temp_file_paths(file, &options.staged_suffix)
+                .iter()
+                .map(|path| json_string(&path.to_string_lossy()))
+                .collect()
+        };
+
+        json.push_str(if i == 0 { "\n" } else { ",\n" });
+        json.push_str(&format!(
+            "    {{\"path\": {}, \"variant\": {}, \"format_path\": {}, \"temp_files\": [{}]}}",
+            json_string(&file.to_string_lossy()),
+            json_string(match target {
+                TargetFile::StagedOnly(_) => "staged-only",
+                TargetFile::UnstagedAndStaged(_) => "unstaged-and-staged",
+            }),
+            json_string(&format_path.to_string_lossy()),
+            temp_files.join(", "),
+        ));
+    }
+    json.push_str(if to_format.is_empty() {
+        "]\n"
+    } else {
+        "\n  ]\n"
+    });
+
+    json.push_str("}\n");
+
+    write_file(path, json.as_bytes());
+}
+
+/// Print the differences between `HEAD` and the index as a patch to stdout.
+pub(crate) fn print_diff_head(repo: &Repository) -> Result<(), Error> {
+    // Before the first commit, everything in the index is new.
+    let head_tree = head_tree(repo)?;
+    let diff = repo.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+
+    let mut stdout = output::data();
+    diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        let origin = match line.origin() {
+            origin @ ('+' | '-' | ' ') => Some(origin as u8),
+            _ => None,
+        };
+        origin
+            .map_or(Ok(()), |origin| stdout.write_all(&[origin]))
+            .and_then(|()| stdout.write_all(line.content()))
+            .is_ok()
+    })
+}
+
+/** Print the lines added and removed by formatting each of `files` to stdout, like `git
+diff --stat`, by comparing their versions in `index_tree` and `formatted_tree`.
+
+Files that formatting didn't change are listed with `0`.
+*/
+pub(crate) fn print_stat(
+    repo: &Repository,
+    index_tree: &Tree,
+    formatted_tree: &Tree,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    options: &Options,
+) -> Result<(), Error> {
+    /// The widest that the `+++---` graph gets.
+    const MAX_GRAPH_WIDTH: usize = 40;
+
+    let mut stats = Vec::with_capacity(files.len());
+    for file in files {
+        let path = repo_relative_path(dir_prefix, file);
+        let staged = repo.find_blob(index_tree.get_path(&path)?.id())?;
+        let formatted = repo.find_blob(formatted_tree.get_path(&path)?.id())?;
+        let patch = git2::Patch::from_blobs(
+            &staged,
+            None,
+            &formatted,
+            None,
+            Some(DiffOptions::new().force_text(true)),
+        )?;
+        let (_, insertions, deletions) = patch.line_stats()?;
+        let shown = quote_path(&options.report_path(dir_prefix, file)).into_owned();
+        stats.push((shown, insertions, deletions));
+    }
+
+    let name_width = stats
+        .iter()
+        .map(|(name, _, _)| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max_changes = stats
+        .iter()
+        .map(|(_, insertions, deletions)| insertions + deletions)
+        .max()
+        .unwrap_or(0);
+    let count_width = max_changes.to_string().len();
+    // Like Git, the graph is scaled down to fit, but a change never disappears from it.
+    let scale = |n: usize| {
+        if n == 0 || max_changes <= MAX_GRAPH_WIDTH {
+            n
+        } else {
+            n * (MAX_GRAPH_WIDTH - 1) / max_changes + 1
+        }
+    };
+    // Also like Git, the total is scaled, and the smaller part is taken from it, so that the
+    // graph never gets wider than the widest allowed.
+    let graph = |insertions: usize, deletions: usize| {
+        let total = scale(insertions + deletions).max(if insertions > 0 && deletions > 0 {
+            2
+        } else {
+            0
+        });
+        if insertions < deletions {
+            let insertions = scale(insertions);
+            (insertions, total - insertions)
+        } else {
+            let deletions = scale(deletions);
+            (total - deletions, deletions)
+        }
+    };
+
+    let mut lines = Vec::with_capacity(stats.len() + 1);
+    for (name, insertions, deletions) in &stats {
+        let changes = insertions + deletions;
+        let mut line = format!(
+            " {:name_width$} | {:>count_width$}",
+            name,
+            changes,
+            name_width = name_width,
+            count_width = count_width
+        );
+        if changes > 0 {
+            let (insertions, deletions) = graph(*insertions, *deletions);
+            line.push(' ');
+            line.push_str(&"+".repeat(insertions));
+            line.push_str(&"-".repeat(deletions));
+        }
+        lines.push(line);
+    }
+
+    let changed = stats
+        .iter()
+        .filter(|(_, insertions, deletions)| insertions + deletions > 0)
+        .count();
+    let insertions: usize = stats.iter().map(|(_, insertions, _)| insertions).sum();
+    let deletions: usize = stats.iter().map(|(_, _, deletions)| deletions).sum();
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+    let mut summary = format!(" {}", plural(changed, "file changed", "files changed"));
+    if insertions > 0 || deletions == 0 {
+        summary.push_str(&format!(
+            ", {}",
+            plural(insertions, "insertion(+)", "insertions(+)")
+        ));
+    }
+    if deletions > 0 || insertions == 0 {
+        summary.push_str(&format!(
+            ", {}",
+            plural(deletions, "deletion(-)", "deletions(-)")
+        ));
+    }
+    lines.push(summary);
+
+    data!("{}", lines.join("\n"));
+    Ok(())
+}
+
+/// Write a JSON summary of the run to stdout. Empty stderr output is left out.
+pub(crate) fn print_json_summary(report: &Report) {
+    let mut json = String::from("{\n  \"files\": [");
+    for (i, file) in report.files.iter().enumerate() {
+        json.push_str(if i == 0 { "\n" } else { ",\n" });
+        json.push_str(&format!(
+            "    {{\"path\": {}, \"reformatted\": {}",
+            json_string(&file.path.to_string_lossy()),
+            file.reformatted
+        ));
+        if !file.stderr.is_empty() {
+            json.push_str(&format!(
+                ", \"stderr\": {}",
+                json_string(&String::from_utf8_lossy(&file.stderr))
+            ));
+        }
+        json.push('}');
+    }
+    json.push_str(if report.files.is_empty() {
+        "]"
+    } else {
+        "\n  ]"
+    });
+
+    if !report.shared_stderr.is_empty() {
+        json.push_str(&format!(
+            ",\n  \"stderr\": {}",
+            json_string(&String::from_utf8_lossy(&report.shared_stderr))
+        ));
+    }
+
+    json.push_str("\n}");
+    output::summary(&json);
+}
+
+/// What a run did, for [`finish`] to report. Paths are as they're reported.
+#[derive(Default)]
+pub(crate) struct Report {
+    /// The files that were formatted.
+    pub(crate) files: Vec<ReportedFile>,
+
+    /// What the formatters wrote to stderr while formatting the files all at once.
+    pub(crate) shared_stderr: Vec<u8>,
+
+    /// The files that were skipped or known to be formatted already.
+    pub(crate) outcomes: Outcomes,
+}
+
+/// A file that was formatted, in a [`Report`].
+pub(crate) struct ReportedFile {
+    pub(crate) path: PathBuf,
+    pub(crate) reformatted: bool,
+
+    /// What the formatters wrote to stderr for this file, in `--stdin` mode.
+    pub(crate) stderr: Vec<u8>,
+}
+
+impl Report {
+    /// Add the results of formatting another repository, with `path` making its paths
+    /// relative to this one's.
+    pub(crate) fn append(&mut self, other: Report, path: impl Fn(&Path) -> PathBuf) {
+        self.files
+            .extend(other.files.into_iter().map(|file| ReportedFile {
+                path: path(&file.path),
+                ..file
+            }));
+        self.shared_stderr.extend(other.shared_stderr);
+        self.outcomes
+            .reformatted
+            .extend(other.outcomes.reformatted.iter().map(|file| path(file)));
+        self.outcomes
+            .unchanged
+            .extend(other.outcomes.unchanged.iter().map(|file| path(file)));
+        self.outcomes.skipped.extend(
+            other
+                .outcomes
+                .skipped
+                .into_iter()
+                .map(|(file, skip)| (path(&file), skip)),
+        );
+        self.outcomes.failed.extend(other.outcomes.failed);
+    }
+}
+
+/// What happened to each file, for [`Options::summary_only`]. Paths are as they're reported.
+#[derive(Clone, Default)]
+pub(crate) struct Outcomes {
+    pub(crate) reformatted: Vec<PathBuf>,
+    pub(crate) unchanged: Vec<PathBuf>,
+    pub(crate) skipped: Vec<(PathBuf, Skip)>,
+
+    /// An error message for each file that couldn't be formatted.
+    pub(crate) failed: Vec<String>,
+}
+
+impl Outcomes {
+    /// The most files listed in each group of the summary.
+    const MAX_LISTED: usize = 10;
+
+    /// Record that formatting `files` failed with `err`.
+    pub(crate) fn fail_all(
+        &mut self,
+        dir_prefix: &Path,
+        files: &[PathBuf],
+        err: &CommandError,
+        options: &Options,
+    ) {
+        for file in files {
+            self.failed.push(format!(
+                "{} ({})",
+                quote_path(&options.report_path(dir_prefix, file)),
+                err
+            ));
+        }
+    }
+
+    /// Print the summary, with a count for each group and the first few files in it.
+    /// Empty groups are left out.
+    pub(crate) fn print(&self) {
+        let skipped: Vec<String> = self
+            .skipped
+            .iter()
+            .map(|(file, skip)| format!("{} ({})", quote_path(file), skip))
+            .collect();
+        let reformatted: Vec<String> = self
+            .reformatted
+            .iter()
+            .map(|file| quote_path(file).into_owned())
+            .collect();
+        let unchanged: Vec<String> = self
+            .unchanged
+            .iter()
+            .map(|file| quote_path(file).into_owned())
+            .collect();
+
+        let groups = [
+            ("reformatted", &reformatted),
+            ("unchanged", &unchanged),
+            ("skipped", &skipped),
+            ("failed", &self.failed),
+        ];
+        if groups.iter().all(|(_, lines)| lines.is_empty()) {
+            output::summary("no files to format");
+            return;
+        }
+        let mut summary = Vec::new();
+        for (name, lines) in groups {
+            if lines.is_empty() {
+                continue;
+            }
+            summary.push(format!("{}: {}", name, lines.len()));
+            for line in lines.iter().take(Outcomes::MAX_LISTED) {
+                summary.push(format!("  {}", line));
+            }
+            if lines.len() > Outcomes::MAX_LISTED {
+                summary.push(format!(
+                    "  ...and {} more",
+                    lines.len() - Outcomes::MAX_LISTED
+                ));
+            }
+        }
+        output::summary(&summary.join("\n"));
+    }
+}
@@ -0,0 +1,1301 @@
+//! The entry point: reading the command line and the config file into [`Options`], and
+//! then running the formatters, the `filter` subcommand, or `--commit`.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fmt::{self, Display},
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use clap::{
+    parser::ValueSource, ArgMatches, ColorChoice, CommandFactory, FromArgMatches, ValueEnum,
+};
+use git2::{Commit, Error, ErrorCode, Index, Oid, Repository};
+
+use crate::{
+    cli::{parse_jobs, Cli, CliCommand, OutputFormat},
+    commands::{
+        exit_on_command_error, filter_commands, shell_split, split_env, FilterOutput, DEADLINE,
+        KILL, PRINT_COMMANDS,
+    },
+    config::{Config, CONFIG_FILE_NAME},
+    cwd_relative_path, env_flag, exit,
+    file_type::FileType,
+    format::{format_by_type, format_with_submodules, git_format_staged},
+    glob::Pattern,
+    json_string,
+    options::Options,
+    output, quote_path, read_file,
+    report::{finish, Report},
+    staging::{dump_staged_blob, staged_diff_paths},
+    ExitCode, EXIT_ZERO, KEEP_TEMP, KEEP_TEMP_VAR, RUNNING_VAR, TRACE_VAR,
+};
+
+/// Run the `filter` subcommand.
+pub(crate) fn filter(path: &Path, command: &[String]) {
+    let mut content = Vec::new();
+    if let Err(err) = std::io::stdin().read_to_end(&mut content) {
+        error!("failed to read stdin: {}", err);
+        exit(ExitCode::Error);
+    }
+    match filter_commands(&[command], path, &content, false, FilterOutput::Stdout) {
+        Ok((formatted, _)) => {
+            if let Err(err) = output::data().write_all(&formatted) {
+                error!("failed to write to stdout: {}", err);
+                exit(ExitCode::Error);
+            }
+        }
+        Err(err) => exit_on_command_error(err),
+    }
+}
+
+/// Run `git-format-staged` with this process's command line arguments.
+pub fn run() {
+    // Clap doesn't tell a missing `--` from a `--` with nothing after it, so another `--`
+    // is added to the end: the command then has a value exactly when a `--` was given,
+    // and that extra value is dropped again below. The matches are kept to tell which
+    // options were given on the command line, for `--dump-config`.
+    let matches =
+        Cli::command().get_matches_from(std::env::args_os().chain([OsString::from("--")]));
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let command_given = matches.value_source("command") == Some(ValueSource::CommandLine);
+    if command_given {
+        cli.command.pop();
+    }
+    if let Some(CliCommand::Filter { command, .. }) = &mut cli.subcommand {
+        command.pop();
+    }
+
+    if let Some(deadline) = cli.deadline {
+        DEADLINE.set(Instant::now() + deadline).unwrap();
+        KILL.set((cli.kill_signal.clone(), cli.kill_grace)).unwrap();
+    }
+    EXIT_ZERO.store(cli.exit_zero, Ordering::Relaxed);
+    output::set_color(cli.color.unwrap_or(ColorChoice::Auto));
+    let trace = env_flag(TRACE_VAR);
+    output::TRACE.store(trace, Ordering::Relaxed);
+    PRINT_COMMANDS.store(cli.print_command || trace, Ordering::Relaxed);
+    KEEP_TEMP.store(env_flag(KEEP_TEMP_VAR), Ordering::Relaxed);
+    output::CAPTURE_OUTPUT.store(cli.capture_output, Ordering::Relaxed);
+    if let Some(report_file) = &cli.report_file {
+        if !cli.summary_only && cli.format != OutputFormat::Json {
+            error!("--report-file needs --summary-only or --format json");
+            exit(ExitCode::Usage);
+        }
+        output::REPORT_FILE.set(report_file.clone()).unwrap();
+    }
+
+    if std::env::var_os(RUNNING_VAR).is_some() {
+        error!(
+            "recursive invocation detected: git-format-staged was run by one of its own formatters ({} is set)",
+            RUNNING_VAR
+        );
+        hint!(
+            "check that the formatter command doesn't run git-format-staged, or a hook that does"
+        );
+        exit(ExitCode::Error);
+    }
+
+    for dir in &cli.chdir {
+        if let Err(err) = std::env::set_current_dir(dir) {
+            error!("cannot change to {}: {}", quote_path(&dir), err);
+            exit(ExitCode::Error);
+        }
+    }
+
+    if let Some(CliCommand::Filter { path, command }) = &cli.subcommand {
+        filter(path.as_deref().unwrap_or(Path::new("")), command);
+        return;
+    }
+
+    if command_given && cli.command.is_empty() {
+        error!("no formatter specified after --");
+        exit(ExitCode::Usage);
+    }
+
+    if let Some(shell_command) = &cli.shell_command {
+        cli.command = shell_split(shell_command).unwrap_or_else(|err| {
+            error!("invalid --command: {}", err);
+            exit(ExitCode::Usage);
+        });
+        if cli.command.is_empty() {
+            error!("no formatter specified in --command");
+            exit(ExitCode::Usage);
+        }
+    }
+
+    // This finds the repository the same way Git does, including `GIT_DIR` and
+    // `GIT_INDEX_FILE`.
+    let repo = match Repository::open_from_env() {
+        Ok(repo) => repo,
+        Err(_)
+            if cli.command.is_empty() && cli.config.is_none() && cli.dump_staged_blob.is_none() =>
+        {
+            // There's nothing to do.
+            return;
+        }
+        Err(_) => {
+            error!("not a Git repository");
+            exit(ExitCode::NotARepository);
+        }
+    };
+
+    if let Some(index_file) = &cli.index_file {
+        if let Err(err) = Index::open(index_file).and_then(|mut index| repo.set_index(&mut index)) {
+            error!("failed to open index {}: {}", quote_path(&index_file), err);
+            exit(ExitCode::Error);
+        }
+    }
+
+    let advice_config = repo
+        .config()
+        .and_then(|config| config.get_bool("format-staged.advice"))
+        .ok();
+    let advice = !cli.no_advice && advice_config.unwrap_or(true);
+    output::ADVICE.store(advice, Ordering::Relaxed);
+
+    // The work tree is usually the parent of the `.git` directory, but it can be moved
+    // with `core.worktree`. Both it and the current directory are canonicalized, so
+    // that they can be compared even if either was reached through a symlink.
+    let repo_path = match repo.workdir() {
+        Some(workdir) => canonicalize(workdir, "the repository's working tree"),
+        None => {
+            error!("the repository has no working tree");
+            exit(ExitCode::Error);
+        }
+    };
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => canonicalize(&cwd, "the current directory"),
+        Err(err) => {
+            error!("couldn't find the current directory: {}", err);
+            exit(ExitCode::Error);
+        }
+    };
+    // The current directory, relative to the repository root.
+    let dir_prefix = match cwd.strip_prefix(&repo_path) {
+        Ok(dir_prefix) => dir_prefix.to_path_buf(),
+        Err(_) => {
+            error!(
+                "the current directory, {}, is outside the repository's working tree, {}",
+                quote_path(&cwd),
+                quote_path(&repo_path)
+            );
+            exit(ExitCode::Error);
+        }
+    };
+
+    trace!(
+        "repository {}, current directory {} within it",
+        quote_path(&repo_path),
+        quote_path(&Path::new(".").join(&dir_prefix))
+    );
+
+    if let Some(file) = &cli.dump_staged_blob {
+        dump_staged_blob(&repo, &dir_prefix, file, cli.dump_to.as_deref());
+        return;
+    }
+
+    let (config, config_path) = load_config(&repo_path, cli.config.as_deref());
+    // Where each option came from, for `--dump-config`.
+    let from_config = match &config_path {
+        Some(path) => format!("config file {}", quote_path(path)),
+        None => String::new(),
+    };
+    let source = |on_command_line: bool, in_config: bool| -> String {
+        if on_command_line {
+            "command line".to_string()
+        } else if in_config {
+            from_config.clone()
+        } else {
+            "default".to_string()
+        }
+    };
+    let color_setting = match (cli.color, &config.color) {
+        (Some(color), _) => (value_name(color), source(true, false)),
+        (None, Some(color)) => (color.clone(), source(false, true)),
+        (None, None) => (value_name(ColorChoice::Auto), source(false, false)),
+    };
+    let files_source = source(
+        cli.paths_from_diff || !cli.files.is_empty(),
+        config.files.is_some() || config.globs.is_some(),
+    );
+    let jobs_source = source(cli.jobs.is_some(), config.jobs.is_some());
+    let command_source = source(!cli.command.is_empty(), config.command.is_some());
+    let index_only_source = source(
+        cli.index_only || cli.no_index_only,
+        config.index_only.is_some(),
+    );
+    let no_working_tree_source = source(
+        cli.no_working_tree || cli.working_tree,
+        config.no_working_tree.is_some(),
+    );
+    let allow_unstaged_source = source(
+        cli.allow_unstaged || cli.no_allow_unstaged,
+        config.allow_unstaged.is_some(),
+    );
+
+    if cli.color.is_none() {
+        if let Some(color) = &config.color {
+            output::set_color(ColorChoice::from_str(color, true).unwrap_or_else(|_| {
+                error!("invalid `color` in config: {}", color);
+                exit(ExitCode::Error);
+            }));
+        }
+    }
+
+    let patterns = |key: &str, patterns: &Option<Vec<String>>| -> Vec<Pattern> {
+        patterns
+            .iter()
+            .flatten()
+            .map(|pattern| {
+                Pattern::new(pattern).unwrap_or_else(|err| {
+                    error!("invalid `{}` in config: {}", key, err);
+                    exit(ExitCode::Error);
+                })
+            })
+            .collect()
+    };
+    let globs = patterns("globs", &config.globs);
+    let excludes = patterns("excludes", &config.excludes);
+    let config_jobs = config.jobs.as_deref().map(|jobs| {
+        parse_jobs(jobs).unwrap_or_else(|err| {
+            error!("invalid `jobs` in config: {}", err);
+            exit(ExitCode::Error);
+        })
+    });
+
+    // Whether the files are the staged ones, rather than a list, so that there may be none.
+    let from_diff = cli.paths_from_diff || (cli.files.is_empty() && !globs.is_empty());
+    let staged_paths = || {
+        staged_diff_paths(&repo).unwrap_or_else(|err| {
+            error!("{}", err);
+            exit(ExitCode::Error);
+        })
+    };
+    // Paths in the config file and from the staged diff are relative to the repository
+    // root. The config file's `excludes` apply to them, but not to files given on the
+    // command line.
+    let from_root = |files: Vec<PathBuf>| -> Vec<PathBuf> {
+        files
+            .iter()
+            .filter(|file| {
+                let path = file.to_string_lossy();
+                !excludes.iter().any(|pattern| pattern.matches(&path))
+            })
+            .map(|file| cwd_relative_path(&dir_prefix, file))
+            .collect()
+    };
+    let files = if cli.paths_from_diff {
+        from_root(staged_paths())
+    } else if cli.files.is_empty() {
+        let mut files: Vec<PathBuf> = config
+            .files
+            .into_iter()
+            .flatten()
+            .map(PathBuf::from)
+            .collect();
+        if !globs.is_empty() {
+            for path in staged_paths() {
+                let matched = {
+                    let path = path.to_string_lossy();
+                    globs.iter().any(|pattern| pattern.matches(&path))
+                };
+                if matched && !files.contains(&path) {
+                    files.push(path);
+                }
+            }
+        }
+        from_root(files)
+    } else if let Some(n) = cli.strip_components {
+        cli.files
+            .iter()
+            .map(|file| {
+                let components: Vec<Component> = file.components().collect();
+                if components.len() <= n {
+                    error!(
+                        "{} has too few path components for --strip-components {}",
+                        quote_path(file),
+                        n
+                    );
+                    exit(ExitCode::Error);
+                }
+                components[n..].iter().collect::<PathBuf>()
+            })
+            .collect()
+    } else {
+        std::mem::take(&mut cli.files)
+    };
+
+    let command = if cli.command.is_empty() {
+        config.command.unwrap_or_default()
+    } else {
+        std::mem::take(&mut cli.command)
+    };
+
+    // A flag on the command line, or its negation, overrides the config file.
+    let flag =
+        |on: bool, off: bool, in_config: Option<bool>| on || (!off && in_config == Some(true));
+    let index_only = flag(cli.index_only, cli.no_index_only, config.index_only);
+    let no_working_tree = flag(
+        cli.no_working_tree,
+        cli.working_tree,
+        config.no_working_tree,
+    );
+    let allow_unstaged = flag(
+        cli.allow_unstaged,
+        cli.no_allow_unstaged,
+        config.allow_unstaged,
+    );
+
+    if cli.dump_config {
+        let advice_source = if cli.no_advice {
+            "command line".to_string()
+        } else if advice_config.is_some() {
+            "git config format-staged.advice".to_string()
+        } else {
+            "default".to_string()
+        };
+        let jobs = cli.jobs.or(config_jobs).unwrap_or(1);
+        dump_config(&settings(
+            &cli,
+            &matches,
+            Resolved {
+                command: (&command, command_source),
+                files: (&files, files_source),
+                globs: (config.globs.as_ref(), source(false, config.globs.is_some())),
+                excludes: (
+                    config.excludes.as_ref(),
+                    source(false, config.excludes.is_some()),
+                ),
+                jobs: (jobs, jobs_source),
+                index_only: (index_only, index_only_source),
+                no_working_tree: (no_working_tree, no_working_tree_source),
+                allow_unstaged: (allow_unstaged, allow_unstaged_source),
+                color: color_setting,
+                advice: (advice, advice_source),
+                config_path: config_path.as_ref(),
+            },
+        ));
+        return;
+    }
+
+    let own_formatters = cli
+        .types
+        .iter()
+        .any(|file_type| file_type.command.is_some());
+    if command.is_empty() && !own_formatters {
+        return;
+    }
+    // With `--paths-from-diff` or `globs`, there's nothing to format when nothing is
+    // staged. Otherwise files should have been given.
+    if files.is_empty() && !from_diff {
+        error!("no files to format");
+        hint!(
+            "give the files to format before the --, or as `files` or `globs` in the config file"
+        );
+        exit(ExitCode::NoFiles);
+    }
+
+    let resolve_commands = |command: &[String]| -> Vec<Vec<String>> {
+        command
+            .split(|arg| arg == "--then")
+            .map(|command| {
+                if command.is_empty() {
+                    error!("no formatter specified around --then");
+                    exit(ExitCode::Usage);
+                }
+                let (env, program) = split_env(command);
+                if program.is_empty() {
+                    error!("no formatter specified after the environment variables");
+                    exit(ExitCode::Usage);
+                }
+                let mut command = command[..env.len()].to_vec();
+                command.extend(check_script(
+                    &resolve_program(program, &repo_path, &dir_prefix),
+                    cli.use_shebang,
+                ));
+                check_program(&command);
+                command
+            })
+            .collect()
+    };
+    let commands: Vec<Vec<String>> = if command.is_empty() {
+        if let Some(file_type) = cli
+            .types
+            .iter()
+            .find(|file_type| file_type.command.is_none())
+        {
+            error!("no formatter specified for --type {}", file_type);
+            hint!(
+                "give the formatter after the --, or give the type its own with `--type {}=COMMAND`",
+                file_type
+            );
+            exit(ExitCode::Usage);
+        }
+        Vec::new()
+    } else {
+        resolve_commands(&command)
+    };
+    let commands: Vec<&[String]> = commands.iter().map(Vec::as_slice).collect();
+    // The formatters of the types that have their own, for `--type TYPE=COMMAND`.
+    let type_commands: Vec<(&FileType, Vec<Vec<String>>)> = cli
+        .types
+        .iter()
+        .filter_map(|file_type| {
+            let command = file_type.command.as_ref()?;
+            Some((&file_type.file_type, resolve_commands(command)))
+        })
+        .collect();
+    let type_commands: Vec<(&FileType, Vec<&[String]>)> = type_commands
+        .iter()
+        .map(|(file_type, commands)| (*file_type, commands.iter().map(Vec::as_slice).collect()))
+        .collect();
+    // Every formatter that might be run, for the checks of their arguments.
+    let all_commands: Vec<&[String]> = commands
+        .iter()
+        .chain(type_commands.iter().flat_map(|(_, commands)| commands))
+        .copied()
+        .collect();
+
+    if cli.stdin && index_only {
+        error!("--stdin can't be used with --index-only");
+        exit(ExitCode::Usage);
+    }
+    if cli.no_index_write && (index_only || no_working_tree) {
+        error!("--no-index-write needs the working tree to be updated");
+        exit(ExitCode::Usage);
+    }
+    if cli.lsp && index_only {
+        error!("--lsp can't be used with --index-only");
+        exit(ExitCode::Usage);
+    }
+    if cli.formatter_stdin
+        && all_commands
+            .iter()
+            .any(|command| !command.iter().any(|arg| arg.contains("{}")))
+    {
+        error!("--formatter-stdin needs `{{}}` in the formatter's arguments, for the file it writes to");
+        exit(ExitCode::Usage);
+    }
+    if cli.output_fifo && cfg!(not(unix)) {
+        error!("--output-fifo is only supported on Unix");
+        exit(ExitCode::Usage);
+    }
+    if cli.output_fifo
+        && all_commands
+            .iter()
+            .any(|command| !command.iter().any(|arg| arg.contains("{output}")))
+    {
+        error!("--output-fifo needs `{{output}}` in the formatter's arguments, for the pipe it writes to");
+        exit(ExitCode::Usage);
+    }
+    if cli.only_changed_lines
+        && all_commands.iter().any(|command| {
+            !command
+                .iter()
+                .any(|arg| arg.contains("{start}") || arg.contains("{end}"))
+        })
+    {
+        error!("--only-changed-lines needs `{{start}}` or `{{end}}` in the formatter's arguments, for the lines to format");
+        exit(ExitCode::Usage);
+    }
+    if cli.only_changed_lines && cli.passes > 1 {
+        error!("--passes can't be used with --only-changed-lines, because the changed lines move");
+        exit(ExitCode::Usage);
+    }
+    if cli.lsp
+        && (commands.len() > 1 || type_commands.iter().any(|(_, commands)| commands.len() > 1))
+    {
+        error!("--then can't be used with --lsp");
+        exit(ExitCode::Usage);
+    }
+    if let (Some(min), Some(max)) = (cli.min_file_size, cli.max_file_size) {
+        if min > max {
+            error!("--min-file-size can't be larger than --max-file-size");
+            exit(ExitCode::Usage);
+        }
+    }
+    if cli.staged_suffix.is_empty() || cli.staged_suffix == ".orig" {
+        error!("--staged-suffix can't be empty or `.orig`, which is used for backups");
+        exit(ExitCode::Usage);
+    }
+    if cli.temp_suffix.is_some() && !index_only && !no_working_tree {
+        error!("--temp-suffix can only be used with --index-only or --no-working-tree");
+        exit(ExitCode::Usage);
+    }
+
+    let options = Options {
+        update_working_tree: !no_working_tree && cli.out_index.is_none(),
+        dump_plan: cli.dump_plan,
+        allow_unstaged,
+        index_only,
+        temp_suffix: cli.temp_suffix,
+        staged_suffix: cli.staged_suffix,
+        stdin: cli.stdin,
+        formatter_stdin: cli.formatter_stdin,
+        output_fifo: cli.output_fifo.then(|| {
+            repo.path()
+                .join(format!("format-staged-{}.fifo", std::process::id()))
+        }),
+        stdin_filepath: cli.stdin_filepath,
+        lsp: cli.lsp,
+        verify_clean: cli.verify_clean,
+        treat_as_text: cli.treat_as_text,
+        min_file_size: cli.min_file_size,
+        max_file_size: cli.max_file_size,
+        encoding: cli.encoding,
+        types: cli
+            .types
+            .iter()
+            .map(|file_type| file_type.file_type.clone())
+            .collect(),
+        attr: cli.attr,
+        fail_on_reformat: cli.fail_on_reformat,
+        ignore_whitespace: cli.ignore_whitespace,
+        keep_line_endings: cli.keep_line_endings,
+        format: cli.format,
+        summary_only: cli.summary_only,
+        relative_to: cli.relative_to,
+        arg_max: cli.arg_max,
+        jobs: cli.jobs.or(config_jobs).unwrap_or(1),
+        jobs_per_extension: cli.jobs_per_extension,
+        chdir_file: cli.chdir_file,
+        only_changed_lines: cli.only_changed_lines,
+        passes: cli.passes,
+        cache: cli.cache,
+        known_formatted: cli
+            .known_formatted
+            .as_deref()
+            .map(load_known_formatted)
+            .unwrap_or_default(),
+        only: cli.only,
+        changed_vs_head: cli.changed_vs_head,
+        base: cli.base,
+        diff_head: cli.diff_head,
+        stat: cli.stat,
+        post_format: cli.post_format,
+        post_format_must_succeed: cli.post_format_must_succeed,
+        write_index: !cli.no_index_write,
+        out_index: cli.out_index,
+        sync: cli.sync,
+        verify_staged_unchanged: cli.verify_staged_unchanged,
+    };
+    // Only `--paths-from-diff` and `globs` get here without files, when nothing matching
+    // is staged.
+    if files.is_empty() {
+        trace!("nothing is staged, so there's nothing to format");
+        finish(&Report::default(), &options);
+        return;
+    }
+    let format = |files: &[PathBuf], commands: &[&[String]]| {
+        if cli.recurse_submodules {
+            format_with_submodules(&repo, &repo_path, &dir_prefix, files, commands, &options)
+        } else {
+            git_format_staged(&repo, &repo_path, &dir_prefix, files, commands, &options)
+        }
+    };
+    let result = if type_commands.is_empty() {
+        format(&files, &commands)
+    } else {
+        format_by_type(
+            &repo,
+            &dir_prefix,
+            &files,
+            &commands,
+            &type_commands,
+            format,
+        )
+    };
+    let report = result.unwrap_or_else(|err| {
+        error!("{}", err);
+        exit(ExitCode::Error);
+    });
+
+    // The commit is made before the run is reported, so that `--post-format` sees it.
+    if let Some(message) = &cli.message {
+        if let Err(err) = commit(&repo, message) {
+            error!("{}", err);
+            exit(ExitCode::Error);
+        }
+    }
+
+    finish(&report, &options);
+}
+
+/// Why `--commit` failed.
+pub(crate) enum CommitError {
+    /// Git failed.
+    Git(Error),
+
+    /// The index is the same as `HEAD`, e.g. because formatting undid every change.
+    NothingToCommit,
+}
+
+impl From<Error> for CommitError {
+    fn from(err: Error) -> CommitError {
+        CommitError::Git(err)
+    }
+}
+
+impl Display for CommitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommitError::Git(err) => write!(f, "failed to commit: {}", err),
+            CommitError::NothingToCommit => write!(f, "nothing to commit"),
+        }
+    }
+}
+
+/// Commit the index on top of `HEAD` with `message`, for `--commit`.
+pub(crate) fn commit(repo: &Repository, message: &str) -> Result<(), CommitError> {
+    let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(err) if err.code() == ErrorCode::UnbornBranch => None,
+        Err(err) => return Err(err.into()),
+    };
+    if parent
+        .as_ref()
+        .is_some_and(|parent| parent.tree_id() == tree.id())
+    {
+        return Err(CommitError::NothingToCommit);
+    }
+
+    let signature = repo.signature()?;
+    let parents: Vec<&Commit> = parent.iter().collect();
+    let id = repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )?;
+
+    let head = repo.head()?;
+    note!(
+        "[{} {}] {}",
+        head.shorthand().unwrap_or("HEAD"),
+        &id.to_string()[..7],
+        message.lines().next().unwrap_or_default()
+    );
+    Ok(())
+}
+
+/** Resolve a relative program path in `command` against the repository root, if it isn't
+found relative to the current directory.
+
+This lets hooks, which may be run from any subdirectory, refer to project-local tools
+such as `node_modules/.bin/prettier`.
+*/
+pub(crate) fn resolve_program(
+    command: &[String],
+    repo_path: &Path,
+    dir_prefix: &Path,
+) -> Vec<String> {
+    let program = Path::new(&command[0]);
+    if !command[0].contains(std::path::MAIN_SEPARATOR)
+        || program.is_absolute()
+        || program.exists()
+        || !repo_path.join(program).exists()
+    {
+        return command.to_vec();
+    }
+
+    let mut resolved = command.to_vec();
+    resolved[0] = match repo_path.join(program).into_os_string().into_string() {
+        Ok(program) => program,
+        // The command is made of strings, so a repository root that isn't valid UTF-8 is
+        // left out. The current directory is inside the repository, so this still works.
+        Err(_) => cwd_relative_path(dir_prefix, program)
+            .to_string_lossy()
+            .into_owned(),
+    };
+    resolved
+}
+
+/** Check that a formatting command given by path is executable.
+
+Scripts in a repository sometimes lose their executable bit. When `use_shebang` is set,
+such a script is run through the interpreter named on its `#!` line instead. Otherwise
+this exits with an error. The script's mode isn't changed, because that would show up
+as a change in the working tree.
+*/
+pub(crate) fn check_script(command: &[String], use_shebang: bool) -> Vec<String> {
+    let program = &command[0];
+    if !program.contains(std::path::MAIN_SEPARATOR) || is_executable(Path::new(program)) {
+        return command.to_vec();
+    }
+
+    if !use_shebang {
+        error!("{} is not executable", quote_path(program));
+        hint!("run `chmod +x` on it, or pass --use-shebang");
+        exit(ExitCode::Error);
+    }
+
+    let content = read_file(Path::new(program));
+    let interpreter = content
+        .strip_prefix(b"#!")
+        .and_then(|line| line.split(|&byte| byte == b'\n').next())
+        .map(|line| String::from_utf8_lossy(line).trim().to_string())
+        .filter(|line| !line.is_empty())
+        .unwrap_or_else(|| {
+            error!(
+                "{} is not executable, and doesn't start with a #! line",
+                quote_path(program)
+            );
+            exit(ExitCode::Error);
+        });
+
+    // Like the kernel, everything after the interpreter is passed as one argument.
+    let mut resolved: Vec<String> = match interpreter.split_once([' ', '\t']) {
+        Some((interpreter, arg)) => vec![interpreter.to_string(), arg.trim().to_string()],
+        None => vec![interpreter],
+    };
+    resolved.extend(command.iter().cloned());
+    resolved
+}
+
+/** Exit with [`ExitCode::FormatterNotFound`] if the program that runs `command` doesn't
+exist, so that nothing has to be cleaned up after trying to run it.
+
+A program given by path must exist, and one given by name must be found in `PATH`, or in
+the `PATH` that `command` sets for it.
+*/
+pub(crate) fn check_program(command: &[String]) {
+    let (env, command) = split_env(command);
+    let program = &command[0];
+    let path = env
+        .iter()
+        .rev()
+        .find(|(name, _)| *name == "PATH")
+        .map(|(_, path)| OsString::from(path));
+    let found = if program.contains(std::path::MAIN_SEPARATOR) {
+        Path::new(program).exists()
+    } else {
+        find_program(program, path).is_some()
+    };
+    if !found {
+        error!("formatter not found: {}", quote_path(program));
+        if !program.contains(std::path::MAIN_SEPARATOR) {
+            hint!("check that it's installed, and that its directory is in PATH");
+        }
+        exit(ExitCode::FormatterNotFound);
+    }
+}
+
+/// Look up the program named `program` in `path`, or in `PATH` if that's `None`.
+pub(crate) fn find_program(program: &str, path: Option<OsString>) -> Option<PathBuf> {
+    let path = path.or_else(|| std::env::var_os("PATH"))?;
+    // Windows also runs programs named without their extension.
+    #[cfg(windows)]
+    let extensions: Vec<String> = std::env::var("PATHEXT")
+        .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+        .split(';')
+        .map(str::to_string)
+        .chain([String::new()])
+        .collect();
+    #[cfg(not(windows))]
+    let extensions = [String::new()];
+    std::env::split_paths(&path).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|extension| dir.join(format!("{}{}", program, extension)))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Whether `path` is a file that can be executed. Paths that don't exist count as
+/// executable, so that running them reports the real error.
+pub(crate) fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        match std::fs::metadata(path) {
+            Ok(metadata) => !metadata.is_file() || metadata.permissions().mode() & 0o111 != 0,
+            Err(_) => true,
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        true
+    }
+}
+
+/// Read the blob IDs listed in a `--known-formatted` file.
+pub(crate) fn load_known_formatted(path: &Path) -> HashSet<Oid> {
+    let source = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        error!("failed to read {}: {}", quote_path(path), err);
+        exit(ExitCode::Error);
+    });
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            // `Oid::from_str` would take an abbreviated ID as a whole one.
+            let id = Some(line)
+                .filter(|line| line.len() == 40)
+                .and_then(|line| Oid::from_str(line).ok());
+            id.unwrap_or_else(|| {
+                error!(
+                    "invalid --known-formatted file {}: line {}: `{}` isn't a blob ID",
+                    quote_path(path),
+                    i + 1,
+                    line
+                );
+                exit(ExitCode::Error);
+            })
+        })
+        .collect()
+}
+
+/** Read the configuration file.
+
+This is `config_path` if it was given, or otherwise [`CONFIG_FILE_NAME`] in the
+repository root if that exists.
+*/
+pub(crate) fn load_config(
+    repo_path: &Path,
+    config_path: Option<&Path>,
+) -> (Config, Option<PathBuf>) {
+    let config_path = match config_path {
+        Some(config_path) => config_path.to_path_buf(),
+        None => {
+            let config_path = repo_path.join(CONFIG_FILE_NAME);
+            if !config_path.exists() {
+                return (Config::default(), None);
+            }
+            config_path
+        }
+    };
+
+    let source = std::fs::read_to_string(&config_path).unwrap_or_else(|err| {
+        error!("failed to read {}: {}", quote_path(&config_path), err);
+        exit(ExitCode::Error);
+    });
+    let config = Config::parse(&source).unwrap_or_else(|err| {
+        error!("invalid config file {}: {}", quote_path(&config_path), err);
+        exit(ExitCode::Error);
+    });
+    (config, Some(config_path))
+}
+
+/// A resolved setting, and where it came from.
+type Setting<T> = (T, String);
+
+/// The settings that don't come from the command line alone, as they were resolved, for
+/// `--dump-config`.
+pub(crate) struct Resolved<'a> {
+    command: Setting<&'a [String]>,
+    files: Setting<&'a [PathBuf]>,
+    globs: Setting<Option<&'a Vec<String>>>,
+    excludes: Setting<Option<&'a Vec<String>>>,
+    jobs: Setting<usize>,
+    index_only: Setting<bool>,
+    no_working_tree: Setting<bool>,
+    allow_unstaged: Setting<bool>,
+    color: Setting<String>,
+    advice: Setting<bool>,
+    config_path: Option<&'a PathBuf>,
+}
+
+/// Every setting, with its value and where it came from, for `--dump-config`. `matches`
+/// are the command line's, to tell which options were given on it rather than left at
+/// their defaults.
+pub(crate) fn settings(
+    cli: &Cli,
+    matches: &ArgMatches,
+    resolved: Resolved,
+) -> Vec<(&'static str, Option<String>, String)> {
+    let Resolved {
+        command,
+        files,
+        globs,
+        excludes,
+        jobs,
+        index_only,
+        no_working_tree,
+        allow_unstaged,
+        color,
+        advice,
+        config_path,
+    } = resolved;
+    let string_array = |values: &mut dyn Iterator<Item = String>| {
+        let values: Vec<String> = values.map(|value| json_string(&value)).collect();
+        format!("[{}]", values.join(", "))
+    };
+    let display_array = |values: &mut dyn Iterator<Item = &dyn Display>| {
+        string_array(&mut values.map(|value| value.to_string()))
+    };
+    let path = |path: Option<&PathBuf>| path.map(|path| json_string(&path.to_string_lossy()));
+    let string = |value: &Option<String>| value.as_deref().map(json_string);
+    let number = |value: Option<u64>| value.map(|value| value.to_string());
+    let seconds = |duration: Duration| duration.as_secs_f64().to_string();
+    let env_source = |name: &str| {
+        if std::env::var_os(name).is_some() {
+            format!("environment {}", name)
+        } else {
+            "default".to_string()
+        }
+    };
+    let cli_source = |id: &str| match matches.value_source(id) {
+        Some(ValueSource::CommandLine) => "command line".to_string(),
+        _ => "default".to_string(),
+    };
+    let mut jobs_per_extension =
+        cli.jobs_per_extension
+            .iter()
+            .map(|(extensions, jobs)| match extensions.as_slice() {
+                [extension] => format!("{}={}", extension, jobs),
+                _ => format!("{{{}}}={}", extensions.join(","), jobs),
+            });
+    let config_patterns = |patterns: Option<&Vec<String>>| {
+        Some(string_array(&mut patterns.into_iter().flatten().cloned()))
+    };
+    // Every option except `--dump-config` itself, and `--shell-command`, which is
+    // shown as the `command` it's split into.
+    vec![
+        (
+            "command",
+            Some(string_array(&mut command.0.iter().cloned())),
+            command.1,
+        ),
+        (
+            "files",
+            Some(string_array(
+                &mut files
+                    .0
+                    .iter()
+                    .map(|file| file.to_string_lossy().into_owned()),
+            )),
+            files.1,
+        ),
+        ("globs", config_patterns(globs.0), globs.1),
+        ("excludes", config_patterns(excludes.0), excludes.1),
+        (
+            "chdir",
+            Some(string_array(
+                &mut cli
+                    .chdir
+                    .iter()
+                    .map(|dir| dir.to_string_lossy().into_owned()),
+            )),
+            cli_source("chdir"),
+        ),
+        (
+            "paths-from-diff",
+            Some(cli.paths_from_diff.to_string()),
+            cli_source("paths_from_diff"),
+        ),
+        (
+            "recurse-submodules",
+            Some(cli.recurse_submodules.to_string()),
+            cli_source("recurse_submodules"),
+        ),
+        (
+            "strip-components",
+            number(cli.strip_components.map(|n| n as u64)),
+            cli_source("strip_components"),
+        ),
+        ("jobs", Some(jobs.0.to_string()), jobs.1),
+        (
+            "jobs-per-extension",
+            Some(string_array(&mut jobs_per_extension)),
+            cli_source("jobs_per_extension"),
+        ),
+        ("index-only", Some(index_only.0.to_string()), index_only.1),
+        (
+            "temp-suffix",
+            string(&cli.temp_suffix),
+            cli_source("temp_suffix"),
+        ),
+        (
+            "staged-suffix",
+            Some(json_string(&cli.staged_suffix)),
+            cli_source("staged_suffix"),
+        ),
+        (
+            "no-working-tree",
+            Some(no_working_tree.0.to_string()),
+            no_working_tree.1,
+        ),
+        (
+            "allow-unstaged",
+            Some(allow_unstaged.0.to_string()),
+            allow_unstaged.1,
+        ),
+        ("stdin", Some(cli.stdin.to_string()), cli_source("stdin")),
+        (
+            "formatter-stdin",
+            Some(cli.formatter_stdin.to_string()),
+            cli_source("formatter_stdin"),
+        ),
+        (
+            "output-fifo",
+            Some(cli.output_fifo.to_string()),
+            cli_source("output_fifo"),
+        ),
+        (
+            "stdin-filepath",
+            Some(json_string(&value_name(cli.stdin_filepath))),
+            cli_source("stdin_filepath"),
+        ),
+        ("lsp", Some(cli.lsp.to_string()), cli_source("lsp")),
+        (
+            "dump-plan",
+            path(cli.dump_plan.as_ref()),
+            cli_source("dump_plan"),
+        ),
+        (
+            "dump-staged-blob",
+            path(cli.dump_staged_blob.as_ref()),
+            cli_source("dump_staged_blob"),
+        ),
+        ("dump-to", path(cli.dump_to.as_ref()), cli_source("dump_to")),
+        (
+            "verify-clean",
+            Some(cli.verify_clean.to_string()),
+            cli_source("verify_clean"),
+        ),
+        (
+            "use-shebang",
+            Some(cli.use_shebang.to_string()),
+            cli_source("use_shebang"),
+        ),
+        (
+            "treat-as-text",
+            Some(display_array(
+                &mut cli
+                    .treat_as_text
+                    .iter()
+                    .map(|pattern| pattern as &dyn Display),
+            )),
+            cli_source("treat_as_text"),
+        ),
+        (
+            "min-file-size",
+            number(cli.min_file_size),
+            cli_source("min_file_size"),
+        ),
+        (
+            "max-file-size",
+            number(cli.max_file_size),
+            cli_source("max_file_size"),
+        ),
+        (
+            "encoding",
+            Some(json_string(&value_name(cli.encoding))),
+            cli_source("encoding"),
+        ),
+        (
+            "type",
+            Some(display_array(
+                &mut cli.types.iter().map(|file_type| file_type as &dyn Display),
+            )),
+            cli_source("types"),
+        ),
+        (
+            "attr",
+            Some(display_array(
+                &mut cli.attr.iter().map(|attribute| attribute as &dyn Display),
+            )),
+            cli_source("attr"),
+        ),
+        (
+            "fail-on-reformat",
+            Some(cli.fail_on_reformat.to_string()),
+            cli_source("fail_on_reformat"),
+        ),
+        (
+            "exit-zero",
+            Some(cli.exit_zero.to_string()),
+            cli_source("exit_zero"),
+        ),
+        (
+            "ignore-whitespace",
+            Some(cli.ignore_whitespace.to_string()),
+            cli_source("ignore_whitespace"),
+        ),
+        (
+            "keep-line-endings",
+            Some(cli.keep_line_endings.to_string()),
+            cli_source("keep_line_endings"),
+        ),
+        (
+            "print-command",
+            Some(cli.print_command.to_string()),
+            cli_source("print_command"),
+        ),
+        (
+            "format",
+            Some(json_string(&value_name(cli.format))),
+            cli_source("format"),
+        ),
+        (
+            "capture-output",
+            Some(cli.capture_output.to_string()),
+            cli_source("capture_output"),
+        ),
+        (
+            "summary-only",
+            Some(cli.summary_only.to_string()),
+            cli_source("summary_only"),
+        ),
+        (
+            "report-file",
+            path(cli.report_file.as_ref()),
+            cli_source("report_file"),
+        ),
+        (
+            "relative-to",
+            Some(json_string(&value_name(cli.relative_to))),
+            cli_source("relative_to"),
+        ),
+        (
+            "arg-max",
+            Some(cli.arg_max.to_string()),
+            cli_source("arg_max"),
+        ),
+        (
+            "chdir-file",
+            Some(cli.chdir_file.to_string()),
+            cli_source("chdir_file"),
+        ),
+        (
+            "only-changed-lines",
+            Some(cli.only_changed_lines.to_string()),
+            cli_source("only_changed_lines"),
+        ),
+        ("passes", Some(cli.passes.to_string()), cli_source("passes")),
+        ("cache", Some(cli.cache.to_string()), cli_source("cache")),
+        (
+            "known-formatted",
+            path(cli.known_formatted.as_ref()),
+            cli_source("known_formatted"),
+        ),
+        (
+            "only",
+            cli.only.map(|only| json_string(&value_name(only))),
+            cli_source("only"),
+        ),
+        (
+            "changed-vs-head",
+            Some(cli.changed_vs_head.to_string()),
+            cli_source("changed_vs_head"),
+        ),
+        ("base", string(&cli.base), cli_source("base")),
+        (
+            "diff-head",
+            Some(cli.diff_head.to_string()),
+            cli_source("diff_head"),
+        ),
+        ("stat", Some(cli.stat.to_string()), cli_source("stat")),
+        (
+            "post-format",
+            string(&cli.post_format),
+            cli_source("post_format"),
+        ),
+        (
+            "post-format-must-succeed",
+            Some(cli.post_format_must_succeed.to_string()),
+            cli_source("post_format_must_succeed"),
+        ),
+        (
+            "no-index-write",
+            Some(cli.no_index_write.to_string()),
+            cli_source("no_index_write"),
+        ),
+        ("commit", Some(cli.commit.to_string()), cli_source("commit")),
+        ("message", string(&cli.message), cli_source("message")),
+        ("sync", Some(cli.sync.to_string()), cli_source("sync")),
+        (
+            "verify-staged-unchanged",
+            Some(cli.verify_staged_unchanged.to_string()),
+            cli_source("verify_staged_unchanged"),
+        ),
+        (
+            "deadline",
+            cli.deadline.map(seconds),
+            cli_source("deadline"),
+        ),
+        (
+            "kill-signal",
+            Some(json_string(&cli.kill_signal)),
+            cli_source("kill_signal"),
+        ),
+        (
+            "kill-grace",
+            Some(seconds(cli.kill_grace)),
+            cli_source("kill_grace"),
+        ),
+        ("color", Some(json_string(&color.0)), color.1),
+        ("advice", Some(advice.0.to_string()), advice.1),
+        ("config", path(config_path), cli_source("config")),
+        (
+            "index-file",
+            path(cli.index_file.as_ref()),
+            cli_source("index_file"),
+        ),
+        (
+            "out-index",
+            path(cli.out_index.as_ref()),
+            cli_source("out_index"),
+        ),
+        (
+            "trace",
+            Some(output::TRACE.load(Ordering::Relaxed).to_string()),
+            env_source(TRACE_VAR),
+        ),
+        (
+            "keep-temp",
+            Some(KEEP_TEMP.load(Ordering::Relaxed).to_string()),
+            env_source(KEEP_TEMP_VAR),
+        ),
+    ]
+}
+
+/// Print each option's `key`, `value` and where it came from, for `--dump-config`. The
+/// lines use the config file's syntax, with the source in a comment. An option without a
+/// value is commented out.
+pub(crate) fn dump_config(settings: &[(&str, Option<String>, String)]) {
+    let lines: Vec<String> = settings
+        .iter()
+        .map(|(key, value, _)| match value {
+            Some(value) => format!("{} = {}", key, value),
+            None => format!("# {} is not set", key),
+        })
+        .collect();
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (line, (_, _, source)) in lines.iter().zip(settings) {
+        data!("{:width$}  # {}", line, source, width = width);
+    }
+}
+
+/// The name of `value`, as it's given on the command line.
+pub(crate) fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Resolve all symlinks in `path`, which is `what`, or exit with an error.
+pub(crate) fn canonicalize(path: &Path, what: &str) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|err| {
+        error!("couldn't resolve {}, {}: {}", what, quote_path(&path), err);
+        exit(ExitCode::Error);
+    })
+}
@@ -0,0 +1,351 @@
+//! Reading staged files out of the index, and writing formatted files back into it or
+//! into a separate `--out-index`.
+
+use std::{
+    borrow::Cow,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use git2::{
+    build::TreeUpdateBuilder, ApplyLocation, ApplyOptions, DiffOptions, Error, FileMode, Index,
+    IndexEntry, IndexTime, ObjectType, Oid, Repository, Tree,
+};
+
+use crate::{
+    encoding::Encoding, exit, output, path_from_bytes, quote_path, repo_relative_path, write_file,
+    ExitCode,
+};
+
+/** Stage the versions of `files` in `formatted_tree`, and write the index.
+
+Only the entries for `files` are changed, so that everything else in the index (such as
+`git add --intent-to-add` entries) is kept as it is.
+
+The index is updated all at once: every new entry is built before any is added, and if
+adding one fails, the changes already made in memory are thrown away by reading the index
+back from disk. Either all of `files` are staged or the index file is left alone.
+*/
+pub(crate) fn update_index(
+    index: &mut Index,
+    formatted_tree: &Tree,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+) -> Result<(), Error> {
+    let entries = files
+        .iter()
+        .map(|file| {
+            let path = repo_relative_path(dir_prefix, file);
+            let mut entry = index
+                .get_path(&path, 0)
+                .ok_or_else(|| index_changed(file))?;
+
+            entry.id = formatted_tree.get_path(&path)?.id();
+
+            // The cached file information no longer describes the staged content, so
+            // Git has to look at the working tree file again.
+            entry.ctime = IndexTime::new(0, 0);
+            entry.mtime = IndexTime::new(0, 0);
+            entry.file_size = 0;
+
+            Ok(entry)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let result = entries.iter().try_for_each(|entry| index.add(entry));
+    if let Err(err) = result {
+        // Discard the entries that were added before the failure.
+        index.read(true)?;
+        return Err(err);
+    }
+    // libgit2 writes the index to a lock file and renames it into place, so a failure
+    // here leaves the old index file intact.
+    index.write()
+}
+
+/// Copy the file of `index` to `path`, for [`Options::out_index`](crate::options::Options::out_index), and open the copy.
+pub(crate) fn copy_index(index: &Index, path: &Path) -> Result<Index, Error> {
+    let index_path = index
+        .path()
+        .ok_or_else(|| Error::from_str("the index has no file to copy"))?;
+    std::fs::copy(index_path, path).map_err(|err| {
+        Error::from_str(&format!("failed to write {}: {}", quote_path(path), err))
+    })?;
+    Index::open(path)
+}
+
+/// Flush `path` to disk, along with the directory entry for it, since it was just renamed
+/// into place.
+pub(crate) fn sync_file(path: &Path) -> std::io::Result<()> {
+    std::fs::File::open(path)?.sync_all()?;
+    // Directories can't be opened as files on Windows, and don't need to be synced.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/** The files in `files` whose formatting changes, from `index_tree` to `formatted_tree`,
+can't be applied to the working tree on their own.
+
+If the changes only conflict when they're applied together, every file with changes is
+counted.
+*/
+pub(crate) fn conflicting_files<'a>(
+    repo: &Repository,
+    index_tree: &Tree,
+    formatted_tree: &Tree,
+    dir_prefix: &Path,
+    files: &'a [PathBuf],
+) -> Result<Vec<&'a PathBuf>, Error> {
+    let mut changed = Vec::new();
+    let mut conflicting = Vec::new();
+    for file in files {
+        let path = repo_relative_path(dir_prefix, file);
+        if index_tree.get_path(&path)?.id() == formatted_tree.get_path(&path)?.id() {
+            continue;
+        }
+        changed.push(file);
+
+        let diff = repo.diff_tree_to_tree(
+            Some(index_tree),
+            Some(formatted_tree),
+            Some(
+                DiffOptions::new()
+                    .context_lines(0)
+                    .force_text(true)
+                    .pathspec(&path)
+                    .disable_pathspec_match(true),
+            ),
+        )?;
+        if repo
+            .apply(
+                &diff,
+                ApplyLocation::WorkDir,
+                Some(ApplyOptions::new().check(true)),
+            )
+            .is_err()
+        {
+            conflicting.push(file);
+        }
+    }
+    Ok(if conflicting.is_empty() {
+        changed
+    } else {
+        conflicting
+    })
+}
+
+/** The files in `files` whose contents differ between `index_tree` and `formatted_tree`.
+
+When `ignore_whitespace` is set, files whose only changes are to whitespace don't count.
+*/
+pub(crate) fn reformatted_files<'a>(
+    repo: &Repository,
+    index_tree: &Tree,
+    formatted_tree: &Tree,
+    dir_prefix: &Path,
+    files: &'a [PathBuf],
+    ignore_whitespace: bool,
+) -> Result<Vec<&'a PathBuf>, Error> {
+    let mut reformatted = Vec::new();
+    for file in files {
+        let path = repo_relative_path(dir_prefix, file);
+        let staged_id = index_tree.get_path(&path)?.id();
+        let formatted_id = formatted_tree.get_path(&path)?.id();
+        if staged_id == formatted_id {
+            continue;
+        }
+
+        if ignore_whitespace
+            && without_whitespace(repo.find_blob(staged_id)?.content())
+                == without_whitespace(repo.find_blob(formatted_id)?.content())
+        {
+            continue;
+        }
+
+        reformatted.push(file);
+    }
+    Ok(reformatted)
+}
+
+/// The lines of `content` with all whitespace removed, leaving out blank lines.
+pub(crate) fn without_whitespace(content: &[u8]) -> Vec<Vec<u8>> {
+    content
+        .split(|&byte| byte == b'\n')
+        .map(|line| {
+            line.iter()
+                .copied()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .collect::<Vec<u8>>()
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/** The paths, relative to the repository root, of the files that are added, modified,
+renamed, copied or changed in type in the index compared to `HEAD`, like
+`git diff --cached --name-only --diff-filter=AMRCT`.
+
+Renames and copies are detected as Git does by default, and are reported by their new
+paths.
+*/
+pub(crate) fn staged_diff_paths(repo: &Repository) -> Result<Vec<PathBuf>, Error> {
+    let head_tree = head_tree(repo)?;
+    let mut diff = repo.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+    diff.find_similar(None)?;
+
+    let mut paths = Vec::new();
+    for delta in diff.deltas() {
+        match delta.status() {
+            git2::Delta::Added
+            | git2::Delta::Modified
+            | git2::Delta::Renamed
+            | git2::Delta::Copied
+            | git2::Delta::Typechange => {
+                if let Some(path) = delta.new_file().path_bytes() {
+                    paths.push(path_from_bytes(path));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(paths)
+}
+
+/// The tree of `HEAD`, or `None` before the first commit.
+pub(crate) fn head_tree(repo: &Repository) -> Result<Option<Tree<'_>>, Error> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_tree()?)),
+        Err(err) if err.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// View the current index as a [`Tree`].
+pub(crate) fn get_index_tree(repo: &Repository) -> Result<Tree<'_>, Error> {
+    let oid = repo.index()?.write_tree()?;
+    repo.find_tree(oid)
+}
+
+/** Create a [`Tree`], based on `index_tree`, but including the new contents of files
+in `files` that have changed on disk.
+
+The new contents of each file are read from the corresponding path in `format_paths`.
+*/
+pub(crate) fn build_formatted_tree<'a>(
+    repo: &'a Repository,
+    index_tree: &Tree,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    formatted: &[Vec<u8>],
+) -> Result<Tree<'a>, Error> {
+    let mut tree_builder = TreeUpdateBuilder::new();
+
+    for (file, content) in files.iter().zip(formatted) {
+        let path = repo_relative_path(dir_prefix, file);
+        let blob_oid = repo.blob(content)?;
+        let file_mode =
+            if index_tree.get_path(&path)?.filemode() == i32::from(FileMode::BlobExecutable) {
+                FileMode::BlobExecutable
+            } else {
+                FileMode::Blob
+            };
+        tree_builder.upsert(path, blob_oid, file_mode);
+    }
+
+    let post_tree_oid = tree_builder.create_updated(repo, index_tree)?;
+    repo.find_tree(post_tree_oid)
+}
+
+/// Write the staged content of `file` to `out`, or to stdout.
+pub(crate) fn dump_staged_blob(
+    repo: &Repository,
+    dir_prefix: &Path,
+    file: &Path,
+    out: Option<&Path>,
+) {
+    let content = repo
+        .index()
+        .and_then(|index| match get_staged(&index, dir_prefix, file) {
+            Some(index_entry) => Ok(Some(repo.find_blob(index_entry.id)?)),
+            None => Ok(None),
+        })
+        .unwrap_or_else(|err| {
+            error!("{}", err);
+            exit(ExitCode::Error);
+        });
+    let Some(blob) = content else {
+        error!("{} is not a staged file", quote_path(file));
+        exit(ExitCode::NotStaged);
+    };
+
+    match out {
+        Some(out) => write_file(out, blob.content()),
+        None => {
+            if let Err(err) = output::data().write_all(blob.content()) {
+                error!("failed to write to stdout: {}", err);
+                exit(ExitCode::Error);
+            }
+        }
+    }
+}
+
+/// The staged `content` of `file`, converted from `encoding` to UTF-8. [`classify_file`](crate::classify::classify_file) has
+/// checked that it's valid in `encoding`, unless the index has changed since.
+pub(crate) fn decode_staged<'a>(
+    encoding: Encoding,
+    file: &Path,
+    content: &'a [u8],
+) -> Result<Cow<'a, [u8]>, Error> {
+    encoding.decode(content).ok_or_else(|| index_changed(file))
+}
+
+/// A hash of the index file on disk, to tell whether it has been changed. It's `None` if
+/// there's no index file.
+pub(crate) fn index_fingerprint(index: &Index) -> Option<Oid> {
+    let content = std::fs::read(index.path()?).ok()?;
+    Oid::hash_object(ObjectType::Blob, &content).ok()
+}
+
+/// Check that the index file is the same as when `snapshot` was taken by
+/// [`index_fingerprint`].
+pub(crate) fn check_index_unchanged(repo: &Repository, snapshot: Option<Oid>) -> Result<(), Error> {
+    if index_fingerprint(&repo.index()?) != snapshot {
+        return Err(Error::from_str(
+            "the index was changed by another process while the files were being formatted, so nothing was staged",
+        ));
+    }
+    Ok(())
+}
+
+/** The index entry for `file`, which [`check_files_staged`](crate::classify::check_files_staged) found to be staged.
+
+Another process can change the index while the formatters run, so this is an error
+rather than a bug if the entry has gone.
+*/
+pub(crate) fn staged_entry(
+    index: &Index,
+    dir_prefix: &Path,
+    file: &Path,
+) -> Result<IndexEntry, Error> {
+    get_staged(index, dir_prefix, file).ok_or_else(|| index_changed(file))
+}
+
+/// The error for when the staged version of `file` isn't what it was when it was checked.
+pub(crate) fn index_changed(file: &Path) -> Error {
+    Error::from_str(&format!(
+        "the staged version of {} changed while git-format-staged was running; was the index changed by another process?",
+        quote_path(file)
+    ))
+}
+
+pub(crate) fn get_staged(index: &Index, dir_prefix: &Path, file: &Path) -> Option<IndexEntry> {
+    let path = repo_relative_path(dir_prefix, file);
+    if path.as_os_str().is_empty() {
+        // The repository root, which git2 refuses to look up.
+        return None;
+    }
+    index.get_path(&path, 0)
+}
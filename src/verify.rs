@@ -0,0 +1,275 @@
+//! Checking that formatting is stable, for `--verify-staged` and `--verify-clean`.
+
+use std::path::{Path, PathBuf};
+
+use git2::{Error, Index, Repository, Tree};
+
+use crate::{
+    cli::OutputFormat,
+    commands::{filter_commands, run_commands, CommandError},
+    exit,
+    options::Options,
+    quote_path, read_file, repo_relative_path,
+    staging::{decode_staged, staged_entry},
+    workdir::{prepare_temp_dir, remove_temp_dir, temp_dir_paths},
+    ExitCode,
+};
+
+/** Check that the index on disk has the `formatted` content of each of `files`, and the
+same file mode as in `index_tree`, for [`Options::verify_staged_unchanged`].
+
+Exits after listing every file that doesn't.
+*/
+pub(crate) fn verify_staged(
+    repo: &Repository,
+    index_tree: &Tree,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    formatted: &[Vec<u8>],
+    options: &Options,
+) -> Result<(), Error> {
+    let mut index = match &options.out_index {
+        Some(out_index) => Index::open(out_index)?,
+        None => repo.index()?,
+    };
+    index.read(true)?;
+
+    let mismatched = staged_mismatches(repo, &index, index_tree, dir_prefix, files, formatted)?;
+    if !mismatched.is_empty() {
+        error!("the staged files aren't what was formatted:");
+        for (file, problem) in &mismatched {
+            note!(
+                "  {} {}",
+                quote_path(&options.report_path(dir_prefix, file)),
+                problem
+            );
+        }
+        hint!("this is a bug in git-format-staged; check the staged files before committing");
+        exit(ExitCode::Error);
+    }
+    Ok(())
+}
+
+/// Each of `files` that `index` doesn't have the `formatted` content and `index_tree`'s
+/// file mode for, with what's wrong with it.
+fn staged_mismatches<'a>(
+    repo: &Repository,
+    index: &Index,
+    index_tree: &Tree,
+    dir_prefix: &Path,
+    files: &'a [PathBuf],
+    formatted: &[Vec<u8>],
+) -> Result<Vec<(&'a Path, &'static str)>, Error> {
+    let mut mismatched = Vec::new();
+    for (file, content) in files.iter().zip(formatted) {
+        let path = repo_relative_path(dir_prefix, file);
+        let problem = match index.get_path(&path, 0) {
+            None => Some("is no longer staged"),
+            Some(entry) if entry.mode != index_tree.get_path(&path)?.filemode() as u32 => {
+                Some("has a different file mode")
+            }
+            Some(entry) if repo.find_blob(entry.id)?.content() != content.as_slice() => {
+                Some("doesn't have the formatted content")
+            }
+            Some(_) => None,
+        };
+        if let Some(problem) = problem {
+            mismatched.push((file.as_path(), problem));
+        }
+    }
+    Ok(mismatched)
+}
+
+/** Check that the formatting commands don't change the newly staged files.
+
+The commands are run again on copies of the files as they're staged in `index`, which
+is the index that the formatted files were written to. The copies are made in
+`.git/format-staged-verify`, apart from the `--index-only` ones, which may have been kept
+by [`KEEP_TEMP_VAR`](crate::KEEP_TEMP_VAR). A warning is printed for each
+file that changes, because it means the formatter isn't idempotent.
+*/
+pub(crate) fn verify_clean(
+    repo: &Repository,
+    index: &Index,
+    repo_path: &Path,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    commands: &[&[String]],
+    options: &Options,
+) -> Result<(), Error> {
+    let capture_stderr = options.format == OutputFormat::Json;
+
+    let mut staged = Vec::new();
+    for file in files {
+        let blob = repo.find_blob(staged_entry(index, dir_prefix, file)?.id)?;
+        staged.push(decode_staged(options.encoding, file, blob.content())?.into_owned());
+    }
+
+    let reformatted: Result<Vec<Vec<u8>>, CommandError> = if options.stdin {
+        files
+            .iter()
+            .zip(&staged)
+            .map(|(file, staged)| {
+                let path = options.stdin_path(repo_path, dir_prefix, file);
+                filter_commands(
+                    commands,
+                    &path,
+                    staged,
+                    capture_stderr,
+                    options.filter_output(),
+                )
+                .map(|(content, _)| content)
+            })
+            .collect()
+    } else {
+        let temp_dir = repo.path().join("format-staged-verify");
+        let verify_paths =
+            temp_dir_paths(&temp_dir, dir_prefix, files, options.temp_suffix.as_deref())?;
+        prepare_temp_dir(
+            repo,
+            index,
+            dir_prefix,
+            files,
+            &temp_dir,
+            &verify_paths,
+            options.encoding,
+        )?;
+        let result = run_commands(commands, &verify_paths, None, capture_stderr, options)
+            .map(|_| verify_paths.iter().map(|path| read_file(path)).collect());
+        remove_temp_dir(&temp_dir);
+        result
+    };
+
+    let reformatted = match reformatted {
+        Ok(reformatted) => reformatted,
+        Err(err) => {
+            warning!("couldn't verify formatting: {}", err);
+            return Ok(());
+        }
+    };
+
+    for ((file, staged), reformatted) in files.iter().zip(&staged).zip(&reformatted) {
+        if reformatted != staged {
+            warning!(
+                "formatting {} again changes it; the formatter may be unstable",
+                quote_path(&options.report_path(dir_prefix, file))
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::{FileMode, IndexEntry, IndexTime};
+
+    use super::*;
+
+    /// A bare repository in a temporary directory. `name` must be unique among the tests.
+    fn temp_repo(name: &str) -> Repository {
+        let path = std::env::temp_dir()
+            .join("git-format-staged-tests")
+            .join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        Repository::init_bare(path).unwrap()
+    }
+
+    /// An in-memory index with each of `files` staged with its content and mode.
+    fn index_with(repo: &Repository, files: &[(&str, &[u8], FileMode)]) -> Index {
+        let mut index = Index::new().unwrap();
+        for &(path, content, mode) in files {
+            index
+                .add(&IndexEntry {
+                    ctime: IndexTime::new(0, 0),
+                    mtime: IndexTime::new(0, 0),
+                    dev: 0,
+                    ino: 0,
+                    mode: mode.into(),
+                    uid: 0,
+                    gid: 0,
+                    file_size: content.len() as u32,
+                    id: repo.blob(content).unwrap(),
+                    flags: 0,
+                    flags_extended: 0,
+                    path: path.as_bytes().to_vec(),
+                })
+                .unwrap();
+        }
+        index
+    }
+
+    /// The problems that [`staged_mismatches`] finds with `staged`, when `a.txt` and
+    /// `b.sh` were staged as they're given here and formatted to `a\n` and `b\n`.
+    fn mismatches(name: &str, staged: &[(&str, &[u8], FileMode)]) -> Vec<(PathBuf, &'static str)> {
+        let repo = temp_repo(name);
+        let original = [
+            ("a.txt", &b"a"[..], FileMode::Blob),
+            ("b.sh", b"b", FileMode::BlobExecutable),
+        ];
+        let tree_id = index_with(&repo, &original).write_tree_to(&repo).unwrap();
+        let index_tree = repo.find_tree(tree_id).unwrap();
+        let files = [PathBuf::from("a.txt"), PathBuf::from("b.sh")];
+        let formatted = [b"a\n".to_vec(), b"b\n".to_vec()];
+
+        let mismatched = staged_mismatches(
+            &repo,
+            &index_with(&repo, staged),
+            &index_tree,
+            Path::new(""),
+            &files,
+            &formatted,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(file, problem)| (file.to_path_buf(), problem))
+        .collect();
+        let _ = std::fs::remove_dir_all(repo.path());
+        mismatched
+    }
+
+    #[test]
+    fn formatted_files_match() {
+        let staged = [
+            ("a.txt", &b"a\n"[..], FileMode::Blob),
+            ("b.sh", b"b\n", FileMode::BlobExecutable),
+        ];
+        assert_eq!(mismatches("verify-match", &staged), []);
+    }
+
+    #[test]
+    fn corrupted_content_is_found() {
+        let staged = [
+            ("a.txt", &b"a\n"[..], FileMode::Blob),
+            ("b.sh", b"corrupted\n", FileMode::BlobExecutable),
+        ];
+        assert_eq!(
+            mismatches("verify-content", &staged),
+            [(PathBuf::from("b.sh"), "doesn't have the formatted content")]
+        );
+    }
+
+    #[test]
+    fn changed_mode_is_found() {
+        let staged = [
+            ("a.txt", &b"a\n"[..], FileMode::Blob),
+            ("b.sh", b"b\n", FileMode::Blob),
+        ];
+        assert_eq!(
+            mismatches("verify-mode", &staged),
+            [(PathBuf::from("b.sh"), "has a different file mode")]
+        );
+    }
+
+    #[test]
+    fn missing_file_is_found() {
+        let staged = [("b.sh", &b"corrupted\n"[..], FileMode::BlobExecutable)];
+        assert_eq!(
+            mismatches("verify-missing", &staged),
+            [
+                (PathBuf::from("a.txt"), "is no longer staged"),
+                (PathBuf::from("b.sh"), "doesn't have the formatted content")
+            ]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! Preparing the files that formatters run on, in the working tree or in a temporary
+//! directory, and putting the working tree back afterwards.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::SystemTime,
+};
+
+use git2::{Error, Index, Repository, Status, StatusOptions};
+
+use crate::{
+    cwd_relative_path,
+    encoding::Encoding,
+    exit,
+    options::Options,
+    path_from_bytes, quote_path, read_file, repo_relative_path,
+    staging::{decode_staged, staged_entry},
+    write_file, ExitCode, KEEP_TEMP, KEEP_TEMP_VAR,
+};
+
+/** The paths within `temp_dir` that mirror the locations of `files` in the repository,
+with `suffix` added to their names.
+
+Fails if two of the files would be copied to the same path, so that neither is staged
+with the other's content.
+*/
+pub(crate) fn temp_dir_paths(
+    temp_dir: &Path,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    suffix: Option<&str>,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut paths: Vec<PathBuf> = Vec::with_capacity(files.len());
+    let mut seen: HashMap<PathBuf, &Path> = HashMap::with_capacity(files.len());
+    for file in files {
+        let mut path = temp_dir.join(repo_relative_path(dir_prefix, file));
+        if let Some(suffix) = suffix {
+            path = with_suffix(&path, &format!(".{}", suffix.trim_start_matches('.')));
+        }
+        if let Some(other) = seen.insert(path.clone(), file) {
+            return Err(Error::from_str(&format!(
+                "{} and {} would both be formatted as {}",
+                quote_path(other),
+                quote_path(file),
+                quote_path(&path)
+            )));
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/** Copy the versions of `files` staged in `index` into `temp_dir`.
+
+Each file is written to its path in `format_paths`, which mirrors the file's location
+in the repository. Nothing in the working tree is touched.
+*/
+pub(crate) fn prepare_temp_dir(
+    repo: &Repository,
+    index: &Index,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    temp_dir: &Path,
+    format_paths: &[PathBuf],
+    encoding: Encoding,
+) -> Result<(), Error> {
+    // Everything is read before anything is written, so that if the index has changed
+    // since the files were checked, there's nothing to clean up.
+    let mut contents = Vec::with_capacity(files.len());
+    for file in files {
+        let blob = repo.find_blob(staged_entry(index, dir_prefix, file)?.id)?;
+        contents.push(decode_staged(encoding, file, blob.content())?.into_owned());
+    }
+
+    std::fs::create_dir(temp_dir).unwrap_or_else(|err| {
+        if err.kind() == std::io::ErrorKind::AlreadyExists {
+            error!(
+                "{} already exists; another git-format-staged may be running, or a previous run was interrupted",
+                quote_path(&temp_dir),
+            );
+        } else {
+            error!(
+                "failed to create {}: {}",
+                quote_path(&temp_dir),
+                err
+            );
+        }
+        exit(ExitCode::Error);
+    });
+
+    for (format_path, content) in format_paths.iter().zip(&contents) {
+        if let Some(parent) = Path::new(format_path).parent() {
+            std::fs::create_dir_all(parent).unwrap_or_else(|err| {
+                error!("failed to create {}: {}", quote_path(&parent), err);
+                remove_temp_dir(temp_dir);
+                exit(ExitCode::Error);
+            });
+        }
+        write_file(format_path, content);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn remove_temp_dir(temp_dir: &Path) {
+    if KEEP_TEMP.load(Ordering::Relaxed) {
+        note!(
+            "{} is set, so {} is kept",
+            KEEP_TEMP_VAR,
+            quote_path(temp_dir)
+        );
+        return;
+    }
+    trace!("removing {}", quote_path(temp_dir));
+    std::fs::remove_dir_all(temp_dir).unwrap_or_else(|err| {
+        warning!(
+            "the temporary directory {} could not be removed, please delete it manually: {}",
+            quote_path(&temp_dir),
+            err
+        );
+    })
+}
+
+/** Creates backups of existing files and copies data out of the index.
+
+* The contents of each file `file` to be formatted are kept in memory, and copied to
+  `file.orig` in case the program is interrupted.
+* The version of `file` in the index is written to the filesystem as `file.staged.orig`,
+  or with `staged_suffix` instead of `.staged.orig`.
+* The version of `file` in the index is also written over `file`, converted to UTF-8
+  from `encoding`.
+
+  This is the file that will be formatted.
+
+Returns the original contents of the files.
+*/
+pub(crate) fn prepare_workdir(
+    repo: &Repository,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    staged_suffix: &str,
+    encoding: Encoding,
+) -> Result<Vec<Vec<u8>>, Error> {
+    // Everything is read before anything is written, so that if the index has changed
+    // since the files were checked, the working tree is left alone.
+    let index = repo.index()?;
+    let mut blobs = Vec::with_capacity(files.len());
+    for file in files {
+        let blob = repo.find_blob(staged_entry(&index, dir_prefix, file)?.id)?;
+        decode_staged(encoding, file, blob.content())?;
+        blobs.push(blob);
+    }
+
+    let originals = backup_originals(files);
+
+    for (file, blob) in files.iter().zip(&blobs) {
+        let content = blob.content();
+        write_file(&with_suffix(file, staged_suffix), content);
+        write_file(file, &decode_staged(encoding, file, content)?);
+    }
+
+    Ok(originals)
+}
+
+/** Undo [`prepare_workdir`] after a failed formatting command.
+
+At this point the index hasn't been changed, so the temporary files can be removed.
+*/
+pub(crate) fn restore_workdir(files: &[PathBuf], originals: &[Vec<u8>], staged_suffix: &str) {
+    for (file, original) in files.iter().zip(originals) {
+        // Restores the file to its original unstaged version.
+        write_file(file, original);
+    }
+
+    remove_temp_files(
+        files
+            .iter()
+            .flat_map(|file| temp_file_paths(file, staged_suffix)),
+    );
+}
+
+/// The modification time of each of `files`, if it can be read.
+pub(crate) fn modified_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| {
+            std::fs::metadata(file)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
+}
+
+/// The tracked files that differ from the index in the working tree, relative to the
+/// repository root, along with their modification times.
+pub(crate) fn modified_tracked_files(
+    repo: &Repository,
+    repo_path: &Path,
+) -> Result<HashMap<PathBuf, Option<SystemTime>>, Error> {
+    let mut status_options = StatusOptions::new();
+    status_options
+        .include_untracked(false)
+        .include_ignored(false)
+        .exclude_submodules(true);
+    Ok(repo
+        .statuses(Some(&mut status_options))?
+        .iter()
+        .filter(|entry| {
+            entry
+                .status()
+                .intersects(Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_TYPECHANGE)
+        })
+        .map(|entry| {
+            let path = path_from_bytes(entry.path_bytes());
+            let modified = std::fs::symlink_metadata(repo_path.join(&path))
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            (path, modified)
+        })
+        .collect())
+}
+
+/** Warn about the tracked files other than `files` that the formatters changed.
+
+A file counts as changed if it differs from the index now but didn't in
+`modified_before`, from [`modified_tracked_files`], or if its modification time has
+changed since.
+*/
+pub(crate) fn warn_other_changes(
+    repo: &Repository,
+    repo_path: &Path,
+    dir_prefix: &Path,
+    files: &[PathBuf],
+    modified_before: &HashMap<PathBuf, Option<SystemTime>>,
+    options: &Options,
+) {
+    let Ok(modified_after) = modified_tracked_files(repo, repo_path) else {
+        return;
+    };
+    let targets: HashSet<PathBuf> = files
+        .iter()
+        .map(|file| repo_relative_path(dir_prefix, file))
+        .collect();
+    let mut changed: Vec<&PathBuf> = modified_after
+        .iter()
+        .filter(|(path, modified)| {
+            !targets.contains(*path) && modified_before.get(*path) != Some(modified)
+        })
+        .map(|(path, _)| path)
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+
+    changed.sort();
+    for path in changed {
+        let file = cwd_relative_path(dir_prefix, path);
+        warning!(
+            "the formatter also changed {}, which isn't being formatted, so the change wasn't staged",
+            quote_path(&options.report_path(dir_prefix, &file))
+        );
+    }
+    hint!("check whether the formatter is configured to format the whole project");
+}
+
+/// Set the modification time of `file` back to `modified`, after it has been rewritten
+/// with its original content. This is only for the benefit of build tools, so failures
+/// are ignored.
+pub(crate) fn restore_modified_time(file: &Path, modified: Option<SystemTime>) {
+    if let Some(modified) = modified {
+        let _ = std::fs::File::options()
+            .write(true)
+            .open(file)
+            .and_then(|file| file.set_modified(modified));
+    }
+}
+
+/// The temporary files that [`prepare_workdir`] creates for `file`.
+pub(crate) fn temp_file_paths(file: &Path, staged_suffix: &str) -> [PathBuf; 2] {
+    [with_suffix(file, staged_suffix), with_suffix(file, ".orig")]
+}
+
+/// `path` with `suffix` added to the end of its file name.
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// The default for [`Cli::staged_suffix`](crate::cli::Cli::staged_suffix).
+pub(crate) const DEFAULT_STAGED_SUFFIX: &str = ".staged.orig";
+
+/** Read the target files, and back each one up from `file` to `file.orig`.
+
+The `.orig` files need to stick around until the very end of the program, in case
+an unexpected failure happens.
+*/
+pub(crate) fn backup_originals(files: &[PathBuf]) -> Vec<Vec<u8>> {
+    files
+        .iter()
+        .map(|file| {
+            let content = read_file(file);
+            write_file(&with_suffix(file, ".orig"), &content);
+            content
+        })
+        .collect()
+}
+
+/** Remove temporary files.
+
+Failing to remove a temporary file isn't fatal. Any files that couldn't be removed are
+reported together, so that the user can delete them by hand.
+*/
+pub(crate) fn remove_temp_files(paths: impl IntoIterator<Item = PathBuf>) {
+    if KEEP_TEMP.load(Ordering::Relaxed) {
+        note!(
+            "{} is set, so these temporary files are kept:",
+            KEEP_TEMP_VAR
+        );
+        for path in paths {
+            note!("  {}", quote_path(&path));
+        }
+        return;
+    }
+    trace!("removing the temporary files");
+    let failures: Vec<(PathBuf, std::io::Error)> = paths
+        .into_iter()
+        .filter_map(|path| std::fs::remove_file(&path).err().map(|err| (path, err)))
+        .collect();
+
+    if !failures.is_empty() {
+        warning!(
+            "the following temporary files could not be removed, please delete them manually:"
+        );
+        for (path, err) in failures {
+            note!("  {}: {}", quote_path(&path), err);
+        }
+    }
+}
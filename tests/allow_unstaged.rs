@@ -0,0 +1,55 @@
+//! Tests for `--allow-unstaged`.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+/// A repository with `a.txt` committed.
+fn committed(name: &str) -> TempRepo {
+    let repo = TempRepo::new(name);
+    repo.stage("a.txt", "a\n");
+    repo.git(&["commit", "--quiet", "-m", "a"]);
+    repo
+}
+
+#[test]
+fn unstaged_file_is_staged_and_formatted() {
+    let repo = committed("allow-unstaged");
+    repo.write("a.txt", "b\na\n");
+
+    let mut args = vec!["--allow-unstaged", "a.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn partly_staged_file_is_staged_whole() {
+    let repo = committed("allow-unstaged-partly-staged");
+    repo.stage("a.txt", "c\na\n");
+    repo.write("a.txt", "c\nb\na\n");
+
+    let mut args = vec!["--allow-unstaged", "a.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\nc\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\nc\n");
+}
+
+#[test]
+fn nothing_is_staged_if_another_file_is_not_staged() {
+    let repo = committed("allow-unstaged-not-staged");
+    repo.write("a.txt", "b\na\n");
+    repo.write("untracked.txt", "b\na\n");
+
+    let mut args = vec!["--allow-unstaged", "a.txt", "untracked.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(repo.staged("a.txt"), "a\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
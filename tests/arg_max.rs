@@ -0,0 +1,74 @@
+//! Tests for `--arg-max`, which splits the files between several runs of a formatter.
+
+mod common;
+
+use common::TempRepo;
+
+/// A formatter that writes how many files it was given to `.git/runs`, and then sorts
+/// them.
+const COUNT_AND_SORT: &[&str] = &[
+    "sh",
+    "-c",
+    r#"echo $# >> .git/runs; for f; do sort -o "$f" "$f"; done"#,
+    "count",
+];
+
+/// The `--arg-max` that fits `command` and `paths` files named like `a.txt`, counting the
+/// NUL after each argument.
+fn arg_max_for(command: &[&str], paths: usize) -> String {
+    let command: usize = command.iter().map(|arg| arg.len() + 1).sum();
+    (command + paths * "a.txt\0".len()).to_string()
+}
+
+#[test]
+fn files_are_split_between_runs() {
+    let repo = TempRepo::new("arg-max");
+    let files = ["a.txt", "b.txt", "c.txt", "d.txt", "e.txt"];
+    for file in files {
+        repo.stage(file, "2\n1\n");
+    }
+
+    let arg_max = arg_max_for(COUNT_AND_SORT, 2);
+    let mut args = vec!["--arg-max", &arg_max];
+    args.extend(files);
+    args.push("--");
+    args.extend(COUNT_AND_SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.read(".git/runs"), "2\n2\n1\n");
+    for file in files {
+        assert_eq!(repo.staged(file), "1\n2\n");
+    }
+}
+
+#[test]
+fn files_that_fit_are_formatted_in_one_run() {
+    let repo = TempRepo::new("arg-max-fits");
+    repo.stage("a.txt", "2\n1\n");
+    repo.stage("b.txt", "2\n1\n");
+
+    let mut args = vec!["a.txt", "b.txt", "--"];
+    args.extend(COUNT_AND_SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.read(".git/runs"), "2\n");
+}
+
+#[test]
+fn failed_batch_stages_nothing() {
+    let repo = TempRepo::new("arg-max-failure");
+    repo.stage("a.txt", "2\n1\n");
+    repo.stage("b.txt", "2\n1\n");
+
+    // Each file gets a run of its own, and the second one fails.
+    let fail_second: &[&str] = &["sh", "-c", r#"sort -o "$1" "$1"; [ "$1" = a.txt ]"#, "fail"];
+    let arg_max = arg_max_for(fail_second, 1);
+    let mut args = vec!["--arg-max", &arg_max, "a.txt", "b.txt", "--"];
+    args.extend(fail_second);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(repo.staged("a.txt"), "2\n1\n");
+    assert_eq!(repo.staged("b.txt"), "2\n1\n");
+    assert_eq!(repo.read("a.txt"), "2\n1\n");
+}
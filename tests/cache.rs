@@ -0,0 +1,104 @@
+//! Tests for `--cache`, which skips staged files that are known to be formatted.
+
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+
+use common::TempRepo;
+
+/// A formatter that reports the version in the `version` file next to it, and otherwise
+/// writes the files it's given to `.git/runs` and sorts them.
+const FORMATTER: &str = r#"#!/bin/sh
+if [ "$1" = --version ]; then
+  cat "$(dirname "$0")/version"
+  exit
+fi
+echo "$@" >> .git/runs
+for f; do sort -o "$f" "$f"; done
+"#;
+
+/// A repository with the formatter at version 1 in `.git/bin/format`.
+fn with_formatter(name: &str) -> TempRepo {
+    let repo = TempRepo::new(name);
+    repo.write(".git/bin/format", FORMATTER);
+    repo.write(".git/bin/version", "1.0\n");
+    std::fs::set_permissions(
+        repo.join(".git/bin/format"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    repo
+}
+
+/// Format `files` with the cache, and return what the formatter was run on.
+fn run(repo: &TempRepo, files: &[&str], extra_args: &[&str]) -> String {
+    let _ = std::fs::remove_file(repo.join(".git/runs"));
+    let formatter = repo.join(".git/bin/format");
+    let mut args = vec!["--cache"];
+    args.extend(files);
+    args.push("--");
+    args.push(formatter.to_str().unwrap());
+    args.extend(extra_args);
+    repo.format_staged_ok(&args);
+    std::fs::read_to_string(repo.join(".git/runs")).unwrap_or_default()
+}
+
+#[test]
+fn unchanged_file_is_skipped_the_next_time() {
+    let repo = with_formatter("cache-hit");
+    repo.stage("a.txt", "a\nb\n");
+    repo.stage("b.txt", "b\na\n");
+
+    // `b.txt` is reformatted, so it isn't known to be formatted until the second run.
+    assert_eq!(run(&repo, &["a.txt", "b.txt"], &[]), "a.txt b.txt\n");
+    assert_eq!(run(&repo, &["a.txt", "b.txt"], &[]), "b.txt\n");
+    assert_eq!(run(&repo, &["a.txt", "b.txt"], &[]), "");
+    assert_eq!(repo.staged("b.txt"), "a\nb\n");
+    assert!(repo.join(".git/format-staged-cache").exists());
+}
+
+#[test]
+fn changed_content_is_formatted_again() {
+    let repo = with_formatter("cache-miss-content");
+    repo.stage("a.txt", "a\nb\n");
+    run(&repo, &["a.txt"], &[]);
+
+    repo.stage("a.txt", "a\nb\nc\n");
+
+    assert_eq!(run(&repo, &["a.txt"], &[]), "a.txt\n");
+}
+
+#[test]
+fn new_formatter_version_formats_again() {
+    let repo = with_formatter("cache-miss-version");
+    repo.stage("a.txt", "a\nb\n");
+    run(&repo, &["a.txt"], &[]);
+    assert_eq!(run(&repo, &["a.txt"], &[]), "");
+
+    repo.write(".git/bin/version", "2.0\n");
+
+    assert_eq!(run(&repo, &["a.txt"], &[]), "a.txt\n");
+    assert_eq!(run(&repo, &["a.txt"], &[]), "");
+}
+
+#[test]
+fn different_arguments_format_again() {
+    let repo = with_formatter("cache-miss-arguments");
+    repo.stage("a.txt", "a\nb\n");
+    run(&repo, &["a.txt"], &[]);
+
+    assert_eq!(run(&repo, &["a.txt"], &["--"]), "-- a.txt\n");
+}
+
+#[test]
+fn files_are_not_skipped_without_the_option() {
+    let repo = with_formatter("cache-off");
+    repo.stage("a.txt", "a\nb\n");
+    run(&repo, &["a.txt"], &[]);
+    assert_eq!(run(&repo, &["a.txt"], &[]), "");
+
+    let formatter = repo.join(".git/bin/format");
+    repo.format_staged_ok(&["a.txt", "--", formatter.to_str().unwrap()]);
+
+    assert_eq!(repo.read(".git/runs"), "a.txt\n");
+}
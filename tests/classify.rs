@@ -0,0 +1,129 @@
+//! Tests for [`classify`], which decides how each file is to be formatted.
+
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use common::TempRepo;
+use git2::Repository;
+use git_format_staged::{classify, FormatStagedError, TargetFile};
+
+/// `classify` looks files up relative to the current directory, which the tests share.
+static CWD: Mutex<()> = Mutex::new(());
+
+fn classify_in(repo: &TempRepo, file: &str) -> Result<Option<TargetFile>, FormatStagedError> {
+    let _cwd = CWD.lock().unwrap_or_else(|err| err.into_inner());
+    std::env::set_current_dir(&repo.path).unwrap();
+    let git_repo = Repository::open(&repo.path).unwrap();
+    classify(&git_repo, Path::new(""), file)
+}
+
+#[test]
+fn staged_file() {
+    let repo = TempRepo::new("classify-staged");
+    repo.stage("a.txt", "a\n");
+    assert_eq!(
+        classify_in(&repo, "a.txt").unwrap(),
+        Some(TargetFile::StagedOnly(PathBuf::from("a.txt")))
+    );
+}
+
+#[test]
+fn file_with_unstaged_changes() {
+    let repo = TempRepo::new("classify-unstaged");
+    repo.stage("a.txt", "a\n");
+    repo.write("a.txt", "a\nb\n");
+    assert_eq!(
+        classify_in(&repo, "a.txt").unwrap(),
+        Some(TargetFile::UnstagedAndStaged(PathBuf::from("a.txt")))
+    );
+}
+
+#[test]
+fn intent_to_add_is_skipped() {
+    let repo = TempRepo::new("classify-intent-to-add");
+    repo.write("a.txt", "a\n");
+    repo.git(&["add", "--intent-to-add", "a.txt"]);
+    assert_eq!(classify_in(&repo, "a.txt").unwrap(), None);
+}
+
+#[test]
+fn binary_file_is_skipped() {
+    let repo = TempRepo::new("classify-binary");
+    repo.stage("a.bin", "a\0b\n");
+    assert_eq!(classify_in(&repo, "a.bin").unwrap(), None);
+}
+
+#[test]
+fn submodule_is_skipped() {
+    let repo = TempRepo::new("classify-submodule");
+    repo.stage("a.txt", "a\n");
+    repo.git(&["commit", "--quiet", "-m", "a"]);
+    let commit = repo.git(&["rev-parse", "HEAD"]);
+    repo.git(&[
+        "update-index",
+        "--add",
+        "--cacheinfo",
+        &format!("160000,{},sub", commit.trim()),
+    ]);
+    assert_eq!(classify_in(&repo, "sub").unwrap(), None);
+}
+
+#[test]
+fn file_deleted_from_working_tree_is_skipped() {
+    let repo = TempRepo::new("classify-deleted");
+    repo.stage("a.txt", "a\n");
+    std::fs::remove_file(repo.join("a.txt")).unwrap();
+    assert_eq!(classify_in(&repo, "a.txt").unwrap(), None);
+}
+
+#[test]
+fn file_now_directory_is_skipped() {
+    let repo = TempRepo::new("classify-now-directory");
+    repo.stage("a", "a\n");
+    std::fs::remove_file(repo.join("a")).unwrap();
+    repo.write("a/b.txt", "b\n");
+    assert_eq!(classify_in(&repo, "a").unwrap(), None);
+}
+
+#[test]
+fn unstaged_file_is_an_error() {
+    let repo = TempRepo::new("classify-not-staged");
+    repo.write("a.txt", "a\n");
+    assert!(matches!(
+        classify_in(&repo, "a.txt"),
+        Err(FormatStagedError::NotStaged { .. })
+    ));
+}
+
+#[test]
+fn file_staged_for_deletion_is_an_error() {
+    let repo = TempRepo::new("classify-staged-for-deletion");
+    repo.stage("a.txt", "a\n");
+    repo.stage("b.txt", "b\n");
+    repo.git(&["commit", "--quiet", "-m", "a"]);
+    repo.git(&["rm", "--quiet", "--cached", "a.txt"]);
+    repo.git(&["rm", "--quiet", "b.txt"]);
+    assert!(matches!(
+        classify_in(&repo, "a.txt"),
+        Err(FormatStagedError::StagedForDeletion { exists: true, .. })
+    ));
+    assert!(matches!(
+        classify_in(&repo, "b.txt"),
+        Err(FormatStagedError::StagedForDeletion { exists: false, .. })
+    ));
+}
+
+#[test]
+fn file_outside_sparse_checkout_is_an_error() {
+    let repo = TempRepo::new("classify-sparse");
+    repo.stage("a.txt", "a\n");
+    repo.git(&["update-index", "--skip-worktree", "a.txt"]);
+    assert!(matches!(
+        classify_in(&repo, "a.txt"),
+        Err(FormatStagedError::OutsideSparseCheckout { .. })
+    ));
+}
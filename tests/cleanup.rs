@@ -0,0 +1,55 @@
+//! Tests for removing the temporary files once formatting is done.
+
+mod common;
+
+use common::TempRepo;
+
+#[test]
+fn files_that_cannot_be_removed_are_reported_together() {
+    let repo = TempRepo::new("cleanup-failure");
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "b\na\n");
+
+    // A directory in place of each backup can't be removed as a file, even by root.
+    let output = repo.format_staged(&[
+        "a.txt",
+        "b.txt",
+        "--",
+        "sh",
+        "-c",
+        r#"for f; do rm "$f.orig"; mkdir "$f.orig"; done; exit 1"#,
+        "fail",
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let warning =
+        "warning: the following temporary files could not be removed, please delete them manually:";
+    assert_eq!(stderr.matches(warning).count(), 1, "{}", stderr);
+    let listed: Vec<&str> = stderr
+        .lines()
+        .skip_while(|line| *line != warning)
+        .skip(1)
+        .map(|line| line.split(':').next().unwrap())
+        .collect();
+    assert_eq!(listed, ["  a.txt.orig", "  b.txt.orig"], "{}", stderr);
+    // The staged and unstaged files are still put back.
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("b.txt"), "b\na\n");
+    assert!(!repo.join("a.txt.staged.orig").exists());
+}
+
+#[test]
+fn nothing_is_reported_when_everything_is_removed() {
+    let repo = TempRepo::new("cleanup-success");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "sh", "-c", "exit 1", "fail"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("could not be removed"));
+    assert_eq!(
+        repo.git(&["status", "--porcelain", "--untracked-files=all"]),
+        "A  a.txt\n"
+    );
+}
@@ -0,0 +1,62 @@
+//! Tests for `--color`.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+/// Fail to format an unstaged file with `options`, and return the diagnostics.
+fn error_output(repo: &TempRepo, env: &[(&str, &str)], options: &[&str]) -> String {
+    repo.write("a.txt", "b\na\n");
+    let mut args = options.to_vec();
+    args.extend(["a.txt", "--"]);
+    args.extend(SORT);
+    let output = repo.format_staged_env(env, &args);
+    assert_eq!(output.status.code(), Some(4));
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn never_has_no_color_codes() {
+    let repo = TempRepo::new("color-never");
+
+    let stderr = error_output(&repo, &[], &["--color=never"]);
+
+    assert!(stderr.starts_with("error: "), "{}", stderr);
+    assert!(!stderr.contains('\x1b'), "{:?}", stderr);
+}
+
+#[test]
+fn always_colors_errors_red() {
+    let repo = TempRepo::new("color-always");
+
+    let stderr = error_output(&repo, &[("NO_COLOR", "1")], &["--color=always"]);
+
+    assert!(
+        stderr.starts_with("\x1b[1;31merror\x1b[0m: "),
+        "{:?}",
+        stderr
+    );
+}
+
+#[test]
+fn auto_has_no_color_codes_when_not_a_terminal() {
+    let repo = TempRepo::new("color-auto");
+
+    let stderr = error_output(&repo, &[], &["--color=auto"]);
+
+    assert!(!stderr.contains('\x1b'), "{:?}", stderr);
+}
+
+#[test]
+fn color_comes_from_the_config_file() {
+    let repo = TempRepo::new("color-config");
+    repo.write(".git-format-staged.toml", "color = \"always\"\n");
+
+    let stderr = error_output(&repo, &[], &[]);
+
+    assert!(
+        stderr.starts_with("\x1b[1;31merror\x1b[0m: "),
+        "{:?}",
+        stderr
+    );
+}
@@ -0,0 +1,45 @@
+//! Tests for how the formatting command is given on the command line.
+
+mod common;
+
+use common::TempRepo;
+
+#[test]
+fn empty_command_after_dashes_is_an_error() {
+    let repo = TempRepo::new("empty-command");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["a.txt", "--"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no formatter specified after --"));
+}
+
+#[test]
+fn command_can_end_with_dashes() {
+    let repo = TempRepo::new("command-ends-with-dashes");
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&[
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        r#"test "$1" = -- && sort -o "$2" "$2""#,
+        "sort",
+        "--",
+    ]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn shell_command_with_dashes_is_an_error() {
+    let repo = TempRepo::new("shell-command-with-dashes");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["--command", "sort -o", "a.txt", "--"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+}
@@ -0,0 +1,56 @@
+//! Tests for `--commit`.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn commits_the_formatted_files() {
+    let repo = TempRepo::new("commit");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["--commit", "-m", "Add a", "a.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.git(&["log", "-1", "--format=%s"]), "Add a\n");
+    assert_eq!(repo.git(&["show", "HEAD:a.txt"]), "a\nb\n");
+    assert_eq!(repo.git(&["status", "--porcelain"]), "");
+}
+
+#[test]
+fn nothing_to_commit_is_an_error() {
+    let repo = TempRepo::new("commit-nothing");
+    repo.stage("a.txt", "a\nb\n");
+    repo.git(&["commit", "--quiet", "-m", "a"]);
+    // Formatting undoes the only staged change.
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["--commit", "-m", "Again", "a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nothing to commit"));
+    assert_eq!(repo.git(&["rev-list", "--count", "HEAD"]), "1\n");
+}
+
+#[test]
+fn post_format_runs_after_the_commit() {
+    let repo = TempRepo::new("commit-post-format");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec![
+        "--commit",
+        "-m",
+        "Add a",
+        "--post-format",
+        "git log -1 --format=%s > .git/last-commit",
+        "a.txt",
+        "--",
+    ];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.read(".git/last-commit"), "Add a\n");
+}
@@ -0,0 +1,201 @@
+//! Helpers for the integration tests, which work in temporary Git repositories.
+
+// Each test crate uses a different subset of the helpers.
+#![allow(dead_code)]
+
+use std::{
+    ffi::OsStr,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    time::SystemTime,
+};
+
+/// A Git repository in a temporary directory, which is removed when it's dropped.
+pub struct TempRepo {
+    pub path: PathBuf,
+}
+
+impl TempRepo {
+    /// Create an empty repository. `name` must be unique among the tests.
+    pub fn new(name: &str) -> TempRepo {
+        let path = std::env::temp_dir()
+            .join("git-format-staged-tests")
+            .join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        let repo = TempRepo { path };
+        repo.git(&["init", "--quiet", "."]);
+        repo.git(&["config", "user.name", "Test"]);
+        repo.git(&["config", "user.email", "test@example.com"]);
+        repo.git(&["config", "commit.gpgsign", "false"]);
+        repo
+    }
+
+    fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut command = Command::new(program);
+        command
+            .current_dir(&self.path)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", self.path.join(".git/no-global-config"))
+            .env_remove("GIT_INDEX_FILE")
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE");
+        command
+    }
+
+    /// Run `git` with `args`, which must succeed, and return its stdout.
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = self.command("git").args(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Run `git` with `args`, which needn't be UTF-8 and must succeed, and return its
+    /// stdout.
+    pub fn git_os(&self, args: &[&OsStr]) -> Vec<u8> {
+        let output = self.command("git").args(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output.stdout
+    }
+
+    /// Run `git-format-staged` with `args`.
+    pub fn format_staged(&self, args: &[&str]) -> Output {
+        self.command(env!("CARGO_BIN_EXE_git-format-staged"))
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Run `git-format-staged` with `args`, which needn't be UTF-8.
+    pub fn format_staged_os(&self, args: &[&OsStr]) -> Output {
+        self.command(env!("CARGO_BIN_EXE_git-format-staged"))
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Run `git-format-staged` with `args`, and with the environment variables `env` set.
+    pub fn format_staged_env(&self, env: &[(&str, &str)], args: &[&str]) -> Output {
+        self.command(env!("CARGO_BIN_EXE_git-format-staged"))
+            .envs(env.iter().copied())
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Run `git-format-staged` with `args`, and with `input` on its stdin.
+    pub fn format_staged_input(&self, args: &[&str], input: &[u8]) -> Output {
+        let mut child = self
+            .command(env!("CARGO_BIN_EXE_git-format-staged"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Run `git-format-staged` with `args`, which must succeed, and return its stdout.
+    pub fn format_staged_ok(&self, args: &[&str]) -> String {
+        let output = self.format_staged(args);
+        assert!(
+            output.status.success(),
+            "git-format-staged {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    pub fn write(&self, file: &str, content: &str) {
+        let path = self.path.join(file);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(path, content).unwrap();
+    }
+
+    pub fn read(&self, file: &str) -> String {
+        std::fs::read_to_string(self.path.join(file)).unwrap()
+    }
+
+    /// The staged content of `file`.
+    pub fn staged(&self, file: &str) -> String {
+        self.git(&["show", &format!(":{}", file)])
+    }
+
+    /// The staged content of `file`, which needn't be UTF-8.
+    pub fn staged_bytes(&self, file: &str) -> Vec<u8> {
+        let output = self
+            .command("git")
+            .args(["cat-file", "blob", &format!(":{}", file)])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        output.stdout
+    }
+
+    /// The content of `file` that's staged in the index file `index`.
+    pub fn staged_in(&self, index: &Path, file: &str) -> String {
+        let output = self
+            .command("git")
+            .env("GIT_INDEX_FILE", index)
+            .args(["show", &format!(":{}", file)])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Write `file` and stage it.
+    pub fn stage(&self, file: &str, content: &str) {
+        self.write(file, content);
+        self.git(&["add", "--", file]);
+    }
+
+    pub fn modified(&self, file: &str) -> SystemTime {
+        std::fs::metadata(self.path.join(file))
+            .unwrap()
+            .modified()
+            .unwrap()
+    }
+
+    /// Set the modification time of `file` to some time ago, so that a rewrite would
+    /// change it.
+    pub fn age(&self, file: &str) {
+        let then = SystemTime::now() - std::time::Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(self.path.join(file))
+            .unwrap()
+            .set_modified(then)
+            .unwrap();
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TempRepo {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A formatter, as the arguments for `sh -c`, that sorts the lines of each file it's
+/// given.
+pub const SORT: &[&str] = &["sh", "-c", r#"for f; do sort -o "$f" "$f"; done"#, "sort"];
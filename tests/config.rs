@@ -0,0 +1,61 @@
+//! Tests for the `.git-format-staged.toml` config file.
+
+mod common;
+
+use common::TempRepo;
+
+const CONFIG: &str = r#"
+files = ["a.txt"]
+command = ["sh", "-c", 'for f; do sort -o "$f" "$f"; done', "sort"]
+no-working-tree = true
+"#;
+
+#[test]
+fn options_come_from_the_config_file() {
+    let repo = TempRepo::new("config");
+    repo.write(".git-format-staged.toml", CONFIG);
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&[]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[test]
+fn command_line_overrides_the_config_file() {
+    let repo = TempRepo::new("config-override");
+    repo.write(".git-format-staged.toml", CONFIG);
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&["--working-tree"]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn command_line_files_and_command_replace_the_config_file_ones() {
+    let repo = TempRepo::new("config-override-command");
+    repo.write(".git-format-staged.toml", CONFIG);
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "b\na\n");
+
+    repo.format_staged_ok(&["b.txt", "--", "sh", "-c", r#"printf 'x\n' > "$1""#, "x"]);
+
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.staged("b.txt"), "x\n");
+}
+
+#[test]
+fn invalid_config_is_an_error() {
+    let repo = TempRepo::new("config-invalid");
+    repo.write(".git-format-staged.toml", "index-only = 1\n");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "true"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("line 1: `index-only` must be a boolean, not an integer"));
+}
@@ -0,0 +1,73 @@
+//! Tests for repositories whose `core.worktree` puts the working tree somewhere other
+//! than the parent of the `.git` directory.
+
+mod common;
+
+use std::path::PathBuf;
+
+use common::{TempRepo, SORT};
+
+/// A repository whose working tree is a sibling directory, which is returned too.
+fn separate_worktree(name: &str) -> (TempRepo, PathBuf) {
+    let repo = TempRepo::new(name);
+    let work = repo.path.with_extension("work");
+    let _ = std::fs::remove_dir_all(&work);
+    std::fs::create_dir_all(work.join("src")).unwrap();
+    repo.git(&["config", "core.worktree", work.to_str().unwrap()]);
+    (repo, work)
+}
+
+/// Write `file` in `work` and stage it.
+fn stage(repo: &TempRepo, work: &std::path::Path, file: &str, content: &str) {
+    std::fs::write(work.join(file), content).unwrap();
+    repo.git(&["add", "--", work.join(file).to_str().unwrap()]);
+}
+
+#[test]
+fn files_are_found_in_the_configured_worktree() {
+    let (repo, work) = separate_worktree("core-worktree");
+    stage(&repo, &work, "a.txt", "b\na\n");
+    stage(&repo, &work, "src/b.txt", "d\nc\n");
+
+    let git_dir = repo.join(".git");
+    let env = [("GIT_DIR", git_dir.to_str().unwrap())];
+    for (dir, file) in [(&work, "a.txt"), (&work.join("src"), "b.txt")] {
+        let mut args = vec!["-C", dir.to_str().unwrap(), file, "--"];
+        args.extend(SORT);
+        let output = repo.format_staged_env(&env, &args);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.staged("src/b.txt"), "c\nd\n");
+    assert_eq!(
+        std::fs::read_to_string(work.join("a.txt")).unwrap(),
+        "a\nb\n"
+    );
+    assert!(!repo.join("a.txt").exists());
+    std::fs::remove_dir_all(work).unwrap();
+}
+
+#[test]
+fn running_outside_the_configured_worktree_is_an_error() {
+    let (repo, work) = separate_worktree("core-worktree-outside");
+    stage(&repo, &work, "a.txt", "b\na\n");
+
+    let mut args = vec!["a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("is outside the repository's working tree"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    std::fs::remove_dir_all(work).unwrap();
+}
@@ -0,0 +1,127 @@
+//! Tests for `--deadline`, and for how formatters are stopped when it passes.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn formatter_is_stopped_at_the_deadline() {
+    let repo = TempRepo::new("deadline");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&[
+        "--deadline",
+        "0.2",
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        "sleep 10",
+        "sleep",
+    ]);
+
+    assert_eq!(output.status.code(), Some(124));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("sh was still running when the --deadline passed"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[test]
+fn deadline_covers_the_whole_run() {
+    let repo = TempRepo::new("deadline-whole-run");
+    repo.stage("a.txt", "b\na\n");
+
+    // Each formatter finishes well within the deadline, but not both of them.
+    let output = repo.format_staged(&[
+        "--deadline",
+        "0.5",
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        "sleep 0.3",
+        "first",
+        "--then",
+        "sh",
+        "-c",
+        "sleep 0.3",
+        "second",
+    ]);
+
+    assert_eq!(output.status.code(), Some(124));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    // The temporary files are cleaned up.
+    assert_eq!(
+        repo.git(&["status", "--porcelain", "--untracked-files=all"]),
+        "A  a.txt\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn formatter_children_are_stopped_too() {
+    let repo = TempRepo::new("deadline-children");
+    repo.stage("a.txt", "b\na\n");
+
+    // The shell waits on a child that would write a file if it weren't stopped.
+    let output = repo.format_staged(&[
+        "--deadline",
+        "0.2",
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        "(sleep 0.5; touch .git/late) & wait",
+        "wait",
+    ]);
+
+    assert_eq!(output.status.code(), Some(124));
+    std::thread::sleep(std::time::Duration::from_millis(800));
+    assert!(!repo.join(".git/late").exists());
+}
+
+#[test]
+fn run_within_the_deadline_succeeds() {
+    let repo = TempRepo::new("deadline-met");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["--deadline", "30", "a.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn missing_kill_is_reported() {
+    let repo = TempRepo::new("deadline-no-kill");
+    repo.stage("a.txt", "b\na\n");
+    std::fs::create_dir(repo.join(".git/empty")).unwrap();
+    let sleep = ["/bin/sleep", "/usr/bin/sleep"]
+        .into_iter()
+        .find(|path| std::path::Path::new(path).exists())
+        .unwrap();
+
+    // Without `kill` on the PATH, the formatter's process group can't be signalled.
+    let empty = repo.join(".git/empty");
+    let script = format!("exec {} 10", sleep);
+    let output = repo.format_staged_env(
+        &[("PATH", empty.to_str().unwrap())],
+        &[
+            "--deadline",
+            "0.2",
+            "a.txt",
+            "--",
+            "/bin/sh",
+            "-c",
+            &script,
+            "sleep",
+        ],
+    );
+
+    assert_eq!(output.status.code(), Some(124));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("couldn't run `kill`"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
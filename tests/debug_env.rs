@@ -0,0 +1,66 @@
+//! Tests for the `GIT_FORMAT_STAGED_TRACE` and `GIT_FORMAT_STAGED_KEEP_TEMP` variables.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn trace_prints_each_step() {
+    let repo = TempRepo::new("trace");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged_env(&[("GIT_FORMAT_STAGED_TRACE", "1")], &args);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("repository "), "{}", stderr);
+    assert!(stderr.contains("sort -o"), "{}", stderr);
+}
+
+#[test]
+fn trace_is_off_for_zero() {
+    let repo = TempRepo::new("trace-zero");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged_env(&[("GIT_FORMAT_STAGED_TRACE", "0")], &args);
+
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("repository "));
+}
+
+#[test]
+fn keep_temp_keeps_the_index_only_copies() {
+    let repo = TempRepo::new("keep-temp");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["--index-only", "a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged_env(&[("GIT_FORMAT_STAGED_KEEP_TEMP", "1")], &args);
+
+    assert!(output.status.success());
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read(".git/format-staged/a.txt"), "a\nb\n");
+}
+
+#[test]
+fn keep_temp_with_verify_clean() {
+    let repo = TempRepo::new("keep-temp-verify-clean");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["--index-only", "--verify-clean", "a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged_env(&[("GIT_FORMAT_STAGED_KEEP_TEMP", "1")], &args);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert!(repo.join(".git/format-staged").exists());
+    assert!(repo.join(".git/format-staged-verify").exists());
+}
@@ -0,0 +1,58 @@
+//! Tests for files that are staged for deletion.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+/// A repository with `a.txt` and `b.txt` committed.
+fn committed(name: &str) -> TempRepo {
+    let repo = TempRepo::new(name);
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "b\na\n");
+    repo.git(&["commit", "--quiet", "-m", "a"]);
+    repo
+}
+
+#[test]
+fn file_deleted_from_the_index_but_not_the_working_tree_is_explained() {
+    let repo = committed("deleted-but-exists");
+    repo.git(&["rm", "--quiet", "--cached", "a.txt"]);
+
+    let mut args = vec!["a.txt", "b.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr
+            .contains("error: a.txt is staged for deletion, but still exists in the working tree"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("hint: run `git add a.txt`"), "{}", stderr);
+    // Nothing is formatted, and the deletion is still staged.
+    assert_eq!(repo.staged("b.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+    assert_eq!(repo.git(&["status", "--porcelain"]), "D  a.txt\n?? a.txt\n");
+}
+
+#[test]
+fn file_deleted_everywhere_is_an_error() {
+    let repo = committed("deleted-everywhere");
+    repo.git(&["rm", "--quiet", "a.txt"]);
+
+    let mut args = vec!["a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("a.txt is staged for deletion"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("still exists"), "{}", stderr);
+    assert!(!repo.join("a.txt").exists());
+}
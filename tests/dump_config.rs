@@ -0,0 +1,73 @@
+//! Tests for `--dump-config`.
+
+mod common;
+
+use common::TempRepo;
+
+/// The value and source of `key` in the output of `--dump-config`.
+fn setting(dump: &str, key: &str) -> (String, String) {
+    let prefix = format!("{} = ", key);
+    let line = dump
+        .lines()
+        .find(|line| line.starts_with(&prefix))
+        .unwrap_or_else(|| panic!("{} isn't in:\n{}", key, dump));
+    let (value, source) = line[prefix.len()..].split_once("  # ").unwrap();
+    (value.trim_end().to_string(), source.to_string())
+}
+
+#[test]
+fn shows_where_each_setting_came_from() {
+    let repo = TempRepo::new("dump-config");
+    repo.write(
+        ".git-format-staged.toml",
+        "index-only = true\njobs = 3\nallow-unstaged = true\n",
+    );
+
+    let dump = repo.format_staged_ok(&[
+        "--dump-config",
+        "--no-allow-unstaged",
+        "--passes",
+        "2",
+        "a.txt",
+        "--",
+        "true",
+    ]);
+
+    let config = format!(
+        "config file {}",
+        repo.join(".git-format-staged.toml").display()
+    );
+    assert_eq!(
+        setting(&dump, "index-only"),
+        ("true".into(), config.clone())
+    );
+    assert_eq!(setting(&dump, "jobs"), ("3".into(), config));
+    assert_eq!(
+        setting(&dump, "allow-unstaged"),
+        ("false".into(), "command line".into())
+    );
+    assert_eq!(
+        setting(&dump, "passes"),
+        ("2".into(), "command line".into())
+    );
+    assert_eq!(setting(&dump, "stdin"), ("false".into(), "default".into()));
+    assert_eq!(
+        setting(&dump, "files"),
+        (r#"["a.txt"]"#.into(), "command line".into())
+    );
+    assert_eq!(
+        setting(&dump, "command"),
+        (r#"["true"]"#.into(), "command line".into())
+    );
+}
+
+#[test]
+fn formats_nothing() {
+    let repo = TempRepo::new("dump-config-formats-nothing");
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&["--dump-config", "a.txt", "--", "sort", "-o", "a.txt"]);
+
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
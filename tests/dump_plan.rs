@@ -0,0 +1,85 @@
+//! Tests for `--dump-plan`.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn plan_matches_the_run() {
+    let repo = TempRepo::new("dump-plan");
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "b\na\n");
+    repo.write("b.txt", "b\na\nc\n");
+
+    let mut args = vec!["--dump-plan", "plan.json", "a.txt", "b.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    let repository = repo.path.canonicalize().unwrap();
+    assert_eq!(
+        repo.read("plan.json"),
+        format!(
+            r#"{{
+  "repository": "{}",
+  "commands": [
+    ["sh", "-c", "for f; do sort -o \"$f\" \"$f\"; done", "sort"]
+  ],
+  "targets": [
+    {{"path": "a.txt", "variant": "staged-only", "format_path": "a.txt", "temp_files": ["a.txt.staged.orig", "a.txt.orig"]}},
+    {{"path": "b.txt", "variant": "unstaged-and-staged", "format_path": "b.txt", "temp_files": ["b.txt.staged.orig", "b.txt.orig"]}}
+  ]
+}}
+"#,
+            repository.display()
+        )
+    );
+    // The run still goes ahead.
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.staged("b.txt"), "a\nb\n");
+}
+
+#[test]
+fn plan_shows_the_index_only_copies() {
+    let repo = TempRepo::new("dump-plan-index-only");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["--dump-plan", "plan.json", "--index-only", "a.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    let copy = repo
+        .path
+        .canonicalize()
+        .unwrap()
+        .join(".git/format-staged/a.txt");
+    let plan = repo.read("plan.json");
+    assert!(
+        plan.contains(&format!(
+            r#"{{"path": "a.txt", "variant": "staged-only", "format_path": "{0}", "temp_files": ["{0}"]}}"#,
+            copy.display()
+        )),
+        "{}",
+        plan
+    );
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn plan_is_written_before_formatting() {
+    let repo = TempRepo::new("dump-plan-failure");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&[
+        "--dump-plan",
+        "plan.json",
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        "exit 1",
+        "fail",
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(repo.read("plan.json").contains(r#""path": "a.txt""#));
+}
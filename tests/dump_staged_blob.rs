@@ -0,0 +1,62 @@
+//! Tests for `--dump-staged-blob`.
+
+mod common;
+
+use common::TempRepo;
+
+/// Content that a text conversion would mangle.
+const CONTENT: &[u8] = b"b\r\na\0\xff\n";
+
+/// A repository with `a.bin` staged as `CONTENT`, and changed since in the working tree.
+fn staged(name: &str) -> TempRepo {
+    let repo = TempRepo::new(name);
+    std::fs::write(repo.join("a.bin"), CONTENT).unwrap();
+    repo.git(&["add", "a.bin"]);
+    std::fs::write(repo.join("a.bin"), b"unstaged\n").unwrap();
+    repo
+}
+
+#[test]
+fn staged_content_is_written_to_stdout() {
+    let repo = staged("dump-staged-blob");
+
+    let output = repo.format_staged(&["--dump-staged-blob", "a.bin"]);
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, CONTENT);
+}
+
+#[test]
+fn staged_content_is_written_to_dump_to() {
+    let repo = staged("dump-staged-blob-to");
+
+    let output = repo.format_staged(&["--dump-staged-blob", "a.bin", "--dump-to", "out"]);
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(std::fs::read(repo.join("out")).unwrap(), CONTENT);
+    assert_eq!(std::fs::read(repo.join("a.bin")).unwrap(), b"unstaged\n");
+}
+
+#[test]
+fn path_is_relative_to_the_current_directory() {
+    let repo = TempRepo::new("dump-staged-blob-subdirectory");
+    repo.stage("src/a.txt", "a\n");
+
+    let output = repo.format_staged(&["-C", "src", "--dump-staged-blob", "a.txt"]);
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"a\n");
+}
+
+#[test]
+fn file_that_is_not_staged_is_an_error() {
+    let repo = TempRepo::new("dump-staged-blob-not-staged");
+    repo.write("a.txt", "a\n");
+
+    let output = repo.format_staged(&["--dump-staged-blob", "a.txt"]);
+
+    assert_eq!(output.status.code(), Some(4));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("a.txt is not a staged file"));
+}
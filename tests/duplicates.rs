@@ -0,0 +1,50 @@
+//! Tests for files that are given more than once.
+
+mod common;
+
+use common::TempRepo;
+
+/// A formatter that writes the path of each file it's given to `.git/args`, and then
+/// appends a line to the file, so that formatting it twice would show.
+const RECORD_AND_APPEND: &[&str] = &[
+    "sh",
+    "-c",
+    r#"for f; do echo "$f" >> .git/args; echo x >> "$f"; done"#,
+    "record",
+];
+
+#[test]
+fn overlapping_globs_format_the_file_once() {
+    let repo = TempRepo::new("duplicate-globs");
+    let command: Vec<String> = RECORD_AND_APPEND
+        .iter()
+        .map(|arg| format!("'{}'", arg))
+        .collect();
+    repo.write(
+        ".git-format-staged.toml",
+        &format!(
+            "globs = [\"src/*.rs\", \"**/main.rs\"]\ncommand = [{}]\n",
+            command.join(", ")
+        ),
+    );
+    repo.stage("src/main.rs", "fn main() {}\n");
+
+    repo.format_staged_ok(&[]);
+
+    assert_eq!(repo.read(".git/args"), "src/main.rs\n");
+    assert_eq!(repo.staged("src/main.rs"), "fn main() {}\nx\n");
+}
+
+#[test]
+fn same_file_given_different_ways_is_formatted_once() {
+    let repo = TempRepo::new("duplicate-files");
+    repo.stage("src/main.rs", "fn main() {}\n");
+
+    let mut args = vec!["src/main.rs", "./src/main.rs", "src", "--"];
+    args.extend(RECORD_AND_APPEND);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.read(".git/args"), "src/main.rs\n");
+    assert_eq!(repo.staged("src/main.rs"), "fn main() {}\nx\n");
+    assert_eq!(repo.read("src/main.rs"), "fn main() {}\nx\n");
+}
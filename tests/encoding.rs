@@ -0,0 +1,86 @@
+//! Tests for `--encoding`, which converts files to UTF-8 for the formatter and back.
+
+mod common;
+
+use common::TempRepo;
+
+/// A formatter that upper-cases `café`, which only works on UTF-8.
+const UPPER_CAFE: &[&str] = &["sed", "-i", "s/café/CAFÉ/"];
+
+fn utf16le(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn utf16be(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+fn stage_bytes(repo: &TempRepo, file: &str, content: &[u8]) {
+    std::fs::write(repo.join(file), content).unwrap();
+    repo.git(&["add", "--", file]);
+}
+
+fn run(repo: &TempRepo, encoding: &str, file: &str) -> std::process::Output {
+    let mut args = vec!["--encoding", encoding, file, "--"];
+    args.extend(UPPER_CAFE);
+    repo.format_staged(&args)
+}
+
+#[test]
+fn utf16le_round_trips_through_the_formatter() {
+    let repo = TempRepo::new("encoding-utf16le");
+    // With a byte order mark, and a character outside the BMP.
+    stage_bytes(&repo, "a.txt", &utf16le("\u{feff}café 😀\nb\n"));
+
+    let output = run(&repo, "utf-16le", "a.txt");
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let formatted = utf16le("\u{feff}CAFÉ 😀\nb\n");
+    assert_eq!(repo.staged_bytes("a.txt"), formatted);
+    assert_eq!(std::fs::read(repo.join("a.txt")).unwrap(), formatted);
+}
+
+#[test]
+fn utf16be_round_trips_through_the_formatter() {
+    let repo = TempRepo::new("encoding-utf16be");
+    stage_bytes(&repo, "a.txt", &utf16be("café\n"));
+
+    let output = run(&repo, "utf-16be", "a.txt");
+
+    assert!(output.status.success());
+    assert_eq!(repo.staged_bytes("a.txt"), utf16be("CAFÉ\n"));
+}
+
+#[test]
+fn file_that_is_not_in_the_encoding_is_skipped() {
+    let repo = TempRepo::new("encoding-invalid");
+    // An odd number of bytes can't be UTF-16.
+    stage_bytes(&repo, "a.txt", b"caf\xc3\xa9!\n");
+
+    let output = run(&repo, "utf-16le", "a.txt");
+
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("skipping a.txt"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.staged_bytes("a.txt"), b"caf\xc3\xa9!\n");
+}
+
+#[test]
+fn utf16_is_not_converted_without_the_option() {
+    let repo = TempRepo::new("encoding-default");
+    let content = utf16le("café\n");
+    stage_bytes(&repo, "a.txt", &content);
+
+    let output = run(&repo, "utf-8", "a.txt");
+
+    // The UTF-16 file looks binary, and the UTF-8 pattern can't match it anyway.
+    assert!(output.status.success());
+    assert_eq!(repo.staged_bytes("a.txt"), content);
+}
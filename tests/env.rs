@@ -0,0 +1,74 @@
+//! Tests for `KEY=VALUE` environment variable assignments at the start of the formatter
+//! command.
+
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+
+use common::TempRepo;
+
+#[test]
+fn formatter_sees_the_assignments() {
+    let repo = TempRepo::new("env");
+    repo.stage("a.txt", "a\n");
+
+    repo.format_staged_ok(&[
+        "a.txt",
+        "--",
+        "GREETING=hello there",
+        "NAME=world",
+        "sh",
+        "-c",
+        r#"echo "$GREETING, $NAME" > .git/env"#,
+        "record",
+    ]);
+
+    assert_eq!(repo.read(".git/env"), "hello there, world\n");
+}
+
+#[test]
+fn assigned_path_is_searched_for_the_formatter() {
+    let repo = TempRepo::new("env-path");
+    repo.write(
+        "bin/sort-lines",
+        "#!/bin/sh\nfor f; do sort -o \"$f\" \"$f\"; done\n",
+    );
+    std::fs::set_permissions(
+        repo.join("bin/sort-lines"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    repo.stage("a.txt", "b\na\n");
+
+    let path = format!(
+        "PATH={}:{}",
+        repo.join("bin").display(),
+        std::env::var("PATH").unwrap()
+    );
+    repo.format_staged_ok(&["a.txt", "--", &path, "sort-lines"]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn assignments_without_a_formatter_are_an_error() {
+    let repo = TempRepo::new("env-no-formatter");
+    repo.stage("a.txt", "a\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "A=1", "B=2"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("no formatter specified after the environment variables"));
+}
+
+#[test]
+fn word_that_is_not_an_assignment_is_the_program() {
+    let repo = TempRepo::new("env-not-a-name");
+    repo.stage("a.txt", "a\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "1A=1", "true"]);
+
+    assert_eq!(output.status.code(), Some(127));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("formatter not found: 1A=1"));
+}
@@ -0,0 +1,135 @@
+//! Tests for the exit codes, which are listed in the README for scripts to rely on.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+fn sort_args<'a>(before: &[&'a str], files: &[&'a str]) -> Vec<&'a str> {
+    let mut args = before.to_vec();
+    args.extend(files);
+    args.push("--");
+    args.extend(SORT);
+    args
+}
+
+#[test]
+fn success_is_0() {
+    let repo = TempRepo::new("exit-success");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&sort_args(&[], &["a.txt"]));
+
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn other_errors_are_1() {
+    let repo = TempRepo::new("exit-error");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&sort_args(
+        &["--known-formatted", ".git/no-such-file"],
+        &["a.txt"],
+    ));
+
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn formatter_killed_by_signal_is_1() {
+    let repo = TempRepo::new("exit-signal");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "sh", "-c", "kill -9 $$"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[test]
+fn usage_errors_are_2() {
+    let repo = TempRepo::new("exit-usage");
+    repo.stage("a.txt", "b\na\n");
+
+    assert_eq!(
+        repo.format_staged(&["--no-such-option"]).status.code(),
+        Some(2)
+    );
+    assert_eq!(repo.format_staged(&["a.txt", "--"]).status.code(), Some(2));
+    assert_eq!(
+        repo.format_staged(&sort_args(&["--stdin", "--lsp"], &["a.txt"]))
+            .status
+            .code(),
+        Some(2)
+    );
+}
+
+#[test]
+fn not_a_repository_is_3() {
+    let repo = TempRepo::new("exit-not-a-repository");
+    std::fs::remove_dir_all(repo.join(".git")).unwrap();
+    repo.write("a.txt", "b\na\n");
+
+    let ceiling = repo.path.parent().unwrap().to_str().unwrap();
+    let output = repo.format_staged_env(
+        &[("GIT_CEILING_DIRECTORIES", ceiling)],
+        &sort_args(&[], &["a.txt"]),
+    );
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[test]
+fn not_staged_is_4() {
+    let repo = TempRepo::new("exit-not-staged");
+    repo.write("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&sort_args(&[], &["a.txt"]));
+
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[test]
+fn no_files_is_5() {
+    let repo = TempRepo::new("exit-no-files");
+
+    let output = repo.format_staged(&sort_args(&[], &[]));
+
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn deadline_is_124() {
+    let repo = TempRepo::new("exit-deadline");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["--deadline", "0.2", "a.txt", "--", "sh", "-c", "sleep 10"]);
+
+    assert_eq!(output.status.code(), Some(124));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[test]
+fn formatter_not_found_is_127() {
+    let repo = TempRepo::new("exit-not-found");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "git-format-staged-no-such-formatter"]);
+
+    assert_eq!(output.status.code(), Some(127));
+}
+
+#[test]
+fn failing_formatter_passes_its_code_through() {
+    let repo = TempRepo::new("exit-formatter");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "sh", "-c", "exit 42"]);
+    assert_eq!(output.status.code(), Some(42));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+
+    let output = repo.format_staged(&["--exit-zero", "a.txt", "--", "sh", "-c", "exit 42"]);
+    assert_eq!(output.status.code(), Some(0));
+}
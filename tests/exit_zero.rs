@@ -0,0 +1,54 @@
+//! Tests for `--exit-zero`.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn failed_formatter_still_exits_successfully() {
+    let repo = TempRepo::new("exit-zero");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&[
+        "--exit-zero",
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        "echo 'cannot parse a.txt' >&2; exit 3",
+        "fail",
+    ]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot parse a.txt"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[test]
+fn errors_are_still_reported() {
+    let repo = TempRepo::new("exit-zero-not-staged");
+    repo.write("a.txt", "b\na\n");
+
+    let mut args = vec!["--exit-zero", "a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("error: a.txt is not a staged file"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn formatter_exit_code_is_used_without_it() {
+    let repo = TempRepo::new("exit-zero-off");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "sh", "-c", "exit 3", "fail"]);
+
+    assert_eq!(output.status.code(), Some(3));
+}
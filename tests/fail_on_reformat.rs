@@ -0,0 +1,52 @@
+//! Tests for `--fail-on-reformat`.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn reformatted_file_fails_the_run() {
+    let repo = TempRepo::new("fail-on-reformat");
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "a\nb\n");
+
+    let mut args = vec!["--fail-on-reformat", "a.txt", "b.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("error: the following files were reformatted:\n  a.txt\n"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("b.txt"), "{}", stderr);
+    assert!(stderr.contains("commit again"), "{}", stderr);
+    // The formatting is still staged.
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn nothing_reformatted_succeeds() {
+    let repo = TempRepo::new("fail-on-reformat-unchanged");
+    repo.stage("a.txt", "a\nb\n");
+
+    let mut args = vec!["--fail-on-reformat", "a.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+}
+
+#[test]
+fn exit_zero_conflicts_with_fail_on_reformat() {
+    let repo = TempRepo::new("fail-on-reformat-exit-zero");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["--fail-on-reformat", "--exit-zero", "a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
@@ -0,0 +1,104 @@
+//! Tests for `--type`.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+/// A repository with an extensionless `Dockerfile` and `a.txt` staged, both unsorted.
+fn staged(name: &str) -> TempRepo {
+    let repo = TempRepo::new(name);
+    repo.stage("Dockerfile", "b\na\n");
+    repo.stage("a.txt", "b\na\n");
+    repo
+}
+
+#[test]
+fn type_picks_out_extensionless_files() {
+    let repo = staged("type-filter");
+
+    let mut args = vec!["--type", "dockerfile", "Dockerfile", "a.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged("Dockerfile"), "a\nb\n");
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[test]
+fn type_with_its_own_formatter_formats_dockerfile() {
+    let repo = staged("type-own-formatter");
+
+    let mut args = vec![
+        "--type",
+        "dockerfile=sed -i s/a/A/",
+        "--type",
+        "make",
+        "Dockerfile",
+        "a.txt",
+        "--",
+    ];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    // The Dockerfile gets its own formatter instead of being sorted, and `a.txt` is of
+    // neither type.
+    assert_eq!(repo.staged("Dockerfile"), "b\nA\n");
+    assert_eq!(repo.read("Dockerfile"), "b\nA\n");
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[test]
+fn other_types_use_the_formatter_after_the_separator() {
+    let repo = TempRepo::new("type-mixed-formatters");
+    repo.stage("Dockerfile", "b\na\n");
+    repo.stage("Makefile", "b\na\n");
+
+    let mut args = vec![
+        "--type",
+        "dockerfile=sed -i s/a/A/",
+        "--type",
+        "make",
+        ".",
+        "--",
+    ];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged("Dockerfile"), "b\nA\n");
+    assert_eq!(repo.staged("Makefile"), "a\nb\n");
+}
+
+#[test]
+fn formatter_after_the_separator_is_needed_for_types_without_their_own() {
+    let repo = staged("type-missing-formatter");
+
+    let output = repo.format_staged(&[
+        "--type",
+        "dockerfile=sed -i s/a/A/",
+        "--type",
+        "make",
+        "Dockerfile",
+    ]);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("no formatter specified for --type make"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.staged("Dockerfile"), "b\na\n");
+}
+
+#[test]
+fn unknown_type_is_an_error() {
+    let repo = staged("type-unknown");
+
+    let mut args = vec!["--type", "cobol", "Dockerfile", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("unknown file type `cobol`"), "{}", stderr);
+}
@@ -0,0 +1,110 @@
+//! Tests for the `filter` subcommand, which formats stdin without touching Git.
+
+mod common;
+
+use common::TempRepo;
+
+#[test]
+fn stdin_is_formatted_to_stdout() {
+    let repo = TempRepo::new("filter");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged_input(&["filter", "--path", "a.txt", "--", "sort"], b"d\nc\n");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"c\nd\n");
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+    assert_eq!(repo.git(&["status", "--porcelain"]), "A  a.txt\n");
+}
+
+#[test]
+fn path_replaces_placeholder() {
+    let repo = TempRepo::new("filter-path");
+
+    let output = repo.format_staged_input(
+        &[
+            "filter",
+            "--path",
+            "src/a.txt",
+            "--",
+            "sh",
+            "-c",
+            "echo {}; cat",
+        ],
+        b"a\n",
+    );
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"src/a.txt\na\n");
+}
+
+#[test]
+fn path_is_optional() {
+    let repo = TempRepo::new("filter-no-path");
+
+    let output =
+        repo.format_staged_input(&["filter", "--", "sh", "-c", "echo [{}]; sort"], b"b\na\n");
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"[]\na\nb\n");
+}
+
+#[test]
+fn works_outside_a_repository() {
+    let repo = TempRepo::new("filter-no-repo");
+    std::fs::remove_dir_all(repo.join(".git")).unwrap();
+
+    let output = repo.format_staged_input(&["filter", "--path", "a.txt", "--", "sort"], b"b\na\n");
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"a\nb\n");
+}
+
+#[test]
+fn failing_formatter_fails_without_output() {
+    let repo = TempRepo::new("filter-fails");
+
+    let output = repo.format_staged_input(
+        &[
+            "filter",
+            "--path",
+            "a.txt",
+            "--",
+            "sh",
+            "-c",
+            "echo partial; exit 3",
+        ],
+        b"a\n",
+    );
+
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, b"");
+}
+
+#[test]
+fn missing_formatter_exits_127() {
+    let repo = TempRepo::new("filter-missing");
+
+    let output = repo.format_staged_input(
+        &[
+            "filter",
+            "--path",
+            "a.txt",
+            "--",
+            "git-format-staged-no-such-formatter",
+        ],
+        b"a\n",
+    );
+
+    assert_eq!(output.status.code(), Some(127));
+    assert_eq!(output.stdout, b"");
+}
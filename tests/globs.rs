@@ -0,0 +1,73 @@
+//! Tests for the errors from malformed glob patterns, which are reported before any work.
+
+mod common;
+
+use common::TempRepo;
+
+/// A formatter that leaves a mark in `.git/ran`, to show whether it was run.
+const MARK: &[&str] = &["sh", "-c", "touch .git/ran", "mark"];
+
+#[test]
+fn malformed_option_pattern_is_a_usage_error() {
+    let repo = TempRepo::new("glob-option");
+    repo.stage("a.txt", "a\n");
+
+    let mut args = vec!["--treat-as-text", "src/[ab", "a.txt", "--"];
+    args.extend(MARK);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("invalid pattern `src/[ab`: unclosed `[` at position 4"),
+        "{}",
+        stderr
+    );
+    assert!(!repo.join(".git/ran").exists());
+}
+
+#[test]
+fn malformed_config_glob_is_an_error() {
+    let repo = TempRepo::new("glob-config");
+    repo.stage("a.txt", "a\n");
+    repo.write(
+        ".git-format-staged.toml",
+        "globs = [\"*.txt\", \"src/[z-a]\"]\n",
+    );
+
+    let mut args = vec!["--"];
+    args.extend(MARK);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "invalid `globs` in config: invalid pattern `src/[z-a]`: \
+             invalid character range at position 6"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!repo.join(".git/ran").exists());
+}
+
+#[test]
+fn malformed_config_exclude_is_an_error() {
+    let repo = TempRepo::new("glob-exclude");
+    repo.stage("a.txt", "a\n");
+    repo.write(".git-format-staged.toml", "excludes = [\"a\\\\\"]\n");
+
+    let mut args = vec!["a.txt", "--"];
+    args.extend(MARK);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("invalid `excludes` in config: invalid pattern `a\\`"),
+        "{}",
+        stderr
+    );
+    assert!(!repo.join(".git/ran").exists());
+}
@@ -0,0 +1,134 @@
+//! Tests for how the formatted files are staged.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn index_changed_while_formatting_is_left_alone() {
+    let repo = TempRepo::new("index-changed");
+    repo.stage("a.txt", "b\na\n");
+    repo.write("b.txt", "b\n");
+
+    // The formatter stages another file, as if another process had.
+    let output = repo.format_staged(&[
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        r#"git add b.txt && for f; do sort -o "$f" "$f"; done"#,
+        "sort",
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("changed by another process"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+    assert_eq!(repo.staged("b.txt"), "b\n");
+}
+
+#[test]
+fn out_index_leaves_the_index_alone() {
+    let repo = TempRepo::new("out-index");
+    repo.stage("a.txt", "b\na\n");
+    let out_index = repo.join(".git/formatted-index");
+
+    let mut args = vec!["--out-index", out_index.to_str().unwrap(), "a.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+    assert_eq!(repo.staged_in(&out_index, "a.txt"), "a\nb\n");
+}
+
+#[test]
+fn verify_clean_checks_the_out_index() {
+    let repo = TempRepo::new("out-index-verify-clean");
+    repo.stage("a.txt", "b\na\n");
+    let out_index = repo.join(".git/formatted-index");
+
+    let mut args = vec![
+        "--out-index",
+        out_index.to_str().unwrap(),
+        "--verify-clean",
+        "a.txt",
+        "--",
+    ];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("warning"), "{}", stderr);
+    assert_eq!(repo.staged_in(&out_index, "a.txt"), "a\nb\n");
+}
+
+/// A repository with `a.txt` in the index, and `a.txt` and `b.txt` in the alternate
+/// index `.git/alt-index`, which is returned too.
+fn alternate_index(name: &str) -> (TempRepo, std::path::PathBuf) {
+    let repo = TempRepo::new(name);
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "b\na\n");
+    let alt_index = repo.join(".git/alt-index");
+    std::fs::copy(repo.join(".git/index"), &alt_index).unwrap();
+    repo.git(&["rm", "--quiet", "--cached", "b.txt"]);
+    (repo, alt_index)
+}
+
+#[test]
+fn index_file_is_used_instead_of_the_index() {
+    let (repo, alt_index) = alternate_index("index-file");
+
+    let mut args = vec!["--index-file", alt_index.to_str().unwrap(), "b.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged_in(&alt_index, "b.txt"), "a\nb\n");
+    assert_eq!(repo.staged_in(&alt_index, "a.txt"), "b\na\n");
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.git(&["ls-files", "--", "b.txt"]), "");
+}
+
+#[test]
+fn git_index_file_is_used_instead_of_the_index() {
+    let (repo, alt_index) = alternate_index("git-index-file");
+
+    let mut args = vec!["a.txt", "b.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged_env(&[("GIT_INDEX_FILE", alt_index.to_str().unwrap())], &args);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.staged_in(&alt_index, "a.txt"), "a\nb\n");
+    assert_eq!(repo.staged_in(&alt_index, "b.txt"), "a\nb\n");
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[test]
+fn file_only_in_the_index_file_is_not_staged_in_the_index() {
+    let (repo, _) = alternate_index("index-file-not-staged");
+
+    let mut args = vec!["b.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn missing_index_file_is_empty() {
+    let repo = TempRepo::new("index-file-missing");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["--index-file", "no/such/index", "a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    // Like Git, an index file that doesn't exist yet has nothing staged in it.
+    assert_eq!(output.status.code(), Some(4));
+    assert!(!repo.join("no/such/index").exists());
+}
@@ -0,0 +1,100 @@
+//! Tests for `--index-only`, which formats copies of the staged files in `.git`.
+
+mod common;
+
+use common::TempRepo;
+
+/// A formatter that writes the path of each file it's given to `.git/args`, and then
+/// sorts the file's lines.
+const RECORD_AND_SORT: &[&str] = &[
+    "sh",
+    "-c",
+    r#"for f; do echo "$f" >> .git/args; sort -o "$f" "$f"; done"#,
+    "record",
+];
+
+fn run(repo: &TempRepo, options: &[&str], files: &[&str]) -> std::process::Output {
+    let mut args = vec!["--index-only"];
+    args.extend(options);
+    args.extend(files);
+    args.push("--");
+    args.extend(RECORD_AND_SORT);
+    repo.format_staged(&args)
+}
+
+#[test]
+fn temp_suffix_is_added_to_the_file_name() {
+    let repo = TempRepo::new("temp-suffix");
+    repo.stage("src/a.ts", "b\na\n");
+
+    let output = run(&repo, &["--temp-suffix", ".tsx"], &["src/a.ts"]);
+
+    assert!(output.status.success());
+    let args = repo.read(".git/args");
+    assert!(
+        args.trim_end().ends_with("format-staged/src/a.ts.tsx"),
+        "formatted as {}",
+        args
+    );
+    assert_eq!(repo.staged("src/a.ts"), "a\nb\n");
+    assert_eq!(repo.read("src/a.ts"), "b\na\n");
+}
+
+#[test]
+fn temp_suffix_without_a_dot() {
+    let repo = TempRepo::new("temp-suffix-no-dot");
+    repo.stage("a.ts", "b\na\n");
+
+    let output = run(&repo, &["--temp-suffix", "tsx"], &["a.ts"]);
+
+    assert!(output.status.success());
+    assert!(repo.read(".git/args").trim_end().ends_with("/a.ts.tsx"));
+}
+
+#[test]
+fn temp_suffix_keeps_files_apart() {
+    let repo = TempRepo::new("temp-suffix-apart");
+    repo.stage("a.ts", "b\na\n");
+    repo.stage("a.ts.tsx", "d\nc\n");
+
+    let output = run(&repo, &["--temp-suffix", "tsx"], &["a.ts", "a.ts.tsx"]);
+
+    assert!(output.status.success());
+    assert_eq!(repo.staged("a.ts"), "a\nb\n");
+    assert_eq!(repo.staged("a.ts.tsx"), "c\nd\n");
+}
+
+#[test]
+fn staged_only_file_is_left_alone_in_the_working_tree() {
+    let repo = TempRepo::new("index-only-staged-only");
+    repo.stage("a.txt", "b\na\n");
+    repo.age("a.txt");
+    let modified = repo.modified("a.txt");
+
+    let output = run(&repo, &[], &["a.txt"]);
+
+    assert!(output.status.success());
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+    assert_eq!(repo.modified("a.txt"), modified);
+}
+
+#[test]
+fn file_with_unstaged_changes_is_left_alone_in_the_working_tree() {
+    let repo = TempRepo::new("index-only-unstaged-and-staged");
+    repo.stage("a.txt", "b\na\n");
+    repo.write("a.txt", "c\nb\na\n");
+    repo.age("a.txt");
+    let modified = repo.modified("a.txt");
+
+    let output = run(&repo, &[], &["a.txt"]);
+
+    assert!(output.status.success());
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "c\nb\na\n");
+    assert_eq!(repo.modified("a.txt"), modified);
+    assert_eq!(
+        repo.git(&["status", "--porcelain", "--untracked-files=all"]),
+        "AM a.txt\n"
+    );
+}
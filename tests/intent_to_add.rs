@@ -0,0 +1,45 @@
+//! Tests for files added with `git add -N`, which have nothing staged.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn intent_to_add_file_is_skipped() {
+    let repo = TempRepo::new("intent-to-add");
+    repo.stage("a.txt", "b\na\n");
+    repo.write("new.txt", "b\na\n");
+    repo.git(&["add", "-N", "new.txt"]);
+
+    let mut args = vec!["a.txt", "new.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("skipping new.txt, which was added with `git add -N`"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("new.txt"), "b\na\n");
+    // It's still only intended to be added.
+    assert_eq!(
+        repo.git(&["status", "--porcelain"]),
+        "A  a.txt\n A new.txt\n"
+    );
+}
+
+#[test]
+fn only_intent_to_add_file_formats_nothing() {
+    let repo = TempRepo::new("intent-to-add-only");
+    repo.write("new.txt", "b\na\n");
+    repo.git(&["add", "-N", "new.txt"]);
+
+    // The formatter would fail if it were run.
+    let output = repo.format_staged(&["new.txt", "--", "false"]);
+
+    assert!(output.status.success());
+    assert_eq!(repo.read("new.txt"), "b\na\n");
+}
@@ -0,0 +1,78 @@
+//! Tests for what's left behind when formatting several files stops part of the way
+//! through.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+/// A repository with `a.txt` staged, and `b.txt` staged with an unstaged change too.
+fn staged(name: &str) -> TempRepo {
+    let repo = TempRepo::new(name);
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "b\na\n");
+    repo.write("b.txt", "b\na\nc\n");
+    repo
+}
+
+#[test]
+fn several_files_are_staged_without_leftovers() {
+    let repo = staged("interruption-none");
+
+    let mut args = vec!["a.txt", "b.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.staged("b.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("b.txt"), "a\nb\nc\n");
+    assert_eq!(
+        repo.git(&["status", "--porcelain", "--untracked-files=all"]),
+        "A  a.txt\nAM b.txt\n"
+    );
+}
+
+#[test]
+fn killed_after_the_first_file_stages_nothing() {
+    let repo = staged("interruption-killed");
+
+    // The formatter sorts the first file, and then kills git-format-staged outright.
+    let output = repo.format_staged(&[
+        "a.txt",
+        "b.txt",
+        "--",
+        "sh",
+        "-c",
+        r#"sort -o "$1" "$1"; kill -9 $PPID"#,
+        "interrupt",
+    ]);
+
+    assert_eq!(output.status.code(), None);
+    // Nothing is staged by halves, and the originals are kept for recovery.
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.staged("b.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt.orig"), "b\na\n");
+    assert_eq!(repo.read("b.txt.orig"), "b\na\nc\n");
+}
+
+#[test]
+fn failure_after_the_first_file_stages_nothing() {
+    let repo = staged("interruption-missing-file");
+
+    // The second file disappears, so its formatted version can't be read.
+    let output = repo.format_staged(&[
+        "a.txt",
+        "b.txt",
+        "--",
+        "sh",
+        "-c",
+        r#"sort -o "$1" "$1"; rm "$2""#,
+        "interrupt",
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("failed to read b.txt"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.staged("b.txt"), "b\na\n");
+    assert_eq!(repo.read("b.txt.orig"), "b\na\nc\n");
+}
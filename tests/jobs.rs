@@ -0,0 +1,100 @@
+//! Tests for `--jobs`, which splits the files between several runs of the formatter.
+
+mod common;
+
+use common::TempRepo;
+
+/// A formatter that writes the files it's given to a line of `.git/runs`, so that each
+/// line is one job.
+const RECORD: &[&str] = &["sh", "-c", r#"echo "$@" >> .git/runs"#, "record"];
+
+/// A repository with `count` files staged.
+fn with_files(name: &str, count: usize) -> (TempRepo, Vec<String>) {
+    let repo = TempRepo::new(name);
+    let files: Vec<String> = (1..=count).map(|n| format!("{}.txt", n)).collect();
+    for file in &files {
+        repo.stage(file, "a\n");
+    }
+    (repo, files)
+}
+
+/// Format `files` with `before` ahead of them, and return the files that each job was
+/// given, in order.
+fn jobs(repo: &TempRepo, before: &[&str], files: &[String]) -> (Vec<String>, String) {
+    let mut args = before.to_vec();
+    args.extend(files.iter().map(String::as_str));
+    args.push("--");
+    args.extend(RECORD);
+    let output = repo.format_staged_env(&[("GIT_FORMAT_STAGED_TRACE", "1")], &args);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut runs: Vec<String> = repo.read(".git/runs").lines().map(String::from).collect();
+    runs.sort();
+    std::fs::remove_file(repo.join(".git/runs")).unwrap();
+    (runs, String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn jobs_split_the_files() {
+    let (repo, files) = with_files("jobs", 4);
+
+    let (runs, stderr) = jobs(&repo, &["--jobs", "2"], &files);
+
+    assert_eq!(runs, ["1.txt 2.txt", "3.txt 4.txt"]);
+    assert!(
+        stderr.contains("formatting 4 files in 2 jobs"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn jobs_are_clamped_to_the_file_count() {
+    let (repo, files) = with_files("jobs-clamped", 3);
+
+    let (runs, stderr) = jobs(&repo, &["--jobs", "8"], &files);
+
+    assert_eq!(runs, ["1.txt", "2.txt", "3.txt"]);
+    assert!(
+        stderr.contains("formatting 3 files in 3 jobs"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn auto_is_the_cpu_count_clamped_to_the_file_count() {
+    let (repo, files) = with_files("jobs-auto", 2);
+    let cpus = std::thread::available_parallelism().unwrap().get();
+
+    for before in [&["--jobs", "auto"][..], &["--threads=auto"]] {
+        let (runs, _) = jobs(&repo, before, &files);
+        assert_eq!(runs.len(), cpus.min(files.len()), "{:?}", before);
+    }
+
+    repo.write(".git-format-staged.toml", "jobs = \"auto\"\n");
+    let (runs, _) = jobs(&repo, &[], &files);
+    assert_eq!(runs.len(), cpus.min(files.len()));
+}
+
+#[test]
+fn one_job_by_default() {
+    let (repo, files) = with_files("jobs-default", 3);
+
+    let (runs, _) = jobs(&repo, &[], &files);
+
+    assert_eq!(runs, ["1.txt 2.txt 3.txt"]);
+}
+
+#[test]
+fn zero_jobs_is_a_usage_error() {
+    let repo = TempRepo::new("jobs-zero");
+    repo.stage("a.txt", "a\n");
+
+    let output = repo.format_staged(&["--jobs", "0", "a.txt", "--", "true"]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,85 @@
+//! Tests for `--keep-line-endings`, which gives the formatted files the staged files' line
+//! endings.
+
+mod common;
+
+use common::TempRepo;
+
+/// A formatter that sorts each file it's given and writes it with CRLF line endings.
+const SORT_CRLF: &[&str] = &[
+    "sh",
+    "-c",
+    r#"for f; do tr -d '\r' < "$f" | sort | sed 's/$/\r/' > "$f.tmp" && mv "$f.tmp" "$f"; done"#,
+    "sort",
+];
+
+/// A formatter that sorts each file it's given and writes it with LF line endings.
+const SORT_LF: &[&str] = &[
+    "sh",
+    "-c",
+    r#"for f; do tr -d '\r' < "$f" | sort > "$f.tmp" && mv "$f.tmp" "$f"; done"#,
+    "sort",
+];
+
+fn args<'a>(before: &[&'a str], files: &[&'a str], formatter: &[&'a str]) -> Vec<&'a str> {
+    let mut args = before.to_vec();
+    args.extend(files);
+    args.push("--");
+    args.extend(formatter);
+    args
+}
+
+#[test]
+fn lf_is_kept_from_crlf_formatter() {
+    let repo = TempRepo::new("eol-lf");
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&args(&["--keep-line-endings"], &["a.txt"], SORT_CRLF));
+
+    assert_eq!(repo.staged_bytes("a.txt"), b"a\nb\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn crlf_is_kept_from_lf_formatter() {
+    let repo = TempRepo::new("eol-crlf");
+    repo.stage("a.txt", "b\r\na\r\n");
+
+    repo.format_staged_ok(&args(&["--keep-line-endings"], &["a.txt"], SORT_LF));
+
+    assert_eq!(repo.staged_bytes("a.txt"), b"a\r\nb\r\n");
+}
+
+#[test]
+fn predominant_ending_is_used_for_mixed_files() {
+    let repo = TempRepo::new("eol-mixed");
+    repo.stage("a.txt", "c\r\nb\r\na\n");
+
+    repo.format_staged_ok(&args(&["--keep-line-endings"], &["a.txt"], SORT_LF));
+
+    assert_eq!(repo.staged_bytes("a.txt"), b"a\r\nb\r\nc\r\n");
+}
+
+#[test]
+fn formatter_endings_are_staged_without_the_option() {
+    let repo = TempRepo::new("eol-off");
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&args(&[], &["a.txt"], SORT_CRLF));
+
+    assert_eq!(repo.staged_bytes("a.txt"), b"a\r\nb\r\n");
+}
+
+#[test]
+fn endings_are_kept_from_stdin_formatter() {
+    let repo = TempRepo::new("eol-stdin");
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&args(
+        &["--stdin", "--keep-line-endings"],
+        &["a.txt"],
+        &["sh", "-c", r"sort | sed 's/$/\r/'"],
+    ));
+
+    assert_eq!(repo.staged_bytes("a.txt"), b"a\nb\n");
+}
@@ -0,0 +1,132 @@
+//! Tests for `--lsp`, which formats through a language server.
+
+mod common;
+
+use common::TempRepo;
+
+/** A fake language server, run by `sh` with the path of a log file and what to answer
+formatting requests with.
+
+It logs the method of each message it's sent, and the whole of each `didOpen`
+notification, and answers each request with a canned result.
+*/
+const FAKE_SERVER: &str = r#"
+log="$1"
+formatting="$2"
+while :; do
+  len=
+  while IFS= read -r line; do
+    line=$(printf '%s' "$line" | tr -d '\r')
+    [ -z "$line" ] && break
+    case "$line" in Content-Length:*) len=${line#Content-Length: } ;; esac
+  done
+  [ -n "$len" ] || exit 0
+  body=$(head -c "$len")
+  id=$(printf '%s' "$body" | sed -n 's/.*"id": \([0-9]*\).*/\1/p')
+  method=$(printf '%s' "$body" | sed -n 's/.*"method": "\([^"]*\)".*/\1/p')
+  echo "$method" >> "$log"
+  case "$method" in
+    initialize) reply="\"result\": {\"capabilities\": {}}" ;;
+    textDocument/didOpen) printf '%s\n' "$body" >> "$log"; continue ;;
+    textDocument/formatting) reply="$formatting" ;;
+    shutdown) reply="\"result\": null" ;;
+    exit) exit 0 ;;
+    *) continue ;;
+  esac
+  reply="{\"jsonrpc\": \"2.0\", \"id\": $id, $reply}"
+  printf 'Content-Length: %d\r\n\r\n%s' "${#reply}" "$reply"
+done
+"#;
+
+/// A formatting result that adds a line to the start of the file.
+const ADD_HEADER: &str = r#""result": [{"range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 0}}, "newText": "// formatted\n"}]"#;
+
+/// Format `files` through the fake server, which answers formatting requests with
+/// `formatting`.
+fn run(repo: &TempRepo, files: &[&str], formatting: &str) -> std::process::Output {
+    repo.write(".git/fake-server.sh", FAKE_SERVER);
+    let server = repo.join(".git/fake-server.sh");
+    let log = repo.join(".git/log");
+    let mut args = vec!["--lsp"];
+    args.extend(files);
+    args.extend([
+        "--",
+        "sh",
+        server.to_str().unwrap(),
+        log.to_str().unwrap(),
+        formatting,
+    ]);
+    repo.format_staged(&args)
+}
+
+#[test]
+fn edits_from_the_server_are_staged() {
+    let repo = TempRepo::new("lsp");
+    repo.stage("a.txt", "b\na\n");
+    repo.write("a.txt", "b\na\nc\n");
+    repo.stage("b.txt", "d\n");
+
+    let output = run(&repo, &["a.txt", "b.txt"], ADD_HEADER);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.staged("a.txt"), "// formatted\nb\na\n");
+    assert_eq!(repo.read("a.txt"), "// formatted\nb\na\nc\n");
+    assert_eq!(repo.staged("b.txt"), "// formatted\nd\n");
+
+    let log = repo.read(".git/log");
+    let methods: Vec<&str> = log.lines().filter(|line| !line.starts_with('{')).collect();
+    assert_eq!(
+        methods,
+        [
+            "initialize",
+            "initialized",
+            "textDocument/didOpen",
+            "textDocument/formatting",
+            "textDocument/didClose",
+            "textDocument/didOpen",
+            "textDocument/formatting",
+            "textDocument/didClose",
+            "shutdown",
+            "exit",
+        ]
+    );
+    // The server is sent the staged content, not the working tree's.
+    assert!(log.contains(r#""text": "b\na\n""#), "{}", log);
+}
+
+#[test]
+fn no_edits_leaves_the_file_alone() {
+    let repo = TempRepo::new("lsp-no-edits");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = run(&repo, &["a.txt"], r#""result": null"#);
+
+    assert!(output.status.success());
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[test]
+fn server_error_is_reported() {
+    let repo = TempRepo::new("lsp-error");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = run(
+        &repo,
+        &["a.txt"],
+        r#""error": {"code": -32603, "message": "syntax error"}"#,
+    );
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("couldn't handle `textDocument/formatting`: syntax error"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
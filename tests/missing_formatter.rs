@@ -0,0 +1,148 @@
+//! Tests for formatters that don't exist, which are caught with exit code 127 before any
+//! files are touched.
+
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+
+use common::{TempRepo, SORT};
+
+const MISSING: &str = "git-format-staged-no-such-formatter";
+
+/// A repository with `a.txt` staged, and a further change to it that isn't.
+fn with_unstaged(name: &str) -> TempRepo {
+    let repo = TempRepo::new(name);
+    repo.stage("a.txt", "b\na\n");
+    repo.write("a.txt", "b\na\nunstaged\n");
+    repo.age("a.txt");
+    repo
+}
+
+/// Check that nothing was written by a run that stopped at the missing formatter.
+fn assert_untouched(repo: &TempRepo, modified: std::time::SystemTime) {
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\nunstaged\n");
+    assert_eq!(repo.modified("a.txt"), modified);
+    assert_eq!(
+        repo.git(&["status", "--porcelain", "--untracked-files=all"]),
+        "AM a.txt\n"
+    );
+    let temp_files: Vec<_> = std::fs::read_dir(repo.join(".git"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().contains("format-staged"))
+        .collect();
+    assert!(temp_files.is_empty(), "{:?}", temp_files);
+    assert!(!repo.join(".git/ran").exists());
+}
+
+#[test]
+fn missing_formatter_exits_before_any_work() {
+    let repo = with_unstaged("missing");
+    let modified = repo.modified("a.txt");
+
+    let output = repo.format_staged(&["a.txt", "--", MISSING]);
+
+    assert_eq!(output.status.code(), Some(127));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("error: formatter not found: {}", MISSING)),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("directory is in PATH"), "{}", stderr);
+    assert_untouched(&repo, modified);
+}
+
+#[test]
+fn missing_formatter_is_found_in_every_mode() {
+    let repo = with_unstaged("missing-modes");
+    let modified = repo.modified("a.txt");
+
+    for before in [
+        &[][..],
+        &["--index-only"],
+        &["--no-working-tree"],
+        &["--stdin"],
+        &["--jobs", "2"],
+    ] {
+        let mut args = before.to_vec();
+        args.extend(["a.txt", "--", MISSING]);
+        let output = repo.format_staged(&args);
+        assert_eq!(output.status.code(), Some(127), "{:?}", before);
+        assert_untouched(&repo, modified);
+    }
+}
+
+#[test]
+fn missing_later_formatter_stops_the_earlier_ones() {
+    let repo = with_unstaged("missing-then");
+    let modified = repo.modified("a.txt");
+
+    let output = repo.format_staged(&[
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        "touch .git/ran",
+        "mark",
+        "--then",
+        MISSING,
+    ]);
+
+    assert_eq!(output.status.code(), Some(127));
+    assert_untouched(&repo, modified);
+}
+
+#[test]
+fn missing_type_formatter_exits_before_any_work() {
+    let repo = with_unstaged("missing-type");
+    let modified = repo.modified("a.txt");
+
+    let type_formatter = format!("rust={}", MISSING);
+    let mut args = vec!["--type", &type_formatter, "a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(127));
+    assert_untouched(&repo, modified);
+}
+
+#[test]
+fn missing_formatter_path_has_no_path_hint() {
+    let repo = with_unstaged("missing-path");
+    let modified = repo.modified("a.txt");
+
+    let output = repo.format_staged(&["a.txt", "--", "./bin/format"]);
+
+    assert_eq!(output.status.code(), Some(127));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("formatter not found: ./bin/format"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("PATH"), "{}", stderr);
+    assert_untouched(&repo, modified);
+}
+
+#[test]
+fn formatter_in_the_commands_own_path_is_found() {
+    let repo = TempRepo::new("missing-own-path");
+    repo.stage("a.txt", "b\na\n");
+    repo.write(".git/bin/format", "#!/bin/sh\nexec sort -o \"$1\" \"$1\"\n");
+    std::fs::set_permissions(
+        repo.join(".git/bin/format"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+
+    let path = format!(
+        "PATH={}:{}",
+        repo.join(".git/bin").display(),
+        std::env::var("PATH").unwrap()
+    );
+    repo.format_staged_ok(&["a.txt", "--", &path, "format"]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+}
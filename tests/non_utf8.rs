@@ -0,0 +1,141 @@
+//! Tests for paths that aren't UTF-8, which Unix allows.
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    ffi::{OsStr, OsString},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+};
+
+use common::{TempRepo, SORT};
+
+/// `café.txt` in Latin-1, which isn't UTF-8.
+fn latin1_name() -> OsString {
+    OsString::from_vec(b"caf\xe9.txt".to_vec())
+}
+
+/// The arguments to format `files` with [`SORT`], with `before` ahead of them.
+fn sort_args<'a>(before: &[&'a OsStr], files: &[&'a OsStr]) -> Vec<&'a OsStr> {
+    let mut args = before.to_vec();
+    args.extend(files);
+    args.push(OsStr::new("--"));
+    args.extend(SORT.iter().map(OsStr::new));
+    args
+}
+
+/// The staged content of `file`.
+fn staged(repo: &TempRepo, dir: &OsStr, file: &OsStr) -> Vec<u8> {
+    let mut blob = OsString::from(":");
+    blob.push(file);
+    repo.git_os(&[
+        OsStr::new("-C"),
+        dir,
+        OsStr::new("cat-file"),
+        OsStr::new("blob"),
+        &blob,
+    ])
+}
+
+#[test]
+fn non_utf8_file_is_formatted() {
+    let repo = TempRepo::new("non-utf8-file");
+    let name = latin1_name();
+    std::fs::write(repo.join(&name), "b\na\n").unwrap();
+    repo.git_os(&[OsStr::new("add"), OsStr::new("--"), &name]);
+
+    let output = repo.format_staged_os(&sort_args(&[], &[&name]));
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(staged(&repo, OsStr::new("."), &name), b"a\nb\n");
+    assert_eq!(std::fs::read(repo.join(&name)).unwrap(), b"a\nb\n");
+    assert_eq!(
+        repo.git(&["status", "--porcelain", "--untracked-files=all"]),
+        "A  \"caf\\351.txt\"\n"
+    );
+}
+
+#[test]
+fn non_utf8_file_keeps_unstaged_changes() {
+    let repo = TempRepo::new("non-utf8-unstaged");
+    let name = latin1_name();
+    std::fs::write(repo.join(&name), "b\na\n").unwrap();
+    repo.git_os(&[OsStr::new("add"), OsStr::new("--"), &name]);
+    std::fs::write(repo.join(&name), "b\na\nz\n").unwrap();
+
+    let output = repo.format_staged_os(&sort_args(&[], &[&name]));
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(staged(&repo, OsStr::new("."), &name), b"a\nb\n");
+    assert_eq!(std::fs::read(repo.join(&name)).unwrap(), b"a\nb\nz\n");
+}
+
+#[test]
+fn non_utf8_file_is_formatted_from_stdin() {
+    let repo = TempRepo::new("non-utf8-stdin");
+    let name = latin1_name();
+    std::fs::write(repo.join(&name), "b\na\n").unwrap();
+    repo.git_os(&[OsStr::new("add"), OsStr::new("--"), &name]);
+
+    let output = repo.format_staged_os(&[
+        OsStr::new("--stdin"),
+        &name,
+        OsStr::new("--"),
+        OsStr::new("sort"),
+    ]);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(staged(&repo, OsStr::new("."), &name), b"a\nb\n");
+}
+
+#[test]
+fn repository_in_non_utf8_directory() {
+    let repo = TempRepo::new("non-utf8-directory");
+    let dir = OsStr::from_bytes(b"r\xff");
+    std::fs::create_dir(repo.join(dir)).unwrap();
+    repo.git_os(&[
+        OsStr::new("-C"),
+        dir,
+        OsStr::new("init"),
+        OsStr::new("--quiet"),
+    ]);
+    std::fs::write(repo.join(dir).join("a.txt"), "b\na\n").unwrap();
+    std::fs::write(repo.join(dir).join("c.txt"), "d\nc\n").unwrap();
+    repo.git_os(&[OsStr::new("-C"), dir, OsStr::new("add"), OsStr::new(".")]);
+
+    let output =
+        repo.format_staged_os(&sort_args(&[OsStr::new("-C"), dir], &[OsStr::new("a.txt")]));
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(staged(&repo, dir, OsStr::new("a.txt")), b"a\nb\n");
+
+    let output = repo.format_staged_os(&sort_args(
+        &[OsStr::new("-C"), dir, OsStr::new("--index-only")],
+        &[OsStr::new("c.txt")],
+    ));
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(staged(&repo, dir, OsStr::new("c.txt")), b"c\nd\n");
+    assert_eq!(
+        std::fs::read(repo.join(dir).join("c.txt")).unwrap(),
+        b"d\nc\n"
+    );
+}
@@ -0,0 +1,105 @@
+//! Tests for `--only-changed-lines`, which passes the staged changes' line ranges to the
+//! formatter.
+
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+
+use common::TempRepo;
+
+/// A formatter that takes `--lines=START:END` for each range to format, and upper-cases
+/// those lines of the file it's given. It writes the file and ranges to `.git/runs`.
+const UPPER_LINES: &str = r#"#!/bin/sh
+ranges=
+for arg; do
+  case $arg in
+    --lines=*) ranges="$ranges ${arg#--lines=}" ;;
+    *) file=$arg ;;
+  esac
+done
+echo "$file$ranges" >> .git/runs
+awk -v ranges="$ranges" '
+  BEGIN { n = split(ranges, r, " "); for (i = 1; i <= n; i++) { split(r[i], b, ":"); s[i] = b[1]; e[i] = b[2] } }
+  { for (i = 1; i <= n; i++) if (NR >= s[i] && NR <= e[i]) $0 = toupper($0); print }
+' "$file" > "$file.tmp" && mv "$file.tmp" "$file"
+"#;
+
+/// A repository with a commit of `a.txt`, and the formatter in `.git/bin/format`.
+fn with_formatter(name: &str) -> TempRepo {
+    let repo = TempRepo::new(name);
+    repo.write(".git/bin/format", UPPER_LINES);
+    std::fs::set_permissions(
+        repo.join(".git/bin/format"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    repo.stage("a.txt", "a\nb\nc\nd\ne\nf\n");
+    repo.git(&["commit", "--quiet", "--message", "Initial"]);
+    repo
+}
+
+/// Format `files` with only their changed lines, and return what the formatter was run
+/// on.
+fn run(repo: &TempRepo, files: &[&str]) -> String {
+    let formatter = repo.join(".git/bin/format");
+    let mut args = vec!["--only-changed-lines"];
+    args.extend(files);
+    args.extend(["--", formatter.to_str().unwrap(), "--lines={start}:{end}"]);
+    repo.format_staged_ok(&args);
+    std::fs::read_to_string(repo.join(".git/runs")).unwrap_or_default()
+}
+
+#[test]
+fn only_staged_changes_are_formatted() {
+    let repo = with_formatter("changed-lines");
+    repo.stage("a.txt", "a\nb2\nc\nd\ne\nf2\ng\n");
+
+    assert_eq!(run(&repo, &["a.txt"]), "a.txt 2:2 6:7\n");
+    assert_eq!(repo.staged("a.txt"), "a\nB2\nc\nd\ne\nF2\nG\n");
+    assert_eq!(repo.read("a.txt"), "a\nB2\nc\nd\ne\nF2\nG\n");
+}
+
+#[test]
+fn unstaged_changes_are_not_formatted() {
+    let repo = with_formatter("changed-lines-unstaged");
+    repo.stage("a.txt", "a\nb\nc2\nd\ne\nf\n");
+    repo.write("a.txt", "a\nb\nc2\nd\ne\nf\nunstaged\n");
+
+    assert_eq!(run(&repo, &["a.txt"]), "a.txt 3:3\n");
+    assert_eq!(repo.staged("a.txt"), "a\nb\nC2\nd\ne\nf\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\nC2\nd\ne\nf\nunstaged\n");
+}
+
+#[test]
+fn new_file_is_all_changed() {
+    let repo = with_formatter("changed-lines-new");
+    repo.stage("new.txt", "p\nq\n");
+
+    assert_eq!(run(&repo, &["new.txt"]), "new.txt 1:2\n");
+    assert_eq!(repo.staged("new.txt"), "P\nQ\n");
+}
+
+#[test]
+fn file_with_only_removed_lines_is_not_formatted() {
+    let repo = with_formatter("changed-lines-removed");
+    repo.stage("a.txt", "a\nb\nd\ne\nf\n");
+
+    assert_eq!(run(&repo, &["a.txt"]), "");
+    assert_eq!(repo.staged("a.txt"), "a\nb\nd\ne\nf\n");
+}
+
+#[test]
+fn formatter_without_line_ranges_is_a_usage_error() {
+    let repo = with_formatter("changed-lines-no-range");
+    repo.stage("a.txt", "a\nb2\n");
+
+    let output = repo.format_staged(&["--only-changed-lines", "a.txt", "--", "true"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("--only-changed-lines needs `{start}` or `{end}`"),
+        "{}",
+        stderr
+    );
+}
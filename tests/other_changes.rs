@@ -0,0 +1,118 @@
+//! Tests for the warning about tracked files that a formatter changes without being given
+//! them, which aren't staged.
+
+mod common;
+
+use common::TempRepo;
+
+const WARNING: &str = "the formatter also changed";
+
+/// A repository with `a.txt` and `sibling.txt` committed, and a change to `a.txt` staged.
+fn with_sibling(name: &str) -> TempRepo {
+    let repo = TempRepo::new(name);
+    repo.stage("a.txt", "a\n");
+    repo.stage("sibling.txt", "sibling\n");
+    repo.stage("sub/nested.txt", "nested\n");
+    repo.git(&["commit", "--quiet", "--message", "Initial"]);
+    repo.stage("a.txt", "b\na\n");
+    repo
+}
+
+/// Sort the file given, and then run `also`.
+fn format_and(repo: &TempRepo, before: &[&str], also: &str) -> String {
+    let script = format!(r#"sort -o "$1" "$1"; {}"#, also);
+    let mut args = before.to_vec();
+    args.extend(["a.txt", "--", "sh", "-c", &script, "format"]);
+    let output = repo.format_staged(&args);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn change_to_sibling_is_warned_about() {
+    let repo = with_sibling("other-changes");
+
+    let stderr = format_and(&repo, &[], "echo more >> sibling.txt");
+
+    assert!(
+        stderr.contains(
+            "warning: the formatter also changed sibling.txt, which isn't being formatted, \
+             so the change wasn't staged"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("a.txt"), "{}", stderr);
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.staged("sibling.txt"), "sibling\n");
+    assert_eq!(repo.read("sibling.txt"), "sibling\nmore\n");
+}
+
+#[test]
+fn paths_are_relative_to_the_current_directory() {
+    let repo = with_sibling("other-changes-relative");
+    repo.git(&["mv", "a.txt", "sub/a.txt"]);
+
+    let stderr = format_and(&repo, &["-C", "sub"], "echo more >> ../sibling.txt");
+
+    assert!(
+        stderr.contains("also changed ../sibling.txt,"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.staged("sub/a.txt"), "a\nb\n");
+}
+
+#[test]
+fn earlier_changes_are_not_warned_about() {
+    let repo = with_sibling("other-changes-earlier");
+    repo.write("sibling.txt", "unstaged\n");
+
+    let stderr = format_and(&repo, &[], "true");
+
+    assert!(!stderr.contains(WARNING), "{}", stderr);
+    assert_eq!(repo.read("sibling.txt"), "unstaged\n");
+}
+
+#[test]
+fn further_changes_are_warned_about() {
+    let repo = with_sibling("other-changes-further");
+    repo.write("sibling.txt", "unstaged\n");
+    repo.age("sibling.txt");
+
+    let stderr = format_and(&repo, &[], "echo more >> sibling.txt");
+
+    assert!(stderr.contains("also changed sibling.txt,"), "{}", stderr);
+}
+
+#[test]
+fn untracked_files_are_not_warned_about() {
+    let repo = with_sibling("other-changes-untracked");
+
+    let stderr = format_and(&repo, &[], "echo new > new.txt");
+
+    assert!(!stderr.contains(WARNING), "{}", stderr);
+}
+
+#[test]
+fn every_changed_file_is_listed() {
+    let repo = with_sibling("other-changes-several");
+
+    let stderr = format_and(
+        &repo,
+        &[],
+        "echo more >> sub/nested.txt; echo more >> sibling.txt",
+    );
+
+    let warned: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.contains(WARNING))
+        .collect();
+    assert_eq!(warned.len(), 2, "{}", stderr);
+    assert!(warned[0].contains("sibling.txt"), "{}", stderr);
+    assert!(warned[1].contains("sub/nested.txt"), "{}", stderr);
+}
@@ -0,0 +1,46 @@
+//! Tests for file paths that Git has to quote.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn path_with_newline_is_formatted_and_restaged() {
+    let repo = TempRepo::new("paths-newline");
+    repo.stage("a\nb.txt", "b\na\n");
+
+    let mut args = vec!["a\nb.txt", "--"];
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged("a\nb.txt"), "a\nb\n");
+    assert_eq!(repo.read("a\nb.txt"), "a\nb\n");
+}
+
+#[test]
+fn path_with_newline_is_quoted_in_errors() {
+    let repo = TempRepo::new("paths-newline-error");
+    repo.write("a\nb.txt", "b\na\n");
+
+    let mut args = vec!["a\nb.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(r#""a\nb.txt""#), "{}", stderr);
+}
+
+#[test]
+fn non_ascii_path_is_quoted_in_errors() {
+    let repo = TempRepo::new("paths-non-ascii-error");
+    repo.write("café.txt", "b\na\n");
+
+    let mut args = vec!["café.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(r#""caf\303\251.txt""#), "{}", stderr);
+}
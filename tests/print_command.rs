@@ -0,0 +1,80 @@
+//! Tests for `--print-command`.
+
+mod common;
+
+use std::process::Command;
+
+use common::TempRepo;
+
+/// A formatter that writes each file it's given to `.git/args`, one per line.
+const RECORD: &[&str] = &["sh", "-c", r#"printf '%s\n' "$@" > .git/args"#, "record"];
+
+/// The words of `line`, as a shell splits them.
+fn shell_words(line: &str) -> Vec<String> {
+    let output = Command::new("sh")
+        .args(["-c", &format!("set -- {}; printf '%s\\n' \"$@\"", line)])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn printed_command_is_the_one_run() {
+    let repo = TempRepo::new("print-command");
+    repo.stage("a b.txt", "a\n");
+    repo.stage("c.txt", "c\n");
+
+    let mut args = vec!["--print-command", "a b.txt", "c.txt", "--"];
+    args.extend(RECORD);
+    let output = repo.format_staged(&args);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let printed: Vec<&str> = stderr.lines().collect();
+    assert_eq!(printed.len(), 1, "{}", stderr);
+    let words = shell_words(printed[0]);
+    let mut expected: Vec<&str> = RECORD.to_vec();
+    expected.extend(["a b.txt", "c.txt"]);
+    assert_eq!(words, expected);
+    assert_eq!(repo.read(".git/args"), "a b.txt\nc.txt\n");
+}
+
+#[test]
+fn printed_command_has_the_path_substituted() {
+    let repo = TempRepo::new("print-command-stdin");
+    repo.stage("a.txt", "a\n");
+
+    let output = repo.format_staged(&[
+        "--print-command",
+        "--stdin",
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        "cat",
+        "filter",
+        "--path={}",
+    ]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "sh -c cat filter --path=a.txt\n");
+}
+
+#[test]
+fn commands_are_not_printed_by_default() {
+    let repo = TempRepo::new("print-command-off");
+    repo.stage("a.txt", "a\n");
+
+    let mut args = vec!["a.txt", "--"];
+    args.extend(RECORD);
+    let output = repo.format_staged(&args);
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
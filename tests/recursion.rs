@@ -0,0 +1,66 @@
+//! Tests for the guard against a formatter that runs git-format-staged itself.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+const RUNNING_VAR: &str = "GIT_FORMAT_STAGED_RUNNING";
+
+#[test]
+fn formatter_that_runs_git_format_staged_is_stopped() {
+    let repo = TempRepo::new("recursion");
+    repo.stage("a.txt", "b\na\n");
+
+    let bin = env!("CARGO_BIN_EXE_git-format-staged");
+    let output = repo.format_staged(&[
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        r#"exec "$0" "$@" -- sort -o"#,
+        bin,
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.matches("recursive invocation detected").count(),
+        1,
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(RUNNING_VAR), "{}", stderr);
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[test]
+fn set_running_variable_refuses_to_run() {
+    let repo = TempRepo::new("recursion-variable");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged_env(&[(RUNNING_VAR, "1")], &args);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("recursive invocation detected"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[test]
+fn formatter_sees_the_running_variable() {
+    let repo = TempRepo::new("recursion-formatter-env");
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&[
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        &format!(r#"echo "${}" > .git/running"#, RUNNING_VAR),
+        "record",
+    ]);
+
+    assert_eq!(repo.read(".git/running"), "1\n");
+}
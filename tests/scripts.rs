@@ -0,0 +1,167 @@
+//! Tests for formatters given as the path of a script.
+
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+
+use common::TempRepo;
+
+/// A script that sorts the lines of each file it's given, run by `interpreter`.
+fn sort_script(interpreter: &str) -> String {
+    format!("#!{}\nfor f; do sort -o \"$f\" \"$f\"; done\n", interpreter)
+}
+
+fn set_mode(repo: &TempRepo, file: &str, mode: u32) {
+    std::fs::set_permissions(repo.join(file), std::fs::Permissions::from_mode(mode)).unwrap();
+}
+
+fn mode(repo: &TempRepo, file: &str) -> u32 {
+    std::fs::metadata(repo.join(file))
+        .unwrap()
+        .permissions()
+        .mode()
+        & 0o777
+}
+
+#[test]
+fn script_that_is_not_executable_is_an_error() {
+    let repo = TempRepo::new("script-not-executable");
+    repo.write("format.sh", &sort_script("/bin/sh"));
+    set_mode(&repo, "format.sh", 0o644);
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "./format.sh"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("./format.sh is not executable"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("--use-shebang"), "{}", stderr);
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[test]
+fn use_shebang_runs_the_script_through_its_interpreter() {
+    let repo = TempRepo::new("script-use-shebang");
+    repo.write("format.sh", &sort_script("/bin/sh"));
+    set_mode(&repo, "format.sh", 0o644);
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&["--use-shebang", "a.txt", "--", "./format.sh"]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    // The script is left as it was.
+    assert_eq!(mode(&repo, "format.sh"), 0o644);
+}
+
+#[test]
+fn use_shebang_passes_the_interpreter_argument() {
+    let repo = TempRepo::new("script-shebang-argument");
+    // With `-e`, the failing `false` stops the script before it sorts anything.
+    repo.write(
+        "format.sh",
+        "#!/bin/sh -e\nfalse\nfor f; do sort -o \"$f\" \"$f\"; done\n",
+    );
+    set_mode(&repo, "format.sh", 0o644);
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["--use-shebang", "a.txt", "--", "./format.sh"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[test]
+fn use_shebang_needs_a_shebang_line() {
+    let repo = TempRepo::new("script-no-shebang");
+    repo.write("format.sh", "for f; do sort -o \"$f\" \"$f\"; done\n");
+    set_mode(&repo, "format.sh", 0o644);
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["--use-shebang", "a.txt", "--", "./format.sh"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("doesn't start with a #! line"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn executable_script_is_run_directly() {
+    let repo = TempRepo::new("script-executable");
+    repo.write("format.sh", &sort_script("/bin/sh"));
+    set_mode(&repo, "format.sh", 0o755);
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&["a.txt", "--", "./format.sh"]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn relative_script_is_found_from_the_repository_root() {
+    let repo = TempRepo::new("script-repo-relative");
+    repo.write("tools/format.sh", &sort_script("/bin/sh"));
+    set_mode(&repo, "tools/format.sh", 0o755);
+    repo.stage("src/a.txt", "b\na\n");
+
+    // From `src`, `tools/format.sh` only exists relative to the repository root.
+    repo.format_staged_ok(&["-C", "src", "a.txt", "--", "tools/format.sh"]);
+
+    assert_eq!(repo.staged("src/a.txt"), "a\nb\n");
+}
+
+#[test]
+fn relative_script_in_the_current_directory_comes_first() {
+    let repo = TempRepo::new("script-cwd-relative");
+    repo.write("tools/format.sh", "#!/bin/sh\nexit 1\n");
+    set_mode(&repo, "tools/format.sh", 0o755);
+    repo.write("src/tools/format.sh", &sort_script("/bin/sh"));
+    set_mode(&repo, "src/tools/format.sh", 0o755);
+    repo.stage("src/a.txt", "b\na\n");
+
+    repo.format_staged_ok(&["-C", "src", "a.txt", "--", "tools/format.sh"]);
+
+    assert_eq!(repo.staged("src/a.txt"), "a\nb\n");
+}
+
+#[test]
+fn relative_script_that_is_nowhere_is_not_found() {
+    let repo = TempRepo::new("script-not-found");
+    repo.stage("src/a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["-C", "src", "a.txt", "--", "tools/format.sh"]);
+
+    assert_eq!(output.status.code(), Some(127));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("formatter not found: tools/format.sh"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn relative_script_from_the_root_can_use_its_shebang() {
+    let repo = TempRepo::new("script-repo-relative-shebang");
+    repo.write("tools/format.sh", &sort_script("/bin/sh"));
+    set_mode(&repo, "tools/format.sh", 0o644);
+    repo.stage("src/a.txt", "b\na\n");
+
+    repo.format_staged_ok(&[
+        "-C",
+        "src",
+        "--use-shebang",
+        "a.txt",
+        "--",
+        "tools/format.sh",
+    ]);
+
+    assert_eq!(repo.staged("src/a.txt"), "a\nb\n");
+}
@@ -0,0 +1,105 @@
+//! Tests for `--stat`, which prints how many lines formatting changed in each file.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+fn sort_args<'a>(before: &[&'a str], files: &[&'a str]) -> Vec<&'a str> {
+    let mut args = before.to_vec();
+    args.extend(files);
+    args.push("--");
+    args.extend(SORT);
+    args
+}
+
+#[test]
+fn stat_counts_insertions_and_deletions() {
+    let repo = TempRepo::new("stat");
+    repo.stage("a.txt", "c\nb\na\n");
+    repo.stage("b.txt", "a\nb\n");
+    repo.stage("c.md", "x\n");
+
+    let stdout = repo.format_staged_ok(&[
+        "--stat",
+        "a.txt",
+        "b.txt",
+        "c.md",
+        "--",
+        "sh",
+        "-c",
+        r#"for f; do case $f in *.md) echo y >> "$f";; *) sort -o "$f" "$f";; esac; done"#,
+        "format",
+    ]);
+
+    assert_eq!(
+        stdout,
+        " a.txt | 4 ++--\n \
+         b.txt | 0\n \
+         c.md  | 1 +\n \
+         2 files changed, 3 insertions(+), 2 deletions(-)\n"
+    );
+}
+
+#[test]
+fn unchanged_files_show_zero() {
+    let repo = TempRepo::new("stat-unchanged");
+    repo.stage("a.txt", "a\n");
+
+    let stdout = repo.format_staged_ok(&sort_args(&["--stat"], &["a.txt"]));
+
+    assert_eq!(
+        stdout,
+        " a.txt | 0\n 0 files changed, 0 insertions(+), 0 deletions(-)\n"
+    );
+}
+
+#[test]
+fn large_changes_are_scaled_down() {
+    let repo = TempRepo::new("stat-scaled");
+    let descending: String = (1..=100).rev().map(|n| format!("{}\n", n)).collect();
+    repo.stage("big.txt", &descending);
+    repo.stage("small.txt", "b\na\n");
+
+    let stdout = repo.format_staged_ok(&[
+        "--stat",
+        "big.txt",
+        "small.txt",
+        "--",
+        "sh",
+        "-c",
+        r#"for f; do sort -n -o "$f" "$f"; done"#,
+        "sort",
+    ]);
+
+    assert_eq!(
+        stdout,
+        format!(
+            " big.txt   | 198 {}{}\n small.txt |   2 +-\n \
+             2 files changed, 100 insertions(+), 100 deletions(-)\n",
+            "+".repeat(20),
+            "-".repeat(20)
+        )
+    );
+}
+
+#[test]
+fn paths_are_relative_to_the_current_directory() {
+    let repo = TempRepo::new("stat-relative");
+    repo.stage("sub/a.txt", "b\na\n");
+    repo.stage("sub/b.txt", "a\n");
+
+    let stdout = repo.format_staged_ok(&sort_args(&["-C", "sub", "--stat"], &["a.txt"]));
+    assert_eq!(
+        stdout,
+        " a.txt | 2 +-\n 1 file changed, 1 insertion(+), 1 deletion(-)\n"
+    );
+
+    let stdout = repo.format_staged_ok(&sort_args(
+        &["-C", "sub", "--stat", "--relative-to", "repo"],
+        &["b.txt"],
+    ));
+    assert_eq!(
+        stdout,
+        " sub/b.txt | 0\n 0 files changed, 0 insertions(+), 0 deletions(-)\n"
+    );
+}
@@ -0,0 +1,232 @@
+//! Tests for the formatters that read the staged content from stdin: filters, with
+//! `--stdin`, and formatters that write the result to a file, with `--formatter-stdin`.
+
+mod common;
+
+use common::TempRepo;
+
+fn run(repo: &TempRepo, options: &[&str], command: &[&str]) -> std::process::Output {
+    let mut args = options.to_vec();
+    args.extend(["a.txt", "--"]);
+    args.extend(command);
+    let output = repo.format_staged(&args);
+    assert!(
+        output.status.success(),
+        "git-format-staged failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn filter() {
+    let repo = TempRepo::new("stdin-filter");
+    repo.stage("a.txt", "b\na\n");
+
+    run(&repo, &["--stdin"], &["sort"]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn formatter_that_writes_a_file() {
+    let repo = TempRepo::new("formatter-stdin");
+    repo.stage("a.txt", "b\na\n");
+
+    run(
+        &repo,
+        &["--formatter-stdin"],
+        &["sh", "-c", r#"sort > "$1""#, "sort", "{}"],
+    );
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn filter_without_output_leaves_the_file_alone() {
+    let repo = TempRepo::new("stdin-no-output");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = run(&repo, &["--stdin"], &["sh", "-c", "cat > /dev/null"]);
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("wrote nothing to stdout"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[test]
+fn formatter_that_empties_the_file_leaves_it_alone() {
+    let repo = TempRepo::new("formatter-stdin-no-output");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = run(
+        &repo,
+        &["--formatter-stdin"],
+        &["sh", "-c", r#"cat > /dev/null; : > "$1""#, "empty", "{}"],
+    );
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("left a.txt empty"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn formatter_that_writes_to_the_output_fifo() {
+    let repo = TempRepo::new("output-fifo");
+    repo.stage("a.txt", "b\na\n");
+
+    run(
+        &repo,
+        &["--stdin", "--output-fifo"],
+        &[
+            "sh",
+            "-c",
+            r#"echo ignored; sort > "$1""#,
+            "sort",
+            "{output}",
+        ],
+    );
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn nothing_written_to_the_output_fifo_leaves_the_file_alone() {
+    let repo = TempRepo::new("output-fifo-no-output");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = run(
+        &repo,
+        &["--stdin", "--output-fifo"],
+        &[
+            "sh",
+            "-c",
+            r#"cat > /dev/null; : > "$1""#,
+            "empty",
+            "{output}",
+        ],
+    );
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("wrote nothing to {output}"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn formatter_that_never_opens_the_output_fifo() {
+    let repo = TempRepo::new("output-fifo-never-opened");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = run(
+        &repo,
+        &["--stdin", "--output-fifo"],
+        &["sh", "-c", "cat > /dev/null", "ignore", "{output}"],
+    );
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("wrote nothing to {output}"));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn output_fifo_left_open_times_out() {
+    let repo = TempRepo::new("output-fifo-left-open");
+    repo.stage("a.txt", "b\na\n");
+
+    // The formatter exits, but leaves the pipe open in a process that's still running.
+    let output = repo.format_staged(&[
+        "--stdin",
+        "--output-fifo",
+        "--deadline",
+        "0.5",
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        r#"exec 3> "$1"; sort >&3; sleep 2 &"#,
+        "sort",
+        "{output}",
+    ]);
+
+    assert_eq!(output.status.code(), Some(124));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+/// A `prettier`-style filter that needs `--stdin-filepath PATH`, which it records in
+/// `.git/paths` before sorting its stdin. It fails for paths that aren't text files.
+const NEEDS_FILEPATH: &[&str] = &[
+    "sh",
+    "-c",
+    r#"[ "$1" = --stdin-filepath ] || exit 2
+case "$2" in *.txt) ;; *) exit 3 ;; esac
+echo "$2" >> "$(git rev-parse --git-dir)/paths"
+sort"#,
+    "prettier",
+    "--stdin-filepath",
+    "{}",
+];
+
+#[test]
+fn filter_is_given_the_file_path() {
+    let repo = TempRepo::new("stdin-filepath");
+    repo.stage("a.txt", "b\na\n");
+
+    run(&repo, &["--stdin"], NEEDS_FILEPATH);
+
+    assert_eq!(repo.read(".git/paths"), "a.txt\n");
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn file_path_can_be_part_of_an_argument() {
+    let repo = TempRepo::new("stdin-filepath-in-argument");
+    repo.stage("a.txt", "b\na\n");
+
+    run(
+        &repo,
+        &["--stdin"],
+        &[
+            "sh",
+            "-c",
+            r#"echo "$1" > .git/paths; sort"#,
+            "sort",
+            "--path={}",
+        ],
+    );
+
+    assert_eq!(repo.read(".git/paths"), "--path=a.txt\n");
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn file_path_forms() {
+    let repo = TempRepo::new("stdin-filepath-forms");
+    repo.stage("src/a.txt", "b\na\n");
+
+    for form in ["cwd", "repo", "absolute"] {
+        let mut args = vec![
+            "-C",
+            "src",
+            "--stdin",
+            "--stdin-filepath",
+            form,
+            "a.txt",
+            "--",
+        ];
+        args.extend(NEEDS_FILEPATH);
+        repo.format_staged_ok(&args);
+    }
+
+    let absolute = repo.path.canonicalize().unwrap().join("src/a.txt");
+    assert_eq!(
+        repo.read(".git/paths"),
+        format!("a.txt\nsrc/a.txt\n{}\n", absolute.display())
+    );
+    assert_eq!(repo.staged("src/a.txt"), "a\nb\n");
+}
@@ -0,0 +1,84 @@
+//! Tests for `--recurse-submodules`, which formats staged files inside submodules too.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+/// A superproject with `o.txt` staged, and a submodule `sub` with `s.txt` staged, both
+/// unsorted.
+fn superproject(name: &str) -> (TempRepo, TempRepo) {
+    let inner = TempRepo::new(&format!("{}-inner", name));
+    inner.stage("s.txt", "a\n");
+    inner.git(&["commit", "--quiet", "-m", "s"]);
+
+    let outer = TempRepo::new(name);
+    outer.git(&[
+        "-c",
+        "protocol.file.allow=always",
+        "submodule",
+        "--quiet",
+        "add",
+        inner.path.to_str().unwrap(),
+        "sub",
+    ]);
+    outer.git(&["commit", "--quiet", "-m", "sub"]);
+
+    outer.stage("o.txt", "b\na\n");
+    outer.write("sub/s.txt", "d\nc\n");
+    outer.git(&["-C", "sub", "add", "s.txt"]);
+    (outer, inner)
+}
+
+fn run(repo: &TempRepo, options: &[&str]) -> std::process::Output {
+    let mut args = vec!["--recurse-submodules"];
+    args.extend(options);
+    args.extend(["o.txt", "sub/s.txt", "--"]);
+    args.extend(SORT);
+    repo.format_staged(&args)
+}
+
+#[test]
+fn formats_the_submodule_and_the_superproject() {
+    let (repo, _inner) = superproject("submodules");
+
+    let output = run(&repo, &[]);
+
+    assert!(output.status.success());
+    assert_eq!(repo.staged("o.txt"), "a\nb\n");
+    assert_eq!(repo.git(&["-C", "sub", "show", ":s.txt"]), "c\nd\n");
+}
+
+#[test]
+fn one_json_summary_with_superproject_paths() {
+    let (repo, _inner) = superproject("submodules-json");
+
+    let output = run(&repo, &["--format", "json"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.matches("\"files\"").count(), 1, "{}", stdout);
+    assert!(
+        stdout.contains(r#"{"path": "sub/s.txt", "reformatted": true}"#),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(r#"{"path": "o.txt", "reformatted": true}"#),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn fail_on_reformat_formats_everything_first() {
+    let (repo, _inner) = superproject("submodules-fail-on-reformat");
+
+    let output = run(&repo, &["--fail-on-reformat"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("  sub/s.txt\n"), "{}", stderr);
+    assert!(stderr.contains("  o.txt\n"), "{}", stderr);
+    assert_eq!(repo.staged("o.txt"), "a\nb\n");
+    assert_eq!(repo.git(&["-C", "sub", "show", ":s.txt"]), "c\nd\n");
+}
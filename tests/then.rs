@@ -0,0 +1,68 @@
+//! Tests for `--then`, which chains several formatters.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+#[test]
+fn formatters_run_in_order_on_each_file() {
+    let repo = TempRepo::new("then-in-place");
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "d\nc\n");
+
+    let mut args = vec!["a.txt", "b.txt", "--"];
+    args.extend(SORT);
+    // Appending after sorting leaves `z` last.
+    args.extend([
+        "--then",
+        "sh",
+        "-c",
+        r#"for f; do echo z >> "$f"; done"#,
+        "append",
+    ]);
+    repo.format_staged_ok(&args);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\nz\n");
+    assert_eq!(repo.staged("b.txt"), "c\nd\nz\n");
+    assert_eq!(repo.read("a.txt"), "a\nb\nz\n");
+}
+
+#[test]
+fn filters_feed_each_other() {
+    let repo = TempRepo::new("then-stdin");
+    repo.stage("a.txt", "b\na\n");
+
+    repo.format_staged_ok(&["--stdin", "a.txt", "--", "sort", "--then", "sed", "s/a/A/"]);
+
+    assert_eq!(repo.staged("a.txt"), "A\nb\n");
+}
+
+#[test]
+fn failed_formatter_stops_the_chain() {
+    let repo = TempRepo::new("then-failure");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["a.txt", "--", "sh", "-c", "exit 3", "fail", "--then"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+    // No temporary files are left behind.
+    assert_eq!(
+        repo.git(&["status", "--porcelain", "--untracked-files=all"]),
+        "A  a.txt\n"
+    );
+}
+
+#[test]
+fn empty_formatter_around_then_is_an_error() {
+    let repo = TempRepo::new("then-empty");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&["a.txt", "--", "sort", "--then"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("around --then"));
+}
@@ -0,0 +1,64 @@
+//! Tests for `--verify-clean`, which formats the newly staged files again to check that
+//! the formatter is stable.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+/// A formatter that never stops changing its files.
+const APPEND: &[&str] = &["sh", "-c", r#"for f; do echo x >> "$f"; done"#, "append"];
+
+#[test]
+fn unstable_formatter_is_warned_about() {
+    let repo = TempRepo::new("verify-clean-unstable");
+    repo.stage("a.txt", "a\n");
+    repo.stage("b.txt", "b\n");
+
+    let mut args = vec!["--verify-clean", "a.txt", "b.txt", "--"];
+    args.extend(APPEND);
+    let output = repo.format_staged(&args);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for file in ["a.txt", "b.txt"] {
+        assert!(
+            stderr.contains(&format!(
+                "warning: formatting {} again changes it; the formatter may be unstable",
+                file
+            )),
+            "{}",
+            stderr
+        );
+    }
+    // Only the first pass is staged.
+    assert_eq!(repo.staged("a.txt"), "a\nx\n");
+    assert_eq!(repo.read("a.txt"), "a\nx\n");
+}
+
+#[test]
+fn stable_formatter_is_not_warned_about() {
+    let repo = TempRepo::new("verify-clean-stable");
+    repo.stage("a.txt", "b\na\n");
+
+    let mut args = vec!["--verify-clean", "a.txt", "--"];
+    args.extend(SORT);
+    let output = repo.format_staged(&args);
+
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("unstable"));
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert!(!repo.join(".git/format-staged-verify").exists());
+}
+
+#[test]
+fn unstable_formatter_is_not_checked_without_the_flag() {
+    let repo = TempRepo::new("verify-clean-off");
+    repo.stage("a.txt", "a\n");
+
+    let mut args = vec!["a.txt", "--"];
+    args.extend(APPEND);
+    let output = repo.format_staged(&args);
+
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("unstable"));
+}
@@ -0,0 +1,116 @@
+//! Tests for `--verify-staged-unchanged`, which checks that the staged files are what
+//! was formatted. A failing check is a bug, so it's tested with injected mismatches in
+//! the unit tests instead.
+
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+
+use common::{TempRepo, SORT};
+
+fn verify_args<'a>(before: &[&'a str], files: &[&'a str]) -> Vec<&'a str> {
+    let mut args = vec!["--verify-staged-unchanged"];
+    args.extend(before);
+    args.extend(files);
+    args.push("--");
+    args.extend(SORT);
+    args
+}
+
+#[test]
+fn normal_run_passes() {
+    let repo = TempRepo::new("verify-staged");
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "a\nb\n");
+    repo.write("script.sh", "b\na\n");
+    std::fs::set_permissions(
+        repo.join("script.sh"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    repo.git(&["add", "script.sh"]);
+
+    let output = repo.format_staged(&verify_args(&[], &["a.txt", "b.txt", "script.sh"]));
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stderr, b"");
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.staged("script.sh"), "a\nb\n");
+    assert!(repo
+        .git(&["ls-files", "--stage", "script.sh"])
+        .starts_with("100755 "));
+}
+
+#[test]
+fn passes_with_other_modes() {
+    let repo = TempRepo::new("verify-staged-modes");
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "d\r\nc\r\n");
+
+    for before in [
+        &["--index-only"][..],
+        &["--no-working-tree"],
+        &["--keep-line-endings"],
+        &["--encoding", "utf-8"],
+    ] {
+        repo.stage("a.txt", "b\na\n");
+        let output = repo.format_staged(&verify_args(before, &["a.txt", "b.txt"]));
+        assert!(
+            output.status.success(),
+            "{:?}: {}",
+            before,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    }
+
+    repo.stage("a.txt", "b\na\n");
+    let output = repo.format_staged(&[
+        "--verify-staged-unchanged",
+        "--stdin",
+        "a.txt",
+        "--",
+        "sort",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+}
+
+#[test]
+fn passes_with_out_index() {
+    let repo = TempRepo::new("verify-staged-out-index");
+    repo.stage("a.txt", "b\na\n");
+    let out_index = repo.join(".git/out-index");
+
+    let output = repo.format_staged(&verify_args(
+        &["--out-index", out_index.to_str().unwrap()],
+        &["a.txt"],
+    ));
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.staged_in(&out_index, "a.txt"), "a\nb\n");
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
+
+#[test]
+fn conflicts_with_no_index_write() {
+    let repo = TempRepo::new("verify-staged-no-write");
+    repo.stage("a.txt", "b\na\n");
+
+    let output = repo.format_staged(&verify_args(&["--no-index-write"], &["a.txt"]));
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(repo.staged("a.txt"), "b\na\n");
+}
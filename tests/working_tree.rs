@@ -0,0 +1,98 @@
+//! Tests for what happens to the working tree files while their staged versions are
+//! formatted.
+
+mod common;
+
+use common::{TempRepo, SORT};
+
+fn run(repo: &TempRepo, options: &[&str], files: &[&str]) {
+    let mut args = options.to_vec();
+    args.extend(files);
+    args.push("--");
+    args.extend(SORT);
+    repo.format_staged_ok(&args);
+}
+
+#[test]
+fn no_working_tree_leaves_the_file_alone() {
+    let repo = TempRepo::new("no-working-tree");
+    repo.stage("a.txt", "b\na\n");
+    repo.age("a.txt");
+    let modified = repo.modified("a.txt");
+
+    run(&repo, &["--no-working-tree"], &["a.txt"]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "b\na\n");
+    assert_eq!(repo.modified("a.txt"), modified);
+}
+
+#[test]
+fn no_working_tree_leaves_unstaged_changes_alone() {
+    let repo = TempRepo::new("no-working-tree-unstaged");
+    repo.stage("a.txt", "b\na\n");
+    repo.write("a.txt", "b\na\nc\n");
+    repo.age("a.txt");
+    let modified = repo.modified("a.txt");
+
+    run(&repo, &["--no-working-tree"], &["a.txt"]);
+
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "b\na\nc\n");
+    assert_eq!(repo.modified("a.txt"), modified);
+}
+
+#[test]
+fn no_working_tree_formats_a_copy() {
+    let repo = TempRepo::new("no-working-tree-copy");
+    repo.stage("a.txt", "b\na\n");
+    repo.write("a.txt", "b\na\nc\n");
+
+    // The formatter sees the staged version, and the working tree file is never
+    // replaced by it, even while the formatter runs.
+    let output = repo.format_staged(&[
+        "--no-working-tree",
+        "a.txt",
+        "--",
+        "sh",
+        "-c",
+        r#"cat a.txt > .git/during && sort -o "$1" "$1" && cp "$1" .git/formatted"#,
+        "sort",
+    ]);
+
+    assert!(output.status.success());
+    assert_eq!(repo.read(".git/during"), "b\na\nc\n");
+    assert_eq!(repo.read(".git/formatted"), "a\nb\n");
+    assert_eq!(repo.staged("a.txt"), "a\nb\n");
+    assert_eq!(repo.read("a.txt"), "b\na\nc\n");
+    assert!(!repo.join(".git/format-staged").exists());
+}
+
+#[test]
+fn unchanged_file_keeps_its_mtime() {
+    let repo = TempRepo::new("unchanged-mtime");
+    repo.stage("a.txt", "a\nb\n");
+    repo.age("a.txt");
+    let modified = repo.modified("a.txt");
+
+    run(&repo, &[], &["a.txt"]);
+
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+    assert_eq!(repo.modified("a.txt"), modified);
+}
+
+#[test]
+fn reformatted_file_gets_a_new_mtime() {
+    let repo = TempRepo::new("reformatted-mtime");
+    repo.stage("a.txt", "b\na\n");
+    repo.stage("b.txt", "a\nb\n");
+    repo.age("a.txt");
+    repo.age("b.txt");
+    let (a_modified, b_modified) = (repo.modified("a.txt"), repo.modified("b.txt"));
+
+    run(&repo, &[], &["a.txt", "b.txt"]);
+
+    assert_eq!(repo.read("a.txt"), "a\nb\n");
+    assert_ne!(repo.modified("a.txt"), a_modified);
+    assert_eq!(repo.modified("b.txt"), b_modified);
+}